@@ -0,0 +1,109 @@
+//! Generate filtergraph descriptions from known filters, labels and
+//! separators mixed with arbitrary text, and check that parsing, printing,
+//! optimizing and serializing them never panic.
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use ffgen::{
+    graph_diff::diff_graphs,
+    graph_optimizer::{optimize, OptimizePass},
+    graph_parser::{graph_serialization, parse_graph},
+    graph_printer::{print_graph, PrintStyle},
+};
+
+#[derive(Arbitrary, Debug)]
+enum Filter {
+    Null,
+    Nullsrc,
+    Nullsink,
+    Scale,
+    Format,
+    Overlay,
+    Split(u8),
+    Hstack(u8),
+    Concat,
+    Unknown(String),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Token {
+    /// Only a few labels so that they are often linked.
+    Label(u8),
+    Filter {
+        filter: Filter,
+        instance: Option<String>,
+        args: Option<String>,
+    },
+    Chain,
+    Separator,
+    Whitespace,
+    SwsFlags(String),
+    Raw(String),
+}
+
+fn description(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens.iter() {
+        match token {
+            Token::Label(x) => text.push_str(&format!("[l{}]", x % 4)),
+            Token::Filter {
+                filter,
+                instance,
+                args,
+            } => {
+                let (name, default_args) = match filter {
+                    Filter::Null => ("null", None),
+                    Filter::Nullsrc => ("nullsrc", None),
+                    Filter::Nullsink => ("nullsink", None),
+                    Filter::Scale => ("scale", Some("320:240".to_owned())),
+                    Filter::Format => ("format", Some("yuv420p|rgb24".to_owned())),
+                    Filter::Overlay => ("overlay", None),
+                    Filter::Split(x) => ("split", Some((x % 5).to_string())),
+                    Filter::Hstack(x) => ("hstack", Some(format!("inputs={}", x % 5))),
+                    Filter::Concat => ("concat", None),
+                    Filter::Unknown(name) => (name.as_str(), None),
+                };
+                text.push_str(name);
+                if let Some(instance) = instance {
+                    text.push('@');
+                    text.push_str(instance);
+                }
+                if let Some(args) = args.as_ref().or_else(|| default_args.as_ref()) {
+                    text.push('=');
+                    text.push_str(args);
+                }
+            }
+            Token::Chain => text.push(','),
+            Token::Separator => text.push(';'),
+            Token::Whitespace => text.push(' '),
+            Token::SwsFlags(flags) => {
+                text.push_str("sws_flags=");
+                text.push_str(flags);
+            }
+            Token::Raw(raw) => text.push_str(raw),
+        }
+    }
+    text
+}
+
+fuzz_target!(|tokens: Vec<Token>| {
+    let text = description(&tokens);
+    let graph = match parse_graph(&text) {
+        Ok(graph) => graph,
+        Err(_) => return,
+    };
+    graph_serialization(&graph);
+    assert!(diff_graphs(&graph, &graph).is_empty());
+
+    for &style in [PrintStyle::Compact, PrintStyle::Pretty].iter() {
+        let printed = print_graph(&graph, style);
+        assert_eq!(parse_graph(&printed).as_ref(), Ok(&graph), "{}", printed);
+    }
+
+    let mut optimized = parse_graph(&text).unwrap();
+    optimize(&mut optimized, OptimizePass::ALL);
+    graph_serialization(&optimized);
+    let printed = print_graph(&optimized, PrintStyle::Compact);
+    assert!(parse_graph(&printed).is_ok(), "{}", printed);
+});
@@ -0,0 +1,48 @@
+//! Generate commandlines from known options, group separators and arguments
+//! mixed with arbitrary text, and check that splitting them never panics.
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+use ffgen::{
+    cmdutils::{init_parse_context, split_commandline, uninit_parse_context},
+    options::{GROUPS, OPTIONS},
+};
+
+#[derive(Arbitrary, Debug)]
+enum Arg {
+    /// One of the options in `OPTIONS`.
+    Option(u8),
+    /// The `-no` prefixed form of one of the options in `OPTIONS`.
+    NoOption(u8),
+    /// One of the options in `OPTIONS` with a stream specifier.
+    Specified(u8, String),
+    Input,
+    DashDash,
+    Dash,
+    /// Not always UTF-8, like file names.
+    Value(Vec<u8>),
+}
+
+fuzz_target!(|args: Vec<Arg>| {
+    let name = |x: &u8| OPTIONS[*x as usize % OPTIONS.len()].name;
+    let args: Vec<OsString> = Some("ffmpeg".into())
+        .into_iter()
+        .chain(args.iter().map(|arg| match arg {
+            Arg::Option(x) => format!("-{}", name(x)).into(),
+            Arg::NoOption(x) => format!("-no{}", name(x)).into(),
+            Arg::Specified(x, specifier) => format!("-{}:{}", name(x), specifier).into(),
+            Arg::Input => "-i".into(),
+            Arg::DashDash => "--".into(),
+            Arg::Dash => "-".into(),
+            Arg::Value(value) => OsString::from_vec(value.clone()),
+        }))
+        .collect();
+
+    let mut octx = init_parse_context(&*GROUPS);
+    let mut filtergraph = None;
+    let _ = split_commandline(&mut octx, &args, &*OPTIONS, &*GROUPS, &mut filtergraph);
+    uninit_parse_context(&mut octx);
+});
@@ -1,21 +1,30 @@
 use bitflags::bitflags;
-use libc::c_void;
-use log::{debug, error, info};
+use libc::{c_char, c_void};
+use log::{debug, error};
 use once_cell::sync::Lazy;
 use rusty_ffmpeg::{
     avutil::{avutils::*, error::*},
     ffi,
 };
 
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
 use std::{
+    borrow::Cow,
     collections::HashMap,
     default,
-    ffi::{CStr, CString},
+    ffi::{CStr, CString, OsStr, OsString},
     fmt, marker, mem, ptr, slice,
     sync::Mutex,
 };
 
-use crate::ffmpeg::OptionsContext;
+use crate::{
+    ffmpeg::OptionsContext,
+    logging,
+    options::{rewrite_alias, Rewrite},
+    preset,
+    target::{self, Norm, Setting},
+};
 
 enum OptGroup {
     GroupOutfile = 0,
@@ -48,12 +57,13 @@ bitflags! {
     }
 }
 
-static mut format_opts: *mut ffi::AVDictionary = ptr::null_mut();
-static mut codec_opts: *mut ffi::AVDictionary = ptr::null_mut();
+pub static mut format_opts: *mut ffi::AVDictionary = ptr::null_mut();
+pub static mut codec_opts: *mut ffi::AVDictionary = ptr::null_mut();
 static mut sws_dict: *mut ffi::AVDictionary = ptr::null_mut();
 static mut swr_opts: *mut ffi::AVDictionary = ptr::null_mut();
 static mut resample_opts: *mut ffi::AVDictionary = ptr::null_mut();
 
+#[derive(Clone, Copy)]
 pub union OptionOperation {
     pub dst_ptr: *mut c_void,
     pub func_arg: fn(*mut c_void, &str, &str) -> i64,
@@ -74,7 +84,7 @@ impl default::Default for OptionOperation {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct OptionDef<'a> {
     pub name: &'a str,
     pub help: &'a str,
@@ -105,14 +115,16 @@ pub struct OptionGroupDef<'global> {
 pub struct OptionKV<'global> {
     pub opt: &'global OptionDef<'global>,
     pub key: String,
-    pub val: String,
+    /// As given on the commandline, which may not be UTF-8.
+    pub val: OsString,
 }
 
 // TODO maybe split the lifetime here
 #[derive(Debug, Clone)]
 pub struct OptionGroup<'global> {
     pub group_def: &'global OptionGroupDef<'global>,
-    pub arg: String,
+    /// As given on the commandline, which may not be UTF-8.
+    pub arg: OsString,
     pub opts: Vec<OptionKV<'global>>,
     pub codec_opts: *mut ffi::AVDictionary,
     pub format_opts: *mut ffi::AVDictionary,
@@ -130,7 +142,7 @@ impl<'global> OptionGroup<'global> {
         };
         OptionGroup {
             group_def: &GLOBAL_GROUP,
-            arg: String::new(),
+            arg: OsString::new(),
             opts: vec![],
             codec_opts: ptr::null_mut(),
             format_opts: ptr::null_mut(),
@@ -150,7 +162,7 @@ impl<'global> OptionGroup<'global> {
         };
         OptionGroup {
             group_def: &NEVER_USE_GROUP,
-            arg: String::new(),
+            arg: OsString::new(),
             opts: vec![],
             codec_opts: ptr::null_mut(),
             format_opts: ptr::null_mut(),
@@ -218,7 +230,8 @@ pub fn parse_optgroup<'ctxt>(
 ) -> Result<(), ()> {
     debug!(
         "Parsing a group of options: {} {}.",
-        g.group_def.name, g.arg
+        g.group_def.name,
+        g.arg.to_string_lossy()
     );
     for o in g.opts.iter() {
         if !g.group_def.flags.is_empty() && !g.group_def.flags.intersects(o.opt.flags) {
@@ -227,12 +240,15 @@ pub fn parse_optgroup<'ctxt>(
                    {} {} -- you are trying to apply an input option to an \
                    output file or vice versa. Move this option before the \
                    file it belongs to.",
-                o.key, o.opt.help, g.group_def.name, g.arg
+                o.key,
+                o.opt.help,
+                g.group_def.name,
+                g.arg.to_string_lossy()
             );
             return Err(());
         }
         debug!(
-            "Applying option {} ({}) with argument {}.",
+            "Applying option {} ({}) with argument {:?}.",
             o.key, o.opt.help, o.val
         );
         write_option(&mut optctx, o.opt, &o.key, &o.val)?
@@ -249,10 +265,10 @@ pub fn parse_number(
     min: f64,
     max: f64,
 ) -> Result<f64, String> {
-    let numstr_ptr = CString::new(numstr).unwrap().as_ptr();
+    let numstr_c = CString::new(numstr).map_err(|_| format!("Invalid number for {}", context))?;
     let mut tail: *mut libc::c_char = ptr::null_mut();
-    let d = unsafe { ffi::av_strtod(numstr_ptr, &mut tail) };
-    let error = if tail.is_null() {
+    let d = unsafe { ffi::av_strtod(numstr_c.as_ptr(), &mut tail) };
+    let error = if tail.is_null() || unsafe { *tail } != 0 {
         format!("Expected number for {} but found: {}", context, numstr)
     } else {
         if d < min || d > max {
@@ -260,7 +276,10 @@ pub fn parse_number(
                 "The value for {} was {} which is not within {} - {}",
                 context, numstr, min, max
             )
-        } else if num_type == OptionFlag::OPT_INT64 && d as i64 as f64 != d {
+        } else if num_type == OptionFlag::OPT_INT64
+            && (d.trunc() != d || d < i64::MIN as f64 || d >= i64::MAX as f64)
+        {
+            // `i64::MAX as f64` is 2^63, which does not fit.
             format!("Expected int64 for {} but found {}", context, numstr)
         } else if num_type == OptionFlag::OPT_INT && d as isize as f64 != d {
             format!("Expected int for {} but found {}", context, numstr)
@@ -292,14 +311,26 @@ fn write_option(
     optctx: &mut Option<&mut OptionsContext>,
     po: &OptionDef,
     opt: &str,
-    arg: &str,
+    arg: &OsStr,
 ) -> Result<(), ()> {
-    let dst: *mut c_void = if po
+    // Strings are kept as the bytes of the argument, the numbers and the
+    // functions of the options take UTF-8.
+    if !po.flags.contains(OptionFlag::OPT_STRING) && arg.to_str().is_none() {
+        error!(
+            "Failed to set value '{}' for option '{}': not valid UTF-8",
+            arg.to_string_lossy(),
+            opt
+        );
+        return Err(());
+    }
+    let utf8 = arg.to_str().unwrap_or_default();
+
+    let mut dst: *mut c_void = if po
         .flags
         .intersects(OptionFlag::OPT_OFFSET | OptionFlag::OPT_SPEC)
     {
         if let &mut Some(ref mut optctx) = optctx {
-            *optctx as *mut _ as *mut c_void
+            unsafe { (*optctx as *mut _ as *mut u8).add(po.u.off) as *mut c_void }
         } else {
             panic!("some option contains OPT_OFFSET or OPT_SPEC but in global_opts")
         }
@@ -315,12 +346,23 @@ fn write_option(
             specifier: s.to_owned(),
             u: Default::default(),
         });
+        // The value goes into the new specifier option, e.g. the `max_frames`
+        // of a stream type for `-frames:v`.
+        dst = &mut so.last_mut().unwrap().u as *mut _ as *mut c_void;
     }
 
     if po.flags.contains(OptionFlag::OPT_STRING) {
-        let dst = dst as *mut String;
+        // A `char *` as in FFmpeg, both at an offset into the OptionsContext,
+        // in the `u.str` of a specifier option and in the global statics. It is
+        // freed by `uninit_options()`.
+        let dst = dst as *mut *mut c_char;
         let dst = unsafe { dst.as_mut() }.unwrap();
-        *dst = arg.to_owned();
+        let s = CString::new(os_bytes(arg).into_owned())
+            .map_err(|_| error!("Option {} contains a NUL byte", opt))?;
+        if !dst.is_null() {
+            drop(unsafe { CString::from_raw(*dst) });
+        }
+        *dst = s.into_raw();
     } else if po
         .flags
         .intersects(OptionFlag::OPT_STRING | OptionFlag::OPT_INT)
@@ -332,7 +374,7 @@ fn write_option(
         // need to change it.
         *dst = parse_number(
             opt,
-            arg,
+            utf8,
             OptionFlag::OPT_INT64,
             isize::MIN as f64,
             isize::MAX as f64,
@@ -343,7 +385,7 @@ fn write_option(
         let dst = unsafe { dst.as_mut() }.unwrap();
         *dst = parse_number(
             opt,
-            arg,
+            utf8,
             OptionFlag::OPT_INT64,
             i64::MIN as f64,
             i64::MAX as f64,
@@ -352,13 +394,13 @@ fn write_option(
     } else if po.flags.contains(OptionFlag::OPT_TIME) {
         let dst = dst as *mut i64;
         let dst = unsafe { dst.as_mut() }.unwrap();
-        *dst = parse_time(opt, arg, true).unwrap();
+        *dst = parse_time(opt, utf8, true).unwrap();
     } else if po.flags.contains(OptionFlag::OPT_FLOAT) {
         let dst = dst as *mut f32;
         let dst = unsafe { dst.as_mut() }.unwrap();
         *dst = parse_number(
             opt,
-            arg,
+            utf8,
             OptionFlag::OPT_INT64,
             i64::MIN as f64,
             i64::MAX as f64,
@@ -369,7 +411,7 @@ fn write_option(
         let dst = unsafe { dst.as_mut() }.unwrap();
         *dst = parse_number(
             opt,
-            arg,
+            utf8,
             OptionFlag::OPT_INT64,
             i64::MIN as f64,
             i64::MAX as f64,
@@ -382,12 +424,12 @@ fn write_option(
             ptr::null_mut()
         };
         let func = unsafe { po.u.func_arg };
-        let ret = func(optctx, opt, arg);
+        let ret = func(optctx, opt, utf8);
         // TODO av_err2str() still haven't been implemented
         if ret < 0 {
             error!(
                 "Failed to set value '{}' for option '{}': {}",
-                arg, opt, "av_err2str()"
+                utf8, opt, "av_err2str()"
             );
             return Err(());
         }
@@ -398,23 +440,90 @@ fn write_option(
     Ok(())
 }
 
+/// `parse_option()`: set the option `opt` of the table to `arg`, which the
+/// alias options do with their canonical option, like `frames:v` for
+/// `-vframes`.
+pub fn parse_option(
+    mut optctx: Option<&mut OptionsContext>,
+    opt: &str,
+    arg: &str,
+    options: &[OptionDef],
+) -> Result<(), ()> {
+    let (po, arg) = match find_option(options, opt) {
+        Some(po) if po.flags.contains(OptionFlag::OPT_BOOL) => (po, "1"),
+        Some(po) => (po, arg),
+        None => match opt.strip_prefix("no").and_then(|x| find_option(options, x)) {
+            Some(po) if po.flags.contains(OptionFlag::OPT_BOOL) => (po, "0"),
+            _ => {
+                error!("Unrecognized option '{}'", opt);
+                return Err(());
+            }
+        },
+    };
+    write_option(&mut optctx, po, opt, OsStr::new(arg))
+}
+
 enum ArgOperation {
     /// opt arg
-    AddOpt(String, String),
+    AddOpt(String, OsString),
     /// group_idx opt
-    FinishGroup(usize, String),
+    FinishGroup(usize, OsString),
     /// opt arg
-    OptDefault(String, String),
+    OptDefault(String, OsString),
+    /// Where the next operation comes from, when not from the commandline.
+    Origin(String),
+}
+
+/// Why `split_commandline()` rejected the commandline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitError {
+    NulByte(OsString),
+    MissingArgument(String),
+    /// option argument
+    InvalidArgument(String, OsString),
+    /// The option, and the closest known options, with their leading `-`.
+    UnrecognizedOption(String, Vec<String>),
+    Preset(String),
+    Target(String),
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::NulByte(arg) => write!(
+                f,
+                "Argument '{}' contains a NUL byte.",
+                arg.to_string_lossy().escape_debug()
+            ),
+            SplitError::MissingArgument(opt) => {
+                write!(f, "Missing argument for option '{}'.", opt)
+            }
+            SplitError::InvalidArgument(opt, arg) => write!(
+                f,
+                "Error parsing option '{}' with argument '{}'.",
+                opt,
+                arg.to_string_lossy()
+            ),
+            SplitError::UnrecognizedOption(opt, suggestions) => write!(
+                f,
+                "Unrecognized option '{}'.{}",
+                opt,
+                did_you_mean(suggestions)
+            ),
+            SplitError::Preset(message) | SplitError::Target(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
 }
 
-// TODO the Err in returned Result need to be a ERROR enum
 pub fn split_commandline<'ctxt, 'global>(
     octx: &'ctxt mut OptionParseContext<'global>,
-    args: &[String],
+    args: &[OsString],
     options: &'global [OptionDef],
     groups: &'global [OptionGroupDef],
-    filtergraph: &mut Option<String>,
-) -> Result<(), ()> {
+    filtergraph: &mut Option<OsString>,
+) -> Result<(), SplitError> {
     let (argc, argv) = (args.len(), args);
 
     let mut operations = vec![];
@@ -423,6 +532,12 @@ pub fn split_commandline<'ctxt, 'global>(
 
     debug!("Splitting the commandline.");
 
+    // Arguments from the real commandline cannot contain NUL bytes, but the
+    // options are passed to FFmpeg as C strings, so ensure it.
+    if let Some(arg) = args.iter().find(|arg| os_bytes(arg).contains(&0)) {
+        return Err(split_error(SplitError::NulByte(arg.clone())));
+    }
+
     let mut optindex = 1;
     let mut dashdash = None;
 
@@ -430,7 +545,7 @@ pub fn split_commandline<'ctxt, 'global>(
         let opt = &argv[optindex];
         optindex += 1;
 
-        debug!("Reading option '{}' ...", opt);
+        debug!("Reading option '{}' ...", opt.to_string_lossy());
 
         if opt == "--" {
             dashdash = Some(optindex);
@@ -438,7 +553,8 @@ pub fn split_commandline<'ctxt, 'global>(
         }
 
         // unnamed group separators, e.g. output filename
-        if !opt.starts_with('-') || opt.len() <= 1 || dashdash == Some(optindex - 1) {
+        let bytes = os_bytes(opt);
+        if bytes.first() != Some(&b'-') || bytes.len() <= 1 || dashdash == Some(optindex - 1) {
             // IMPROVEMENT original FFmpeg uses 0 rather than enum value here,
             // we can use the enum value since we know we are using FFmpeg.
             let out_index = OptGroup::GroupOutfile as usize;
@@ -448,14 +564,22 @@ pub fn split_commandline<'ctxt, 'global>(
             continue;
         }
 
-        // Jump over prefix `-`
-        let opt = &opt[1..];
+        // Jump over prefix `-`, the names of the options are all UTF-8.
+        let opt = match opt.to_str() {
+            Some(opt) => &opt[1..],
+            None => {
+                return Err(split_error(SplitError::UnrecognizedOption(
+                    opt.to_string_lossy()[1..].into(),
+                    vec![],
+                )))
+            }
+        };
 
         // Named group separators, e.g. -i
         if let Some(group_idx) = match_group_separator(groups, opt) {
             let arg = match argv.get(optindex) {
                 Some(arg) => arg,
-                None => return Err(()),
+                None => return Err(split_error(SplitError::MissingArgument(opt.into()))),
             };
             optindex += 1;
 
@@ -463,7 +587,8 @@ pub fn split_commandline<'ctxt, 'global>(
             operations.push(ArgOperation::FinishGroup(group_idx, arg.into()));
             debug!(
                 " matched as {} with argument '{}'.",
-                groups[group_idx].name, arg
+                groups[group_idx].name,
+                arg.to_string_lossy()
             );
             continue;
         }
@@ -476,33 +601,73 @@ pub fn split_commandline<'ctxt, 'global>(
                 // Yes, we cannot use unwrap_or() here because a coercion needed.
                 let arg = match argv.get(optindex) {
                     Some(x) => x,
-                    None => "",
+                    None => OsStr::new(""),
                 };
                 optindex += 1;
                 arg
             } else if po.flags.intersects(OptionFlag::HAS_ARG) {
                 let arg = match argv.get(optindex) {
                     Some(x) => x,
-                    None => return Err(()),
+                    None => return Err(split_error(SplitError::MissingArgument(opt.into()))),
                 };
                 optindex += 1;
                 arg
             } else {
-                "1"
+                OsStr::new("1")
             };
 
+            if apply_preset(octx, options, opt, arg, &mut operations)?
+                || apply_target(octx, options, opt, arg, &mut operations)?
+            {
+                continue;
+            }
+
             // match vf af filter_complex, For presentation purpose
-            match opt {
-                "vf" | "af" | "filter_complex" => *filtergraph = Some(arg.to_string()),
-                _ => {}
+            if let "vf" | "af" | "filter_complex" = opt {
+                *filtergraph = Some(arg.to_owned());
             }
 
-            add_opt(octx, po, opt, arg);
-            operations.push(ArgOperation::AddOpt(opt.into(), arg.into()));
+            // Only the canonical options are put into the groups.
+            let (po, key) = match rewrite_alias(opt) {
+                Some(Rewrite::Option(key)) => match find_option(options, &key) {
+                    Some(canonical) => (canonical, key),
+                    None => (po, opt.to_owned()),
+                },
+                Some(Rewrite::CodecOption(key)) => {
+                    set_codec_opt(&key, arg);
+                    debug!(" matched as alias of codec option '{}'.", key);
+                    operations.push(ArgOperation::OptDefault(key, arg.into()));
+                    continue;
+                }
+                None => (po, opt.to_owned()),
+            };
+
+            // Numbers are only parsed by `parse_optgroup()`, which is too
+            // late to tell which argument is wrong.
+            if po.flags.contains(OptionFlag::OPT_INT64) {
+                let parsed = arg.to_str().ok_or_else(String::new).and_then(|x| {
+                    parse_number(
+                        &key,
+                        x,
+                        OptionFlag::OPT_INT64,
+                        i64::MIN as f64,
+                        i64::MAX as f64,
+                    )
+                });
+                if let Err(e) = parsed {
+                    if !e.is_empty() {
+                        error!("{}", e);
+                    }
+                    return Err(split_error(SplitError::InvalidArgument(key, arg.into())));
+                }
+            }
+
+            add_opt(octx, po, &key, arg);
             debug!(
                 " matched as option '{}' ({}) with argument '{:?}'.",
                 po.name, po.help, arg
             );
+            operations.push(ArgOperation::AddOpt(key, arg.into()));
             continue;
         }
 
@@ -517,12 +682,14 @@ pub fn split_commandline<'ctxt, 'global>(
                 // We can put it here because currently opt_default() only
                 // returns 0 or AVERROR_OPTION_NOT_FOUND.
                 operations.push(ArgOperation::OptDefault(opt.into(), arg.into()));
-                debug!(" matched as AVOption '{}' with argument '{}'.", opt, arg);
+                debug!(" matched as AVOption '{}' with argument {:?}.", opt, arg);
                 optindex += 1;
                 continue;
             } else if ret != AVERROR_OPTION_NOT_FOUND {
-                error!("Error parsing option '{}' with argument '{}'.\n", opt, arg);
-                return Err(());
+                return Err(split_error(SplitError::InvalidArgument(
+                    opt.into(),
+                    arg.clone(),
+                )));
             }
         }
 
@@ -530,7 +697,7 @@ pub fn split_commandline<'ctxt, 'global>(
         if opt.starts_with("no") {
             if let Some(po) = find_option(options, &opt[2..]) {
                 if po.flags.contains(OptionFlag::OPT_BOOL) {
-                    add_opt(octx, po, opt, "0");
+                    add_opt(octx, po, opt, OsStr::new("0"));
                     operations.push(ArgOperation::AddOpt(opt[2..].into(), opt.into()));
                     debug!(
                         " matched as option '{}' ({}) with argument 0.",
@@ -541,8 +708,10 @@ pub fn split_commandline<'ctxt, 'global>(
             }
         }
 
-        error!("Unrecognized option '{}'.", opt);
-        return Err(());
+        return Err(split_error(SplitError::UnrecognizedOption(
+            opt.into(),
+            option_suggestions(options, opt),
+        )));
     }
 
     if !octx.cur_group.opts.is_empty()
@@ -561,7 +730,9 @@ pub fn split_commandline<'ctxt, 'global>(
                 r#"
 add_opt(octx, find_option(options, "{}"), "{}", "{}");
 "#,
-                opt, opt, arg
+                escape_c_string(opt),
+                escape_c_string(opt),
+                escape_c_bytes(&os_bytes(arg))
             );
         }
         ArgOperation::FinishGroup(group_idx, opt) => {
@@ -569,7 +740,8 @@ add_opt(octx, find_option(options, "{}"), "{}", "{}");
                 r#"
 finish_group(octx, {}, "{}");
 "#,
-                group_idx, opt
+                group_idx,
+                escape_c_bytes(&os_bytes(opt))
             );
         }
         ArgOperation::OptDefault(opt, arg) => {
@@ -577,9 +749,11 @@ finish_group(octx, {}, "{}");
                 r#"
 opt_default(NULL, "{}", "{}");
 "#,
-                opt, arg
+                escape_c_string(opt),
+                escape_c_bytes(&os_bytes(arg))
             );
         }
+        ArgOperation::Origin(origin) => print!("\n/* {} */", origin.replace("*/", "*\\/")),
     };
 
     for operation in operations.iter() {
@@ -589,17 +763,339 @@ opt_default(NULL, "{}", "{}");
     Ok(())
 }
 
-fn opt_default(_: *mut c_void, opt: &str, arg: &str) -> i32 {
+/// `av_dict_set(&o->g->codec_opts, ...)` of the aliases of codec options,
+/// which does not look the option up.
+fn set_codec_opt(key: &str, arg: &OsStr) {
+    let key = CString::new(key).unwrap();
+    let arg = CString::new(os_bytes(arg).as_ref()).unwrap();
+    unsafe { ffi::av_dict_set(&mut codec_opts as *mut _, key.as_ptr(), arg.as_ptr(), 0) };
+}
+
+/// The codec given earlier in the current group for the stream specifier,
+/// like `MATCH_PER_TYPE_OPT(codec_names, ...)` finds it.
+fn group_codec(octx: &OptionParseContext, specifier: &str) -> Option<String> {
+    octx.cur_group.opts.iter().rev().find_map(|o| {
+        let (name, spec) = match o.key.find(':') {
+            Some(i) => (&o.key[..i], &o.key[i + 1..]),
+            None => (o.key.as_str(), ""),
+        };
+        let matches = (name == "c" || name == "codec") && spec == specifier;
+        matches.then(|| o.val.to_string_lossy().into_owned())
+    })
+}
+
+/// Read the preset of `-pre`, `-vpre`, `-apre`, `-spre` or `-fpre`, and put
+/// its values into the current group in place of the preset option. Returns
+/// whether `opt` is a preset option.
+fn apply_preset<'global>(
+    octx: &mut OptionParseContext<'global>,
+    options: &'global [OptionDef],
+    opt: &str,
+    arg: &OsStr,
+    operations: &mut Vec<ArgOperation>,
+) -> Result<bool, SplitError> {
+    let name = opt.split(':').next().unwrap_or_default();
+    let specifier = opt[name.len()..].trim_start_matches(':');
+    let preset = match name {
+        "vpre" | "apre" | "spre" | "fpre" => {
+            let codec = group_codec(octx, &name[..1]);
+            preset::load_ffpreset(arg, name == "fpre", codec.as_deref())
+        }
+        "pre" => {
+            let codec = group_codec(octx, specifier);
+            preset::load_avpreset(&arg.to_string_lossy(), codec.as_deref())
+        }
+        _ => return Ok(false),
+    }
+    .map_err(|e| split_error(SplitError::Preset(e)))?;
+    debug!(
+        " matched as preset '{}' with {} value(s).",
+        preset.path.display(),
+        preset.entries.len()
+    );
+
+    for entry in preset.entries.iter() {
+        let origin = preset::origin(&preset, entry, opt, arg);
+        let value = OsStr::new(&entry.value);
+        let codec_type = match entry.key.as_str() {
+            "vcodec" | "acodec" | "scodec" | "dcodec" if name != "pre" => Some(&entry.key[..1]),
+            _ => None,
+        };
+        if let Some(codec_type) = codec_type {
+            let po = find_option(options, "codec").ok_or_else(|| {
+                split_error(SplitError::Preset(format!(
+                    "{}: No codec option for '{}'",
+                    preset.path.display(),
+                    entry.key
+                )))
+            })?;
+            let key = format!("codec:{}", codec_type);
+            add_opt(octx, po, &key, value);
+            operations.push(ArgOperation::Origin(origin));
+            operations.push(ArgOperation::AddOpt(key, value.into()));
+            continue;
+        }
+
+        // The values of `-pre` are given to the encoders of the streams it
+        // applies to, without overwriting the options already set.
+        let key = if name == "pre" && !specifier.is_empty() {
+            format!("{}:{}", entry.key, specifier)
+        } else {
+            entry.key.clone()
+        };
+        if name == "pre" {
+            let key_c = CString::new(key.as_str()).unwrap();
+            let is_set =
+                |dict| unsafe { !ffi::av_dict_get(dict, key_c.as_ptr(), ptr::null(), 0).is_null() };
+            if unsafe { is_set(codec_opts) || is_set(format_opts) } {
+                continue;
+            }
+        }
+        if opt_default(ptr::null_mut(), &key, value) < 0 {
+            return Err(split_error(SplitError::Preset(format!(
+                "{}: Invalid option or argument: '{}={}', parsed as '{}' = '{}'",
+                preset.path.display(),
+                entry.key,
+                entry.value,
+                key,
+                entry.value
+            ))));
+        }
+        operations.push(ArgOperation::Origin(origin));
+        operations.push(ArgOperation::OptDefault(key, value.into()));
+    }
+    Ok(true)
+}
+
+/// The norm of the first input frame rate given on the commandline, with
+/// `-r` or the `framerate` of the demuxer. The input files are not opened.
+fn input_norm(octx: &OptionParseContext) -> Option<Norm> {
+    let framerate = CString::new("framerate").unwrap();
+    octx.groups[OptGroup::GroupInfile as usize]
+        .groups
+        .iter()
+        .find_map(|g| {
+            let mut rates: Vec<String> = g
+                .opts
+                .iter()
+                .filter(|o| o.key.split(':').next() == Some("r"))
+                .map(|o| o.val.to_string_lossy().into_owned())
+                .collect();
+            let entry =
+                unsafe { ffi::av_dict_get(g.format_opts, framerate.as_ptr(), ptr::null(), 0) };
+            if !entry.is_null() {
+                let value = unsafe { CStr::from_ptr((*entry).value) };
+                rates.push(value.to_string_lossy().into_owned());
+            }
+            rates.iter().find_map(|x| target::rate_norm(x))
+        })
+}
+
+/// Put the options of `-target` into the current group in place of it.
+/// Returns whether `opt` is `-target`.
+fn apply_target<'global>(
+    octx: &mut OptionParseContext<'global>,
+    options: &'global [OptionDef],
+    opt: &str,
+    arg: &OsStr,
+    operations: &mut Vec<ArgOperation>,
+) -> Result<bool, SplitError> {
+    if opt.split(':').next() != Some("target") {
+        return Ok(false);
+    }
+    let name = arg.to_string_lossy();
+    let (_, settings) =
+        target::expand(&name, input_norm(octx)).map_err(|e| split_error(SplitError::Target(e)))?;
+    debug!(" matched as target with {} option(s).", settings.len());
+
+    let origin = format!("from -{} {}", opt, name);
+    for setting in settings {
+        match setting {
+            Setting::Parse(key, value) => {
+                let po = find_option(options, key).ok_or_else(|| {
+                    split_error(SplitError::Target(format!(
+                        "Option '-{}' of target '{}' is not available.",
+                        key, name
+                    )))
+                })?;
+                add_opt(octx, po, key, OsStr::new(value));
+                operations.push(ArgOperation::Origin(origin.clone()));
+                operations.push(ArgOperation::AddOpt(key.into(), value.into()));
+            }
+            // Like FFmpeg, the options missing in the linked build are left
+            // out without an error.
+            Setting::AVOption(key, value) => {
+                if opt_default(ptr::null_mut(), key, OsStr::new(value)) < 0 {
+                    continue;
+                }
+                operations.push(ArgOperation::Origin(origin.clone()));
+                operations.push(ArgOperation::OptDefault(key.into(), value.into()));
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn split_error(err: SplitError) -> SplitError {
+    error!("{}", err);
+    err
+}
+
+/// Levenshtein distance, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let substitution = diagonal + if x == y { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidates closest to `name`, at most three of them, none when nothing
+/// is close enough to be a typo of it.
+pub fn suggestions<'a, I>(name: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    close.sort();
+    close.dedup();
+    close.into_iter().take(3).map(|(_, x)| x.into()).collect()
+}
+
+/// The sentence appended to an error message for the suggestions.
+pub fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|x| format!("'{}'", x)).collect();
+    match quoted.len() {
+        0 => String::new(),
+        1 => format!(" Did you mean {}?", quoted[0]),
+        _ => format!(" Did you mean one of {}?", quoted.join(", ")),
+    }
+}
+
+/// Names of the AVOptions of the class and of its children, without the
+/// constants.
+unsafe fn avoption_names(class: *const ffi::AVClass, names: &mut Vec<String>) {
+    if class.is_null() {
+        return;
+    }
+    let obj = &class as *const *const ffi::AVClass as *mut c_void;
+    let mut opt = ffi::av_opt_next(obj, ptr::null());
+    while let Some(x) = opt.as_ref() {
+        if x.type_ != ffi::AVOptionType_AV_OPT_TYPE_CONST && !x.name.is_null() {
+            names.push(CStr::from_ptr(x.name).to_string_lossy().into());
+        }
+        opt = ffi::av_opt_next(obj, opt);
+    }
+    let mut child = ffi::av_opt_child_class_next(class, ptr::null());
+    while !child.is_null() {
+        avoption_names(child, names);
+        child = ffi::av_opt_child_class_next(class, child);
+    }
+}
+
+/// Options close to the unrecognized `opt`, which is without its leading `-`,
+/// keeping its stream specifier.
+fn option_suggestions(options: &[OptionDef], opt: &str) -> Vec<String> {
+    let (name, specifier) = match opt.find(':') {
+        Some(i) => opt.split_at(i),
+        None => (opt, ""),
+    };
+    let mut candidates: Vec<String> = vec![];
+    for po in options.iter() {
+        candidates.push(po.name.into());
+        if po.flags.contains(OptionFlag::OPT_BOOL) {
+            candidates.push(format!("no{}", po.name));
+        }
+    }
+    unsafe {
+        avoption_names(ffi::avcodec_get_class(), &mut candidates);
+        avoption_names(ffi::avformat_get_class(), &mut candidates);
+    }
+    suggestions(name, candidates.iter().map(String::as_str))
+        .into_iter()
+        .map(|x| format!("-{}{}", x, specifier))
+        .collect()
+}
+
+/// The bytes of a commandline argument, which are what FFmpeg gets in `argv`.
+#[cfg(unix)]
+pub fn os_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Borrowed(s.as_bytes())
+}
+
+/// The bytes of a commandline argument, which are what FFmpeg gets in `argv`,
+/// UTF-8 converted from the wide strings on Windows.
+#[cfg(not(unix))]
+pub fn os_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    match s.to_string_lossy() {
+        Cow::Borrowed(x) => Cow::Borrowed(x.as_bytes()),
+        Cow::Owned(x) => Cow::Owned(x.into_bytes()),
+    }
+}
+
+/// A commandline argument from the bytes FFmpeg would get in `argv`.
+#[cfg(unix)]
+pub fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+/// A commandline argument from the bytes FFmpeg would get in `argv`, which
+/// are UTF-8 on Windows.
+#[cfg(not(unix))]
+pub fn os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// Escape a string to be put between the double quotes of a C string literal
+/// in the generated code.
+pub fn escape_c_string(s: &str) -> String {
+    escape_c_bytes(s.as_bytes())
+}
+
+/// Escape bytes, UTF-8 or not, to be put between the double quotes of a C
+/// string literal in the generated code.
+pub fn escape_c_bytes(s: &[u8]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for &x in s {
+        match x {
+            b'\\' => escaped.push_str("\\\\"),
+            b'"' => escaped.push_str("\\\""),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b' '..=b'~' => escaped.push(x as char),
+            // Octal escapes take at most 3 digits, unlike the hexadecimal ones
+            // which would eat the digits following them.
+            _ => escaped.push_str(&format!("\\{:03o}", x)),
+        }
+    }
+    escaped
+}
+
+pub fn opt_default(_: *mut c_void, opt: &str, arg: &OsStr) -> i32 {
     if opt == "debug" || opt == "fdebug" {
-        // TODO implement equivalent function of av_log_set_level()
-        info!("debug is the default");
+        logging::set_level(logging::AV_LOG_DEBUG, unsafe { ffi::av_log_get_flags() });
     }
     let opt_stripped = CString::new(opt.split(':').next().unwrap()).unwrap();
     // This is unicode-safe because it's only used when first char is ascii.
     let opt_nohead = opt.get(1..).map(|x| CString::new(x).unwrap());
 
     let opt_c = CString::new(opt).unwrap();
-    let arg_c = CString::new(arg).unwrap();
+    let arg = os_bytes(arg);
+    let arg_c = CString::new(arg.as_ref()).unwrap();
 
     let (opt_ptr, arg_ptr) = (opt_c.as_ptr(), arg_c.as_ptr());
 
@@ -635,7 +1131,7 @@ fn opt_default(_: *mut c_void, opt: &str, arg: &str) -> i32 {
         // Shouldn't be null, so unwrap.
         let o = unsafe { o.as_ref() }.unwrap();
         let flags = if o.type_ == ffi::AVOptionType_AV_OPT_TYPE_FLAGS
-            && (arg.starts_with('-') || arg.starts_with('+'))
+            && (arg.starts_with(b"-") || arg.starts_with(b"+"))
         {
             ffi::AV_DICT_APPEND
         } else {
@@ -653,7 +1149,7 @@ fn opt_default(_: *mut c_void, opt: &str, arg: &str) -> i32 {
     );
     if let Some(o) = unsafe { o.as_ref() } {
         let flags = if o.type_ == ffi::AVOptionType_AV_OPT_TYPE_FLAGS
-            && (arg.starts_with('-') || arg.starts_with('+'))
+            && (arg.starts_with(b"-") || arg.starts_with(b"+"))
         {
             ffi::AV_DICT_APPEND
         } else {
@@ -701,7 +1197,7 @@ fn match_group_separator(groups: &[OptionGroupDef], opt: &str) -> Option<usize>
 /// # Parameters
 /// `group_idx`     which group definition should this group belong to
 /// `arg`           argument of the group delimiting option
-fn finish_group(octx: &mut OptionParseContext, group_idx: usize, arg: &str) {
+fn finish_group(octx: &mut OptionParseContext, group_idx: usize, arg: &OsStr) {
     let mut new_group = octx.cur_group.clone();
     new_group.arg = arg.to_owned();
     new_group.group_def = octx.groups[group_idx].group_def;
@@ -751,12 +1247,37 @@ fn find_option<'global>(
     options.iter().find(|&option_def| option_def.name == name)
 }
 
+/// `locate_option()`: the index of the first `-name` argument, for the options
+/// like `-loglevel` which are applied before the commandline is split.
+/// Unknown options are assumed to take an argument.
+pub fn locate_option(args: &[OsString], options: &[OptionDef], name: &str) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        let cur_opt = args[i].to_string_lossy();
+        if let Some(cur_opt) = cur_opt.strip_prefix('-') {
+            let po = find_option(options, cur_opt).or_else(|| {
+                cur_opt
+                    .strip_prefix("no")
+                    .and_then(|x| find_option(options, x))
+            });
+            match po {
+                Some(po) if po.name == name => return Some(i),
+                None if cur_opt == name => return Some(i),
+                Some(po) if !po.flags.contains(OptionFlag::HAS_ARG) => {}
+                _ => i += 1,
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
 /// Add an option instance to currently parsed group.
 fn add_opt<'ctxt, 'global>(
     octx: &'ctxt mut OptionParseContext<'global>,
     opt: &'global OptionDef<'global>,
     key: &str,
-    val: &str,
+    val: &OsStr,
 ) {
     let global = !opt
         .flags
@@ -822,3 +1343,359 @@ mod types_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod split_commandline_tests {
+    use super::*;
+    use crate::{
+        options::{FFmpegVersion, GROUPS, OPTIONS},
+        test_util::TempDir,
+    };
+
+    fn split(args: &[&str]) -> Result<(), SplitError> {
+        split_with(&*OPTIONS, args, |_, _| ())
+    }
+
+    /// Splits `args` and hands the parse context and the filtergraph to `f`
+    /// before the context is released.
+    fn split_with<A: AsRef<OsStr>, T>(
+        options: &'static [OptionDef<'static>],
+        args: &[A],
+        f: impl FnOnce(&OptionParseContext, &Option<OsString>) -> T,
+    ) -> Result<T, SplitError> {
+        let args: Vec<OsString> = args.iter().map(|x| x.as_ref().into()).collect();
+        let mut octx = init_parse_context(&*GROUPS);
+        let mut filtergraph = None;
+        let ret = split_commandline(&mut octx, &args, options, &*GROUPS, &mut filtergraph)
+            .map(|_| f(&octx, &filtergraph));
+        uninit_parse_context(&mut octx);
+        ret
+    }
+
+    #[test]
+    fn fuzz_regressions() {
+        assert!(split(&["ffmpeg"]).is_ok());
+        assert!(split(&["ffmpeg", "-", "--", "-i"]).is_ok());
+        assert!(split(&["ffmpeg", "-i"]).is_err());
+        assert!(split(&["ffmpeg", "-vf"]).is_err());
+        assert!(split(&["ffmpeg", "-nofoo"]).is_err());
+        assert!(split(&["ffmpeg", "-ÿ", "x"]).is_err());
+        // Used to panic on converting them into `CString`.
+        assert!(split(&["ffmpeg", "-b\0", "x"]).is_err());
+        assert!(split(&["ffmpeg", "-i", "in\0put.mp4"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_arguments() {
+        use std::os::unix::ffi::OsStringExt;
+
+        // "café.mp4" and "ça.mkv" in Latin-1.
+        let input = OsString::from_vec(b"caf\xe9.mp4".to_vec());
+        let output = OsString::from_vec(b"\xe7a.mkv".to_vec());
+        let args = vec![
+            "ffmpeg".into(),
+            "-i".into(),
+            input.clone(),
+            "-metadata".into(),
+            OsString::from_vec(b"title=\xe9t\xe9".to_vec()),
+            output.clone(),
+        ];
+        split_with(&*OPTIONS, &args, |octx, _| {
+            let infile = &octx.groups[OptGroup::GroupInfile as usize].groups[0];
+            let outfile = &octx.groups[OptGroup::GroupOutfile as usize].groups[0];
+            assert_eq!(infile.arg, input);
+            assert_eq!(outfile.arg, output);
+            assert_eq!(os_bytes(&outfile.opts[0].val), &b"title=\xe9t\xe9"[..]);
+
+            // The string options keep the bytes, the others take UTF-8.
+            let mut outfile = outfile.clone();
+            let opts = outfile.opts.clone();
+            let mut o = OptionsContext::new(&mut outfile);
+            assert!(
+                write_option(&mut Some(&mut o), opts[0].opt, &opts[0].key, &opts[0].val).is_ok()
+            );
+            let metadata = unsafe { CStr::from_ptr(o.metadata[0].u.str as *const c_char) };
+            assert_eq!(metadata.to_bytes(), b"title=\xe9t\xe9");
+            let latin1 = OsString::from_vec(b"\xe9".to_vec());
+            let ar = find_option(&*OPTIONS, "ar").unwrap();
+            assert!(write_option(&mut Some(&mut o), ar, "ar", &latin1).is_err());
+            crate::ffmpeg_opt::uninit_options(&mut o, &*OPTIONS);
+        })
+        .unwrap();
+
+        let vf = OsString::from_vec(b"drawtext=text=\xe9t\xe9".to_vec());
+        let args = ["ffmpeg".into(), "-vf".into(), vf.clone()];
+        let filtergraph = split_with(&*OPTIONS, &args, |_, filtergraph| filtergraph.clone());
+        assert_eq!(filtergraph, Ok(Some(vf)));
+    }
+
+    #[test]
+    fn unrecognized_options() {
+        let suggested = |args: &[&str]| match split(args) {
+            Err(SplitError::UnrecognizedOption(_, suggestions)) => suggestions,
+            ret => panic!("{:?}", ret),
+        };
+        assert_eq!(
+            suggested(&["ffmpeg", "-filter_complx"]),
+            ["-filter_complex"]
+        );
+        assert_eq!(suggested(&["ffmpeg", "-cdec:v"]), ["-codec:v"]);
+        assert_eq!(suggested(&["ffmpeg", "-nostat"]), ["-nostats"]);
+        assert!(suggested(&["ffmpeg", "-xyzzy"]).is_empty());
+        assert_eq!(
+            split(&["ffmpeg", "-vf"]),
+            Err(SplitError::MissingArgument("vf".into()))
+        );
+
+        let error = SplitError::UnrecognizedOption("qq".into(), vec!["-q".into(), "-y".into()]);
+        assert_eq!(
+            error.to_string(),
+            "Unrecognized option 'qq'. Did you mean one of '-q', '-y'?"
+        );
+    }
+
+    #[test]
+    fn close_names() {
+        assert_eq!(edit_distance("scale", "scale"), 0);
+        assert_eq!(edit_distance("scal", "scale"), 1);
+        assert_eq!(edit_distance("sacle", "scale"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(
+            suggestions("ovrlay", vec!["overlay", "null", "overlay", "volume"]),
+            ["overlay"]
+        );
+        assert_eq!(did_you_mean(&[]), "");
+        assert_eq!(did_you_mean(&["-vf".into()]), " Did you mean '-vf'?");
+    }
+
+    #[test]
+    fn presets() {
+        let dir = TempDir::new("split_presets");
+        let good = dir.join("good.ffpreset");
+        let bad = dir.join("bad.ffpreset");
+        std::fs::write(&good, "# x264\nvcodec=libx264\ng=250\n").unwrap();
+        std::fs::write(&bad, "g=250\nxyzzy=1\n").unwrap();
+
+        let args = [
+            OsStr::new("ffmpeg"),
+            "-fpre".as_ref(),
+            good.as_ref(),
+            "out.mp4".as_ref(),
+        ];
+        split_with(&*OPTIONS, &args, |octx, _| {
+            let outfile = &octx.groups[OptGroup::GroupOutfile as usize].groups[0];
+            assert_eq!(outfile.opts[0].key, "codec:v");
+            assert_eq!(outfile.opts[0].val, "libx264");
+            let g = CString::new("g").unwrap();
+            let entry = unsafe { ffi::av_dict_get(outfile.codec_opts, g.as_ptr(), ptr::null(), 0) };
+            assert!(!entry.is_null());
+        })
+        .unwrap();
+
+        let error = split(&["ffmpeg", "-fpre", bad.to_str().unwrap(), "out.mp4"]).unwrap_err();
+        assert!(matches!(error, SplitError::Preset(message) if message.contains("'xyzzy=1'")));
+        assert!(split(&["ffmpeg", "-vpre", "ffgen-nonexistent", "out.mp4"]).is_err());
+    }
+
+    #[test]
+    fn targets() {
+        let output_opts = |args: &[&str]| {
+            split_with(&*OPTIONS, args, |octx, _| -> Vec<(String, OsString)> {
+                octx.groups[OptGroup::GroupOutfile as usize]
+                    .groups
+                    .iter()
+                    .flat_map(|g| g.opts.iter().map(|o| (o.key.clone(), o.val.clone())))
+                    .collect()
+            })
+        };
+        let opts = output_opts(&[
+            "ffmpeg",
+            "-r",
+            "30000/1001",
+            "-i",
+            "in.mp4",
+            "-target",
+            "vcd",
+            "out.mpg",
+        ])
+        .unwrap();
+        assert!(opts.contains(&("s".into(), "352x240".into())));
+        assert!(opts.contains(&("c:v".into(), "mpeg1video".into())));
+        let opts =
+            output_opts(&["ffmpeg", "-i", "in.mp4", "-target", "pal-dv50", "out.dv"]).unwrap();
+        assert!(opts.contains(&("pix_fmt".into(), "yuv422p".into())));
+
+        assert!(matches!(
+            output_opts(&["ffmpeg", "-i", "in.mp4", "-target", "dvd", "out.mpg"]),
+            Err(SplitError::Target(_))
+        ));
+        assert!(output_opts(&["ffmpeg", "-target", "ntsc-laserdisc", "out.mpg"]).is_err());
+    }
+
+    #[test]
+    fn canonical_options() {
+        let args = [
+            "ffmpeg",
+            "-i",
+            "in.mp4",
+            "-vcodec",
+            "libx264",
+            "-vf",
+            "scale=320:240",
+            "-qscale",
+            "2",
+            "-b",
+            "1M",
+            "out.mp4",
+        ];
+        split_with(&*OPTIONS, &args, |octx, filtergraph| {
+            let outfile = &octx.groups[OptGroup::GroupOutfile as usize].groups[0];
+            let keys: Vec<(&str, &str)> = outfile
+                .opts
+                .iter()
+                .map(|o| (o.key.as_str(), o.opt.name))
+                .collect();
+            assert_eq!(
+                keys,
+                [("codec:v", "codec"), ("filter:v", "filter"), ("q:v", "q")]
+            );
+            let b = CString::new("b:v").unwrap();
+            let entry = unsafe { ffi::av_dict_get(outfile.codec_opts, b.as_ptr(), ptr::null(), 0) };
+            assert_eq!(unsafe { CStr::from_ptr((*entry).value) }.to_str(), Ok("1M"));
+            // The filtergraph is still shown.
+            assert_eq!(filtergraph.as_deref(), Some(OsStr::new("scale=320:240")));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn frame_limits() {
+        let args = [
+            "ffmpeg", "-i", "in.mp4", "-vframes", "100", "-aframes", "1e3", "out.mp4",
+        ];
+        split_with(&*OPTIONS, &args, |octx, _| {
+            let mut outfile = octx.groups[OptGroup::GroupOutfile as usize].groups[0].clone();
+            let opts = outfile.opts.clone();
+            let keys: Vec<&str> = opts.iter().map(|o| o.key.as_str()).collect();
+            assert_eq!(keys, ["frames:v", "frames:a"]);
+
+            let mut o = OptionsContext::new(&mut outfile);
+            for kv in opts.iter() {
+                assert!(write_option(&mut Some(&mut o), kv.opt, &kv.key, &kv.val).is_ok());
+            }
+            let limits: Vec<(&str, i64)> = o
+                .max_frames
+                .iter()
+                .map(|x| (x.specifier.as_str(), unsafe { x.u.i64 }))
+                .collect();
+            assert_eq!(limits, [("v", 100), ("a", 1000)]);
+        })
+        .unwrap();
+
+        let invalid = |value: &str| {
+            split(&["ffmpeg", "-i", "in.mp4", "-frames:v", value, "out.mp4"])
+                == Err(SplitError::InvalidArgument("frames:v".into(), value.into()))
+        };
+        assert!(invalid("1.5"));
+        assert!(invalid("ten"));
+        assert!(invalid("9223372036854775808"));
+        assert!(!invalid("-9223372036854775808"));
+    }
+
+    #[test]
+    fn string_specifier_options() {
+        let args = [
+            "ffmpeg",
+            "-i",
+            "in.mp4",
+            "-c:v",
+            "libx264",
+            "-metadata:s:a",
+            "title=x",
+            "-f",
+            "matroska",
+            "-f",
+            "mp4",
+            "out.mp4",
+        ];
+        split_with(&*OPTIONS, &args, |octx, _| {
+            let mut outfile = octx.groups[OptGroup::GroupOutfile as usize].groups[0].clone();
+            let opts = outfile.opts.clone();
+
+            let mut o = OptionsContext::new(&mut outfile);
+            for kv in opts.iter() {
+                assert!(write_option(&mut Some(&mut o), kv.opt, &kv.key, &kv.val).is_ok());
+            }
+            let strings = |so: &[SpecifierOpt]| -> Vec<(String, String)> {
+                so.iter()
+                    .map(|x| {
+                        let s = unsafe { CStr::from_ptr(x.u.str as *const c_char) };
+                        (x.specifier.clone(), s.to_str().unwrap().to_owned())
+                    })
+                    .collect()
+            };
+            assert_eq!(strings(&o.codec_names), [("v".into(), "libx264".into())]);
+            assert_eq!(strings(&o.metadata), [("s:a".into(), "title=x".into())]);
+            // The last `-f` replaces the first one.
+            assert_eq!(unsafe { CStr::from_ptr(o.format) }.to_str(), Ok("mp4"));
+
+            crate::ffmpeg_opt::uninit_options(&mut o, &*OPTIONS);
+            assert!(o.codec_names.is_empty() && o.metadata.is_empty());
+            assert!(o.format.is_null());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn versioned_options() {
+        let accepts = |version: FFmpegVersion, args: &[&str]| {
+            let args: Vec<&str> = ["ffmpeg"]
+                .iter()
+                .chain(args)
+                .chain(&["out.mp4"])
+                .copied()
+                .collect();
+            split_with(version.options(), &args, |_, _| ()).is_ok()
+        };
+        assert!(!accepts(FFmpegVersion::V4_3, &["-stats_period", "1"]));
+        assert!(accepts(FFmpegVersion::V4_4, &["-stats_period", "1"]));
+        assert!(accepts(FFmpegVersion::V7_0, &["-stats_period", "1"]));
+
+        assert!(!accepts(
+            FFmpegVersion::V5_1,
+            &["-fix_sub_duration_heartbeat:v"]
+        ));
+        assert!(accepts(
+            FFmpegVersion::V6_0,
+            &["-fix_sub_duration_heartbeat:v"]
+        ));
+
+        assert!(accepts(FFmpegVersion::V4_4, &["-vc", "1"]));
+        assert!(!accepts(FFmpegVersion::V5_0, &["-vc", "1"]));
+        assert!(accepts(FFmpegVersion::V6_1, &["-map_channel", "0.0.0"]));
+        assert!(!accepts(FFmpegVersion::V7_0, &["-map_channel", "0.0.0"]));
+        // Deprecated in favour of -fps_mode, but still there.
+        assert!(accepts(
+            FFmpegVersion::V7_0,
+            &["-vsync", "cfr", "-fps_mode", "cfr"]
+        ));
+    }
+}
+
+#[cfg(test)]
+mod serialization_tests {
+    use super::*;
+
+    #[test]
+    fn escape_c_string_literal() {
+        assert_eq!(escape_c_string("scale=720:480"), "scale=720:480");
+        assert_eq!(
+            escape_c_string("text='say \"hi\"\\n'"),
+            "text='say \\\"hi\\\"\\\\n'"
+        );
+        assert_eq!(escape_c_string("a\nb\tc"), "a\\nb\\tc");
+        assert_eq!(escape_c_string("\x01é1"), "\\001\\303\\2511");
+        assert_eq!(escape_c_bytes(b"caf\xe9.mp4"), "caf\\351.mp4");
+    }
+}
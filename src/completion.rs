@@ -0,0 +1,442 @@
+//! Completion scripts for bash, zsh and fish, generated from the option table
+//! and the option groups.
+//!
+//! The names of the filters, which are completed inside filtergraphs, are
+//! taken from the linked libavfilter when the script is generated.
+use log::error;
+
+use crate::{
+    cmdutils::{OptionDef, OptionFlag, OptionGroupDef},
+    options::{FFmpegVersion, GROUPS},
+    show,
+};
+
+/// Stream types of the stream specifiers offered for `OPT_SPEC` options.
+const STREAM_TYPES: [(&str, &str); 5] = [
+    ("v", "video"),
+    ("a", "audio"),
+    ("s", "subtitle"),
+    ("d", "data"),
+    ("t", "attachment"),
+];
+
+/// What the argument of an option is completed with.
+#[derive(Debug, Clone, PartialEq)]
+enum Argument {
+    None,
+    /// Anything, nothing is offered.
+    Any,
+    File,
+    /// Filter names, after the last `,`, `;` or `]`.
+    Filtergraph,
+    Values(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Completion {
+    /// Without the leading `-`.
+    name: String,
+    help: String,
+    argument: Argument,
+}
+
+/// The options, with the stream specifiers of the `OPT_SPEC` ones, the
+/// separators of the groups, and the flags of ffgen itself.
+fn completions(options: &[OptionDef], groups: &[OptionGroupDef]) -> Vec<Completion> {
+    let mut completions = vec![];
+    for po in options.iter() {
+        // The argument of options like `-h` is optional.
+        let argument = if !po.flags.contains(OptionFlag::HAS_ARG) {
+            Argument::None
+        } else {
+            match po.argname {
+                Some("filename") | Some("file") => Argument::File,
+                Some("filter_graph") | Some("graph_description") => Argument::Filtergraph,
+                _ => Argument::Any,
+            }
+        };
+        completions.push(Completion {
+            name: po.name.into(),
+            help: po.help.into(),
+            argument: argument.clone(),
+        });
+        if po.flags.contains(OptionFlag::OPT_SPEC) {
+            for (specifier, stream_type) in STREAM_TYPES.iter() {
+                completions.push(Completion {
+                    name: format!("{}:{}", po.name, specifier),
+                    help: format!("{} ({} streams)", po.help, stream_type),
+                    argument: argument.clone(),
+                });
+            }
+        }
+    }
+    for group in groups.iter() {
+        if let Some(sep) = group.sep {
+            completions.push(Completion {
+                name: sep.into(),
+                help: group.name.into(),
+                argument: Argument::File,
+            });
+        }
+    }
+
+    completions.push(Completion {
+        name: "-ffmpeg-version".into(),
+        help: "generate code for this FFmpeg release".into(),
+        argument: Argument::Values(
+            FFmpegVersion::ALL
+                .iter()
+                .map(|x| x.name().to_owned())
+                .collect(),
+        ),
+    });
+    completions.push(Completion {
+        name: "-codegen".into(),
+        help: "API the generated code creates the filtergraph with".into(),
+        argument: Argument::Values(vec!["legacy".into(), "segment".into()]),
+    });
+    completions.push(Completion {
+        name: "-command".into(),
+        help: "whole ffmpeg command, split with shell quoting".into(),
+        argument: Argument::Any,
+    });
+    completions.push(Completion {
+        name: "-command-file".into(),
+        help: "file with a whole ffmpeg command".into(),
+        argument: Argument::File,
+    });
+    completions
+}
+
+/// Single quoted for bash and zsh.
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r#"'\''"#))
+}
+
+/// Single quoted for fish.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn bash(completions: &[Completion], filters: &[show::Filter]) -> String {
+    let names: Vec<String> = completions
+        .iter()
+        .map(|x| sh_quote(&format!("-{}", x.name)))
+        .collect();
+    let filter_names: Vec<String> = filters.iter().map(|x| sh_quote(&x.name)).collect();
+    let patterns = |argument: &Argument| -> String {
+        let names: Vec<String> = completions
+            .iter()
+            .filter(|x| x.argument == *argument)
+            .map(|x| sh_quote(&format!("-{}", x.name)))
+            .collect();
+        names.join("|")
+    };
+
+    let mut cases = vec![];
+    for (argument, action) in [
+        (
+            Argument::File,
+            r#"compopt -o filenames
+            COMPREPLY=($(compgen -f -- "$cur"))"#,
+        ),
+        (
+            Argument::Filtergraph,
+            r#"local name="${cur##*[,;\]]}"
+            _ffgen_compreply "$cur" "${_ffgen_filters[@]/#/${cur%"$name"}}""#,
+        ),
+        (Argument::Any, "COMPREPLY=()"),
+    ]
+    .iter()
+    {
+        cases.push(format!(
+            "        {})\n            {}\n            return\n            ;;\n",
+            patterns(argument),
+            action
+        ));
+    }
+    for completion in completions.iter() {
+        if let Argument::Values(values) = &completion.argument {
+            let values: Vec<String> = values.iter().map(|x| sh_quote(x)).collect();
+            cases.push(format!(
+                "        {})\n            _ffgen_compreply \"$cur\" {}\n            return\n            ;;\n",
+                sh_quote(&format!("-{}", completion.name)),
+                values.join(" ")
+            ));
+        }
+    }
+
+    format!(
+        r#"# bash completion for ffgen, generated by `ffgen completion bash`.
+
+_ffgen_options=({names})
+_ffgen_filters=({filters})
+
+# Bash only replaces what follows the last word break of the word, e.g. the
+# `:` of `-c:v`.
+_ffgen_compreply() {{
+    local cur="$1" prefix="" word
+    shift
+    if [[ "$cur" == *[$COMP_WORDBREAKS]* ]]; then
+        prefix="${{cur%"${{cur##*[$COMP_WORDBREAKS]}}"}}"
+    fi
+    COMPREPLY=()
+    for word in "$@"; do
+        if [[ "$word" == "$cur"* ]]; then
+            COMPREPLY+=("${{word#"$prefix"}}")
+        fi
+    done
+}}
+
+_ffgen() {{
+    local line="${{COMP_LINE:0:COMP_POINT}}" cur prev words
+    read -ra words <<< "$line"
+    if [[ "$line" == *[[:space:]] ]]; then
+        cur=""
+        prev="${{words[${{#words[@]}}-1]}}"
+    else
+        cur="${{words[${{#words[@]}}-1]}}"
+        prev="${{words[${{#words[@]}}-2]}}"
+    fi
+
+    case "$prev" in
+{cases}    esac
+
+    if [[ "$cur" == -* ]]; then
+        _ffgen_compreply "$cur" "${{_ffgen_options[@]}}"
+    else
+        compopt -o filenames
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+
+complete -F _ffgen ffgen
+"#,
+        names = names.join(" "),
+        filters = filter_names.join(" "),
+        cases = cases.concat()
+    )
+}
+
+fn zsh(completions: &[Completion], filters: &[show::Filter]) -> String {
+    let escape = |s: &str| {
+        s.replace('\\', r"\\")
+            .replace('[', r"\[")
+            .replace(']', r"\]")
+            .replace(':', r"\:")
+    };
+    let filters: Vec<String> = filters
+        .iter()
+        .map(|x| {
+            format!(
+                "        {}\n",
+                sh_quote(&format!("{}:{}", escape(&x.name), x.description))
+            )
+        })
+        .collect();
+    let mut specs = vec![];
+    for completion in completions.iter() {
+        let message = completion.help.replace(':', " ");
+        let argument = match &completion.argument {
+            Argument::None => String::new(),
+            Argument::Any => format!(":{}: ", message),
+            Argument::File => format!(":{}:_files", message),
+            Argument::Filtergraph => format!(":{}:_ffgen_filtergraph", message),
+            Argument::Values(values) => format!(":{}:({})", message, values.join(" ")),
+        };
+        // Every option can be given once per file.
+        specs.push(format!(
+            "        {} \\\n",
+            sh_quote(&format!(
+                "*-{}[{}]{}",
+                escape(&completion.name),
+                escape(&completion.help),
+                argument
+            ))
+        ));
+    }
+
+    format!(
+        r#"#compdef ffgen
+# zsh completion for ffgen, generated by `ffgen completion zsh`.
+
+_ffgen_filtergraph() {{
+    local -a filters
+    filters=(
+{filters}    )
+    compset -P '*[,;\]]'
+    _describe -t filters filter filters -S ''
+}}
+
+_ffgen() {{
+    _arguments \
+{specs}        '*:output url:_files'
+}}
+
+_ffgen "$@"
+"#,
+        filters = filters.concat(),
+        specs = specs.concat()
+    )
+}
+
+fn fish(completions: &[Completion], filters: &[show::Filter]) -> String {
+    let filters: Vec<String> = filters
+        .iter()
+        .map(|x| {
+            format!(
+                " \\\n        {} {}",
+                fish_quote(&x.name),
+                fish_quote(&x.description)
+            )
+        })
+        .collect();
+    let mut lines = vec![];
+    for completion in completions.iter() {
+        // Old style options begin with one `-`, long ones with two.
+        let option = match completion.name.strip_prefix('-') {
+            Some(name) => format!("-l {}", fish_quote(name)),
+            None => format!("-o {}", fish_quote(&completion.name)),
+        };
+        let argument = match &completion.argument {
+            Argument::None => String::new(),
+            Argument::Any => " -x".into(),
+            Argument::File => " -r -F".into(),
+            Argument::Filtergraph => " -x -a '(__ffgen_filtergraph)'".into(),
+            Argument::Values(values) => format!(" -x -a {}", fish_quote(&values.join(" "))),
+        };
+        lines.push(format!(
+            "complete -c ffgen {}{} -d {}\n",
+            option,
+            argument,
+            fish_quote(&completion.help)
+        ));
+    }
+
+    format!(
+        r#"# fish completion for ffgen, generated by `ffgen completion fish`.
+
+function __ffgen_filters
+    printf '%s\t%s\n'{filters}
+end
+
+function __ffgen_filtergraph
+    set -l head (string replace -r '[^,;\]]*$' '' -- (commandline -ct))
+    for filter in (__ffgen_filters)
+        echo $head$filter
+    end
+end
+
+{lines}"#,
+        filters = filters.concat(),
+        lines = lines.concat()
+    )
+}
+
+/// Entry of `ffgen completion <bash|zsh|fish> [<FFmpeg version>]`, which
+/// prints the completion script of the shell for the option table of the
+/// release, the linked one by default.
+pub fn completion(args: &[String]) -> Result<(), ()> {
+    let version = match args.get(1) {
+        Some(version) => version.parse().map_err(|_| {
+            let names: Vec<_> = FFmpegVersion::ALL.iter().map(|x| x.name()).collect();
+            error!(
+                "Unknown FFmpeg version '{}', supported ones are {}.",
+                version,
+                names.join(", ")
+            )
+        })?,
+        None => FFmpegVersion::linked(),
+    };
+    let completions = completions(version.options(), &*GROUPS);
+    let filters = unsafe { show::filters() };
+    let script = match args.first().map(String::as_str) {
+        Some("bash") => bash(&completions, &filters),
+        Some("zsh") => zsh(&completions, &filters),
+        Some("fish") => fish(&completions, &filters),
+        _ => {
+            error!("Usage: ffgen completion <bash|zsh|fish> [<FFmpeg version>]");
+            return Err(());
+        }
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::options::OPTIONS;
+
+    fn find<'a>(completions: &'a [Completion], name: &str) -> Option<&'a Completion> {
+        completions.iter().find(|x| x.name == name)
+    }
+
+    #[test]
+    fn completions_of_options() {
+        let completions = completions(&*OPTIONS, &*GROUPS);
+        let argument = |name| find(&completions, name).map(|x| x.argument.clone());
+        assert_eq!(argument("y"), Some(Argument::None));
+        assert_eq!(argument("i"), Some(Argument::File));
+        assert_eq!(argument("vf"), Some(Argument::Filtergraph));
+        assert_eq!(argument("filter_complex"), Some(Argument::Filtergraph));
+        assert_eq!(argument("filter_script:a"), Some(Argument::File));
+        assert_eq!(argument("c:v"), Some(Argument::Any));
+        assert_eq!(
+            find(&completions, "c:a").map(|x| x.help.as_str()),
+            Some("codec name (audio streams)")
+        );
+        // Only `OPT_SPEC` options get stream specifiers.
+        assert_eq!(argument("vf:v"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bash_script() {
+        use std::{
+            io::Write,
+            process::{Command, Stdio},
+        };
+
+        let script = bash(&completions(&*OPTIONS, &*GROUPS), &[]);
+        let mut child = Command::new("bash")
+            .arg("-n")
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        // The patterns and the first line of the action of the case arm of
+        // `option`.
+        let arm = |option: &str| {
+            let mut lines = script.lines();
+            while let Some(line) = lines.next() {
+                if let Some(patterns) = line.trim().strip_suffix(')') {
+                    if patterns.split('|').any(|x| x == option) {
+                        let patterns: Vec<&str> = patterns.split('|').collect();
+                        return Some((patterns, lines.next()?.trim()));
+                    }
+                }
+            }
+            None
+        };
+        let (patterns, action) = arm("'-i'").unwrap();
+        assert!(patterns.contains(&"'-filter_script:a'"));
+        assert_eq!(action, "compopt -o filenames");
+        let (patterns, action) = arm("'-vf'").unwrap();
+        assert!(patterns.contains(&"'-filter_complex'"));
+        assert_eq!(action, r#"local name="${cur##*[,;\]]}""#);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(sh_quote("it's"), r#"'it'\''s'"#);
+        assert_eq!(fish_quote(r"it's \o/"), r"'it\'s \\o/'");
+    }
+}
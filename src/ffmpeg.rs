@@ -5,14 +5,18 @@ use rusty_ffmpeg::{avutil::avutils::*, ffi};
 
 use std::{
     env,
-    ffi::{CStr, CString},
-    ptr,
+    ffi::{CStr, CString, OsString},
+    fs, ptr,
     sync::Mutex,
 };
 
 use crate::{
-    cmdutils::{OptionGroup, SpecifierOpt},
+    cmdutils::{os_bytes, OptionGroup, SpecifierOpt},
     ffmpeg_opt,
+    graph_optimizer::OptimizePass,
+    logging,
+    options::FFmpegVersion,
+    shell,
 };
 
 use ffmpeg_opt::ffmpeg_parse_options;
@@ -74,7 +78,9 @@ pub struct StreamMap {
     pub linklabel: String,
 }
 
-#[derive(Debug, Default)]
+/// One `-map_channel`, with -1 for what is not given and for the input of a
+/// muted channel.
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct AudioChannelMap {
     // input
     pub file_idx: isize,
@@ -93,7 +99,7 @@ pub struct OptionsContext<'a, 'group> {
     pub start_time: i64,
     pub start_time_eof: i64,
     pub seek_timestamp: isize,
-    pub format: String,
+    pub format: *mut libc::c_char,
 
     pub codec_names: Vec<SpecifierOpt>,
     pub audio_channels: Vec<SpecifierOpt>,
@@ -106,6 +112,7 @@ pub struct OptionsContext<'a, 'group> {
     pub input_ts_offset: i64,
     pub loops: isize,
     pub rate_emu: isize,
+    pub readrate: f32,
     pub accurate_seek: isize,
     pub thread_queue_size: isize,
 
@@ -115,6 +122,9 @@ pub struct OptionsContext<'a, 'group> {
     pub hwaccel_devices: Vec<SpecifierOpt>,
     pub hwaccel_output_formats: Vec<SpecifierOpt>,
     pub autorotate: Vec<SpecifierOpt>,
+    pub display_rotations: Vec<SpecifierOpt>,
+    pub display_hflips: Vec<SpecifierOpt>,
+    pub display_vflips: Vec<SpecifierOpt>,
 
     // output options
     pub stream_maps: Vec<StreamMap>,
@@ -127,6 +137,7 @@ pub struct OptionsContext<'a, 'group> {
     pub metadata_streams_manual: isize,
     pub metadata_chapters_manual: isize,
     pub attachments: Vec<String>,
+    pub stream_groups: Vec<String>,
 
     pub chapters_input_file: isize,
 
@@ -136,6 +147,7 @@ pub struct OptionsContext<'a, 'group> {
     pub mux_preload: f32,
     pub mux_max_delay: f32,
     pub shortest: isize,
+    pub shortest_buf_duration: f32,
     pub bitexact: isize,
 
     pub video_disable: isize,
@@ -179,6 +191,16 @@ pub struct OptionsContext<'a, 'group> {
     pub program: Vec<SpecifierOpt>,
     pub time_bases: Vec<SpecifierOpt>,
     pub enc_time_bases: Vec<SpecifierOpt>,
+    pub autoscale: Vec<SpecifierOpt>,
+    pub max_frame_rates: Vec<SpecifierOpt>,
+    pub fps_mode: Vec<SpecifierOpt>,
+    pub fix_sub_duration_heartbeat: Vec<SpecifierOpt>,
+    pub enc_stats_pre: Vec<SpecifierOpt>,
+    pub enc_stats_post: Vec<SpecifierOpt>,
+    pub mux_stats: Vec<SpecifierOpt>,
+    pub enc_stats_pre_fmt: Vec<SpecifierOpt>,
+    pub enc_stats_post_fmt: Vec<SpecifierOpt>,
+    pub mux_stats_fmt: Vec<SpecifierOpt>,
 }
 
 impl<'a, 'group> OptionsContext<'a, 'group> {
@@ -198,7 +220,7 @@ impl<'a, 'group> OptionsContext<'a, 'group> {
 
             // input/output options
             seek_timestamp: 0,
-            format: Default::default(),
+            format: ptr::null_mut(),
 
             codec_names: vec![],
             audio_channels: vec![],
@@ -211,6 +233,7 @@ impl<'a, 'group> OptionsContext<'a, 'group> {
             input_ts_offset: 0,
             loops: 0,
             rate_emu: 0,
+            readrate: 0.,
             thread_queue_size: 0,
 
             ts_scale: vec![],
@@ -219,6 +242,9 @@ impl<'a, 'group> OptionsContext<'a, 'group> {
             hwaccel_devices: vec![],
             hwaccel_output_formats: vec![],
             autorotate: vec![],
+            display_rotations: vec![],
+            display_hflips: vec![],
+            display_vflips: vec![],
 
             // output options
             stream_maps: vec![],
@@ -227,9 +253,11 @@ impl<'a, 'group> OptionsContext<'a, 'group> {
             metadata_streams_manual: 0,
             metadata_chapters_manual: 0,
             attachments: vec![],
+            stream_groups: vec![],
 
             mux_preload: 0.,
             shortest: 0,
+            shortest_buf_duration: 10.,
             bitexact: 0,
 
             video_disable: 0,
@@ -273,13 +301,133 @@ impl<'a, 'group> OptionsContext<'a, 'group> {
             program: vec![],
             time_bases: vec![],
             enc_time_bases: vec![],
+            autoscale: vec![],
+            max_frame_rates: vec![],
+            fps_mode: vec![],
+            fix_sub_duration_heartbeat: vec![],
+            enc_stats_pre: vec![],
+            enc_stats_post: vec![],
+            mux_stats: vec![],
+            enc_stats_pre_fmt: vec![],
+            enc_stats_post_fmt: vec![],
+            mux_stats_fmt: vec![],
+        }
+    }
+}
+
+/// API the generated code creates the filtergraph with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodegenTarget {
+    /// `avfilter_graph_alloc_filter()`, `avfilter_init_str()` and
+    /// `avfilter_link()`, like `avfilter_graph_parse2()` does.
+    Legacy,
+    /// The graph segment API of FFmpeg 6.0 and later.
+    Segment,
+}
+
+/// Flags of ffgen, each followed by its value.
+const FLAGS: [&str; 5] = [
+    "--ffmpeg-version",
+    "--codegen",
+    "--optimize",
+    "--command",
+    "--command-file",
+];
+
+/// Settings of ffgen itself, given with `--` flags before the ffmpeg
+/// arguments.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Release whose options are accepted, the linked one by default.
+    pub version: FFmpegVersion,
+    pub target: CodegenTarget,
+    /// Passes applied to the filtergraph before the code is generated.
+    pub optimize: OptimizePass,
+}
+
+impl Settings {
+    /// Take the `--ffmpeg-version <version>`, `--codegen <target>` and
+    /// `--optimize <passes>` flags out of the args. A whole ffmpeg command
+    /// given with `--command <command>` or `--command-file <path>` is split
+    /// into the args.
+    fn from_args(args: &mut Vec<OsString>) -> Result<Self, ()> {
+        let mut version = None;
+        let mut target = CodegenTarget::Legacy;
+        let mut optimize = OptimizePass::NONE;
+        let mut command = None;
+        while let Some(flag) = args
+            .get(1)
+            .and_then(|x| x.to_str())
+            .filter(|x| FLAGS.contains(x))
+            .map(String::from)
+        {
+            let raw_value = args.get(2).cloned().unwrap_or_default();
+            let value = raw_value.to_string_lossy().into_owned();
+            args.drain(1..args.len().min(3));
+            if flag == "--command" {
+                command = Some(os_bytes(&raw_value).into_owned());
+            } else if flag == "--command-file" {
+                command =
+                    Some(fs::read(&raw_value).map_err(|e| {
+                        error!("Failed to read the command from '{}': {}", value, e)
+                    })?);
+            } else if flag == "--ffmpeg-version" {
+                version = Some(value.parse().map_err(|_| {
+                    let names: Vec<_> = FFmpegVersion::ALL.iter().map(|x| x.name()).collect();
+                    error!(
+                        "Unknown FFmpeg version '{}', supported ones are {}.",
+                        value,
+                        names.join(", ")
+                    )
+                })?);
+            } else if flag == "--optimize" {
+                optimize = OptimizePass::parse(&value).map_err(|e| error!("{}", e))?;
+            } else {
+                target = match value.as_str() {
+                    "legacy" => CodegenTarget::Legacy,
+                    "segment" => CodegenTarget::Segment,
+                    _ => {
+                        error!(
+                            "Unknown code generation target '{}', supported ones are legacy, segment.",
+                            value
+                        );
+                        return Err(());
+                    }
+                };
+            }
+        }
+
+        if let Some(command) = command {
+            if args.len() > 1 {
+                error!("No ffmpeg arguments can follow --command or --command-file.");
+                return Err(());
+            }
+            args.extend(shell::command_args(&command).map_err(|e| error!("{}", e))?);
         }
+
+        let version = version.unwrap_or_else(FFmpegVersion::linked);
+        if target == CodegenTarget::Segment && version < FFmpegVersion::V6_0 {
+            error!(
+                "The graph segment API needs FFmpeg 6.0 or later, not {}.",
+                version.name()
+            );
+            return Err(());
+        }
+        Ok(Settings {
+            version,
+            target,
+            optimize,
+        })
     }
 }
 
-pub fn ffmpeg() {
-    // TODO: May need to change to Vec<u8> for non-UTF8 args.
-    let args: Vec<String> = env::args().collect();
+pub fn ffmpeg() -> Result<(), ()> {
+    // File names are not always UTF-8.
+    let mut args: Vec<OsString> = env::args_os().collect();
+
+    let settings = Settings::from_args(&mut args)?;
+    logging::parse_loglevel(&args, settings.version.options())?;
+    debug!("Parsing the options of FFmpeg {}.", settings.version.name());
 
-    ffmpeg_parse_options(&args);
+    ffmpeg_parse_options(&args, &settings)
 }
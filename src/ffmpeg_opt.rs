@@ -1,23 +1,36 @@
-use libc::c_void;
-use log::{debug, error, info};
+use libc::{c_char, c_int, c_void};
+use log::{debug, error, info, warn};
 use rusty_ffmpeg::{
     avutil::{avutils::*, error::*},
     ffi,
 };
 use std::{
-    ffi::{CStr, CString},
+    ffi::{CStr, CString, OsStr, OsString},
+    path::Path,
     ptr, slice,
 };
 
 use crate::{
     cmdutils::{
         // need to remove the directly imported functions
+        escape_c_bytes,
+        escape_c_string,
         init_parse_context,
+        os_bytes,
+        parse_number,
         split_commandline,
         uninit_parse_context,
+        OptionDef,
+        OptionFlag,
+        OptionGroup,
+        OptionKV,
+        OptionParseContext,
+        SpecifierOpt,
     },
-    ffmpeg::{self, OptionsContext, INT_CB},
-    graph_parser::avfilter_graph_parse2,
+    ffmpeg::{self, AudioChannelMap, CodegenTarget, OptionsContext, Settings, INT_CB},
+    graph_optimizer::optimize,
+    graph_parser::{graph_serialization, load_file_options, parse_graph},
+    graph_segment::segment_serialization,
     options::*,
 };
 
@@ -26,17 +39,561 @@ enum OptGroup {
     GroupInFile = 1,
 }
 
-pub fn ffmpeg_parse_options(args: &[String]) {
+/// Free the strings `write_option()` stored in an OptionsContext, which are
+/// all found through the `OPT_SPEC` and `OPT_STRING` options as in FFmpeg.
+pub fn uninit_options(o: &mut OptionsContext, options: &[OptionDef]) {
+    for po in options.iter() {
+        if !po
+            .flags
+            .intersects(OptionFlag::OPT_OFFSET | OptionFlag::OPT_SPEC)
+        {
+            continue;
+        }
+        let dst = unsafe { (o as *mut OptionsContext as *mut u8).add(po.u.off) };
+        if po.flags.contains(OptionFlag::OPT_SPEC) {
+            let so = unsafe { &mut *(dst as *mut Vec<SpecifierOpt>) };
+            if po.flags.contains(OptionFlag::OPT_STRING) {
+                for x in so.iter().filter(|x| !unsafe { x.u.str }.is_null()) {
+                    drop(unsafe { CString::from_raw(x.u.str as *mut c_char) });
+                }
+            }
+            so.clear();
+        } else if po.flags.contains(OptionFlag::OPT_STRING) {
+            let dst = unsafe { &mut *(dst as *mut *mut c_char) };
+            if !dst.is_null() {
+                drop(unsafe { CString::from_raw(*dst) });
+            }
+            *dst = ptr::null_mut();
+        }
+    }
+}
+
+/// The `pan` filter FFmpeg puts at the end of the audio filters of an output
+/// stream for its `-map_channel`s, in `configure_output_audio_filter()`: the
+/// default layout of as many channels as maps, as a mask.
+fn pan_filter(maps: &[AudioChannelMap]) -> String {
+    let layout = unsafe { ffi::av_get_default_channel_layout(maps.len() as c_int) } as u64;
+    let mut pan = format!("pan=0x{:x}", layout);
+    for (i, m) in maps.iter().enumerate() {
+        if m.channel_idx != -1 {
+            pan.push_str(&format!("|c{}=c{}", i, m.channel_idx));
+        }
+    }
+    pan
+}
+
+/// The argument of an option taking UTF-8, which `write_option()` checks the
+/// same way for the options it sets.
+fn utf8_arg<'a>(o: &'a OptionKV) -> Result<&'a str, ()> {
+    o.val.to_str().ok_or_else(|| {
+        error!(
+            "Failed to set value '{}' for option '{}': not valid UTF-8",
+            o.val.to_string_lossy(),
+            o.key
+        )
+    })
+}
+
+/// Append the `pan` filter of the `-map_channel`s of an output file to its
+/// `-af`, or make it the filtergraph when there is none. The input and output
+/// streams are not known before the files are opened, so the maps of an output
+/// file have to be of one input stream into one output stream.
+fn channel_map_filter(
+    octx: &OptionParseContext,
+    filtergraph: &mut Option<OsString>,
+) -> Result<(), ()> {
+    let nb_input_files = octx.groups[OptGroup::GroupInFile as usize].groups.len() as isize;
+    let outputs = &octx.groups[OptGroup::GroupOutFile as usize].groups;
+    for (ofile_idx, group) in outputs.iter().enumerate() {
+        let mut maps = vec![];
+        for o in group.opts.iter().filter(|o| o.key == "map_channel") {
+            let m = parse_map_channel(utf8_arg(o)?).map_err(|e| error!("{}", e))?;
+            if m.file_idx >= nb_input_files {
+                error!("mapchan: invalid input file index: {}", m.file_idx);
+                return Err(());
+            }
+            if m.ofile_idx == -1 || m.ofile_idx == ofile_idx as isize {
+                maps.push(m);
+            }
+        }
+        if maps.is_empty() {
+            continue;
+        }
+
+        let mut istreams: Vec<_> = maps
+            .iter()
+            .filter(|m| m.channel_idx != -1)
+            .map(|m| (m.file_idx, m.stream_idx))
+            .collect();
+        let mut ostreams: Vec<_> = maps
+            .iter()
+            .map(|m| m.ostream_idx)
+            .filter(|&x| x != -1)
+            .collect();
+        istreams.sort_unstable();
+        istreams.dedup();
+        ostreams.sort_unstable();
+        ostreams.dedup();
+        if istreams.len() > 1 || ostreams.len() > 1 {
+            error!(
+                "The -map_channel options of output file #{} use more than one stream, \
+                 which no code can be generated for.",
+                ofile_idx
+            );
+            return Err(());
+        }
+
+        let pan = pan_filter(&maps);
+        debug!("-map_channel of output file #{} as '{}'.", ofile_idx, pan);
+        let af = group
+            .opts
+            .iter()
+            .rev()
+            .find(|o| o.key == "filter:a")
+            .map(|o| o.val.as_os_str());
+        match (af, filtergraph.as_mut()) {
+            (None, None) => *filtergraph = Some(pan.into()),
+            (Some(af), Some(graph)) if af == graph.as_os_str() => {
+                graph.push(",");
+                graph.push(pan);
+            }
+            _ => warn!(
+                "The '{}' of the -map_channel of output file #{} is left out, \
+                 the code is generated for another filtergraph.",
+                pan, ofile_idx
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// The MIME type of an attachment by its extension. Matroska needs one for
+/// every attachment, these are the ones it knows.
+fn attachment_mimetype(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mimetype = match extension.as_str() {
+        "ttf" | "ttc" => "application/x-truetype-font",
+        "otf" => "application/vnd.ms-opentype",
+        "txt" => "text/plain",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "tif" | "tiff" => "image/tiff",
+        _ => return None,
+    };
+    Some(mimetype)
+}
+
+/// Code dumping the attachments of the input files, from `open_input_file()`,
+/// and creating the attachment streams of the output files, from
+/// `open_output_file()`. The attached files are read then, so they have to
+/// exist now.
+fn attachments(octx: &OptionParseContext) -> Result<(), ()> {
+    let inputs = &octx.groups[OptGroup::GroupInFile as usize].groups;
+    for (i, group) in inputs.iter().enumerate() {
+        for o in group
+            .opts
+            .iter()
+            .filter(|o| o.opt.name == "dump_attachment")
+        {
+            let specifier = o.key.find(':').map_or("", |n| &o.key[n + 1..]);
+            println!(
+                r#"
+/* -{key} of input file #{i} */
+for (i = 0; i < ic->nb_streams; i++) {{
+    if (check_stream_specifier(ic, ic->streams[i], "{specifier}") == 1)
+        dump_attachment(ic->streams[i], "{filename}");
+}}"#,
+                key = o.key,
+                i = i,
+                specifier = escape_c_string(specifier),
+                filename = escape_c_bytes(&os_bytes(&o.val))
+            );
+        }
+    }
+
+    let outputs = &octx.groups[OptGroup::GroupOutFile as usize].groups;
+    for (i, group) in outputs.iter().enumerate() {
+        for o in group.opts.iter().filter(|o| o.key == "attach") {
+            let path = Path::new(&o.val);
+            if !path.is_file() {
+                error!("Could not open attachment file {}.", path.display());
+                return Err(());
+            }
+            // FFmpeg names it after what follows the last `/`.
+            let filename = path.file_name().unwrap_or(o.val.as_os_str());
+            let mimetype = match attachment_mimetype(path) {
+                Some(mimetype) => format!(
+                    "\nav_dict_set(&ost->st->metadata, \"mimetype\", \"{}\", AV_DICT_DONT_OVERWRITE);",
+                    mimetype
+                ),
+                None => {
+                    warn!(
+                        "No MIME type is known for attachment {}, set it with \
+                         -metadata:s:t mimetype=<type> for Matroska.",
+                        path.display()
+                    );
+                    String::new()
+                }
+            };
+            println!(
+                r#"
+/* -attach of output file #{i} */
+if ((err = avio_open2(&pb, "{path}", AVIO_FLAG_READ, &int_cb, NULL)) < 0) {{
+    av_log(NULL, AV_LOG_FATAL, "Could not open attachment file %s.\n", "{path}");
+    exit_program(1);
+}}
+if ((len = avio_size(pb)) <= 0) {{
+    av_log(NULL, AV_LOG_FATAL, "Could not get size of the attachment %s.\n", "{path}");
+    exit_program(1);
+}}
+if (len > INT_MAX - AV_INPUT_BUFFER_PADDING_SIZE ||
+    !(attachment = av_malloc(len + AV_INPUT_BUFFER_PADDING_SIZE))) {{
+    av_log(NULL, AV_LOG_FATAL, "Attachment %s too large.\n", "{path}");
+    exit_program(1);
+}}
+avio_read(pb, attachment, len);
+memset(attachment + len, 0, AV_INPUT_BUFFER_PADDING_SIZE);
+
+ost = new_attachment_stream(o, oc, -1);
+ost->stream_copy = 0;
+ost->attachment_filename = "{path}";
+ost->st->codecpar->extradata = attachment;
+ost->st->codecpar->extradata_size = len;
+av_dict_set(&ost->st->metadata, "filename", "{filename}", AV_DICT_DONT_OVERWRITE);{mimetype}
+avio_closep(&pb);"#,
+                i = i,
+                path = escape_c_bytes(&os_bytes(&o.val)),
+                filename = escape_c_bytes(&os_bytes(filename)),
+                mimetype = mimetype
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The `aformat` arguments `configure_output_audio_filter()` puts before the
+/// `abuffersink` of the audio of an output file for its `-sample_fmt`, `-ar`,
+/// `-ac` and `-channel_layout`, with the trailing `:` FFmpeg leaves. `None`
+/// without any of them.
+fn aformat_args(group: &OptionGroup) -> Result<Option<String>, ()> {
+    let (mut sample_fmt, mut sample_rate, mut channels, mut layout) = (None, None, None, None);
+    for o in group.opts.iter() {
+        match o.opt.name {
+            "ar" | "ac" | "sample_fmt" | "channel_layout" => {}
+            _ => continue,
+        }
+        let specifier = o.key.find(':').map_or("", |n| &o.key[n + 1..]);
+        if !specifier.is_empty() && specifier != "a" {
+            warn!(
+                "-{} is left out of the audio output format, which is only \
+                 generated for the options of all audio streams.",
+                o.key
+            );
+            continue;
+        }
+        let arg = utf8_arg(o)?;
+        let number = || {
+            parse_number(&o.key, arg, OptionFlag::OPT_INT, 1., c_int::MAX as f64)
+                .map(|x| x as c_int)
+                .map_err(|e| error!("{}", e))
+        };
+        match o.opt.name {
+            "ar" => sample_rate = Some(number()?),
+            "ac" => {
+                let n = number()?;
+                // A layout of another number of channels is left for the
+                // default one.
+                if let Some((_, layout_channels)) = layout {
+                    if layout_channels != n {
+                        layout = None;
+                    }
+                }
+                channels = Some(n);
+            }
+            "sample_fmt" => {
+                parse_sample_fmt(arg).map_err(|e| error!("{}", e))?;
+                sample_fmt = Some(arg.to_owned());
+            }
+            _ => {
+                let (mask, n) = parse_channel_layout(arg).map_err(|e| error!("{}", e))?;
+                layout = Some((mask, n));
+                channels = Some(n);
+            }
+        }
+    }
+    let layout = match (layout, channels) {
+        (Some((mask, _)), _) => Some(mask),
+        (None, Some(n)) => Some(unsafe { ffi::av_get_default_channel_layout(n) } as u64),
+        (None, None) => None,
+    };
+
+    let mut args = String::new();
+    if let Some(sample_fmt) = sample_fmt {
+        args.push_str(&format!("sample_fmts={}:", sample_fmt));
+    }
+    if let Some(sample_rate) = sample_rate {
+        args.push_str(&format!("sample_rates={}:", sample_rate));
+    }
+    if let Some(layout) = layout.filter(|&x| x != 0) {
+        args.push_str(&format!("channel_layouts=0x{:x}:", layout));
+    }
+    Ok(Some(args).filter(|x| !x.is_empty()))
+}
+
+/// The output file whose audio the filtergraph is generated for, and the
+/// `aformat` arguments of its audio options. That is the output whose `-af`
+/// is the filtergraph, or without filtergraph, the first one with audio
+/// options, whose audio is filtered by `anull` then.
+fn audio_output_format(
+    octx: &OptionParseContext,
+    filtergraph: Option<&OsStr>,
+) -> Result<Option<(usize, String)>, ()> {
+    let mut format = None;
+    let outputs = &octx.groups[OptGroup::GroupOutFile as usize].groups;
+    for (ofile_idx, group) in outputs.iter().enumerate() {
+        let args = match aformat_args(group)? {
+            Some(args) => args,
+            None => continue,
+        };
+        let af = group
+            .opts
+            .iter()
+            .rev()
+            .find(|o| o.key == "filter:a")
+            .map(|o| o.val.as_os_str());
+        let generated = match (filtergraph, af) {
+            (None, None) => true,
+            (Some(graph), Some(af)) => graph == af,
+            _ => false,
+        };
+        if generated && format.is_none() {
+            debug!(
+                "Audio output format of output file #{} as 'aformat={}'.",
+                ofile_idx, args
+            );
+            format = Some((ofile_idx, args));
+        } else {
+            warn!(
+                "The audio output format of output file #{} is left out, \
+                 the code is generated for another filtergraph.",
+                ofile_idx
+            );
+        }
+    }
+    Ok(format)
+}
+
+/// The `abuffersink` and `aformat` of `configure_output_audio_filter()`,
+/// linked to the first output of the parsed filtergraph. The legacy code
+/// creates the filters in `ctx` and returns on failure, the graph segment code
+/// in `graph` and frees the segment at `end` on failure.
+fn audio_sink_code(ofile_idx: usize, args: &str, target: CodegenTarget) -> String {
+    let (graph, fail, ret) = match target {
+        CodegenTarget::Legacy => ("ctx", "return ret;", "int ret;\n"),
+        CodegenTarget::Segment => ("graph", "goto end;", ""),
+    };
+    format!(
+        r#"
+/* The audio output of output file #{i}, as configure_output_audio_filter()
+ * does, with the outputs the graph was parsed into. */
+{{
+{ret}AVFilterContext *last_filter = (*outputs)->filt_ctx;
+int pad_idx = (*outputs)->pad_idx;
+AVFilterContext *out_{i}_a, *format_out_{i}_a;
+if ((ret = avfilter_graph_create_filter(&out_{i}_a, avfilter_get_by_name("abuffersink"),
+                                        "out_{i}_a", NULL, NULL, {graph})) < 0)
+    {fail}
+if ((ret = av_opt_set_int(out_{i}_a, "all_channel_counts", 1, AV_OPT_SEARCH_CHILDREN)) < 0)
+    {fail}
+if ((ret = avfilter_graph_create_filter(&format_out_{i}_a, avfilter_get_by_name("aformat"),
+                                        "format_out_{i}_a", "{args}", NULL, {graph})) < 0)
+    {fail}
+if ((ret = avfilter_link(last_filter, pad_idx, format_out_{i}_a, 0)) < 0)
+    {fail}
+if ((ret = avfilter_link(format_out_{i}_a, 0, out_{i}_a, 0)) < 0)
+    {fail}
+}}"#,
+        i = ofile_idx,
+        ret = ret,
+        graph = graph,
+        fail = fail,
+        args = escape_c_string(args)
+    )
+}
+
+/// Run the function of a global option, as `parse_optgroup()` does.
+fn run_global_option(o: &OptionKV) -> Result<(), ()> {
+    let arg = utf8_arg(o)?;
+    let ret = unsafe { o.opt.u.func_arg }(ptr::null_mut(), &o.key, arg);
+    if ret < 0 {
+        error!("Failed to set value '{}' for option '{}'.", arg, o.key);
+        return Err(());
+    }
+    Ok(())
+}
+
+/// `-timelimit`, `-max_alloc` and `-cpuflags`: checked as their options do,
+/// and generated as the calls the options make, so the generated program runs
+/// with the limits. They are not applied to ffgen itself, where e.g. a small
+/// `-max_alloc` would break parsing the filtergraph.
+fn global_limits(octx: &OptionParseContext) -> Result<(), ()> {
+    for o in octx.global_opts.opts.iter() {
+        match o.opt.name {
+            "timelimit" | "max_alloc" | "cpuflags" => {}
+            _ => continue,
+        }
+        let arg = utf8_arg(o)?;
+        let invalid = |e: String| {
+            error!("{}", e);
+            error!("Failed to set value '{}' for option '{}'.", arg, o.key);
+        };
+        match o.opt.name {
+            "timelimit" => println!(
+                r#"
+/* -{key} */
+#if HAVE_SETRLIMIT
+{{
+    struct rlimit rl = {{ {lim}, {lim} + 1 }};
+    if (setrlimit(RLIMIT_CPU, &rl))
+        perror("setrlimit");
+}}
+#else
+av_log(NULL, AV_LOG_WARNING, "-%s not implemented on this OS\n", "{key}");
+#endif"#,
+                key = o.key,
+                lim = parse_timelimit(arg).map_err(invalid)?
+            ),
+            "max_alloc" => println!(
+                "\n/* -{} */\nav_max_alloc({});",
+                o.key,
+                parse_max_alloc(arg).map_err(invalid)?
+            ),
+            _ => {
+                parse_cpuflags(arg).map_err(invalid)?;
+                println!(
+                    r#"
+/* -{key} */
+{{
+    unsigned flags = av_get_cpu_flags();
+    if (av_parse_cpu_caps(&flags, "{arg}") < 0)
+        exit_program(1);
+    av_force_cpu_flags(flags);
+}}"#,
+                    key = o.key,
+                    arg = escape_c_string(arg)
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `-vsync`, `-abort_on` and `-stats_period`, kept in `GLOBAL_OPTIONS`, and
+/// generated as the globals of ffmpeg_opt.c the transcoder reads.
+fn transcoder_globals(octx: &OptionParseContext) -> Result<(), ()> {
+    let (mut vsync, mut abort_on, mut stats_period) = (false, false, false);
+    for o in octx.global_opts.opts.iter() {
+        match o.opt.name {
+            "vsync" => vsync = true,
+            "abort_on" => abort_on = true,
+            "stats_period" => stats_period = true,
+            _ => continue,
+        }
+        run_global_option(o)?;
+    }
+    let global = GLOBAL_OPTIONS.lock().unwrap();
+    if vsync {
+        println!(
+            "\n/* -vsync */\nvideo_sync_method = {};",
+            global.video_sync_method.c_name()
+        );
+    }
+    if abort_on {
+        println!(
+            "\n/* -abort_on */\nabort_on_flags = {};",
+            global.abort_on_flags.c_expr()
+        );
+    }
+    if stats_period {
+        println!(
+            "\n/* -stats_period */\nstats_period = {};",
+            global.stats_period
+        );
+    }
+    Ok(())
+}
+
+pub fn ffmpeg_parse_options(args: &[OsString], settings: &Settings) -> Result<(), ()> {
     let mut octx = init_parse_context(&*GROUPS);
 
     let mut filtergraph = None;
 
-    split_commandline(&mut octx, &args, &*OPTIONS, &*GROUPS, &mut filtergraph)
-        .expect("split_commandline() failed!");
+    unsafe { option_table = Some(settings.version.options()) };
+    split_commandline(
+        &mut octx,
+        &args,
+        settings.version.options(),
+        &*GROUPS,
+        &mut filtergraph,
+    )
+    .map_err(|_| uninit_parse_context(&mut octx))?;
     // println!("{:#?}", octx);
 
+    // Options like `-h` or `-formats` print something instead of generating
+    // code, FFmpeg exits after them in `parse_optgroup()`.
+    let exit_option = octx
+        .global_opts
+        .opts
+        .iter()
+        .find(|o| o.opt.flags.contains(OptionFlag::OPT_EXIT))
+        .map(|o| {
+            let val = utf8_arg(o)?.to_owned();
+            Ok((unsafe { o.opt.u.func_arg }, o.key.clone(), val))
+        })
+        .transpose()
+        .map_err(|()| uninit_parse_context(&mut octx))?;
+    if let Some((func, key, val)) = exit_option {
+        let ret = func(ptr::null_mut(), &key, &val);
+        uninit_parse_context(&mut octx);
+        if ret < 0 {
+            error!("Failed to run option '{}' with argument '{}'.", key, val);
+            return Err(());
+        }
+        return Ok(());
+    }
+
+    global_limits(&octx).map_err(|_| uninit_parse_context(&mut octx))?;
+    transcoder_globals(&octx).map_err(|_| uninit_parse_context(&mut octx))?;
+    let audio_format = audio_output_format(&octx, filtergraph.as_deref())
+        .map_err(|_| uninit_parse_context(&mut octx))?;
+    channel_map_filter(&octx, &mut filtergraph).map_err(|_| uninit_parse_context(&mut octx))?;
+    if audio_format.is_some() && filtergraph.is_none() {
+        filtergraph = Some("anull".into());
+    }
+    attachments(&octx).map_err(|_| uninit_parse_context(&mut octx))?;
+
+    let sink =
+        audio_format.map(|(ofile_idx, args)| audio_sink_code(ofile_idx, &args, settings.target));
     if let Some(filtergraph) = filtergraph {
-        avfilter_graph_parse2(&filtergraph).unwrap();
+        let filtergraph = os_bytes(&filtergraph);
+        let mut graph = parse_graph(&*filtergraph).map_err(|_| uninit_parse_context(&mut octx))?;
+        load_file_options(&mut graph).map_err(|_| uninit_parse_context(&mut octx))?;
+        for change in optimize(&mut graph, settings.optimize) {
+            info!("{}", change);
+        }
+        match settings.target {
+            CodegenTarget::Legacy => {
+                graph_serialization(&graph);
+                if let Some(sink) = sink {
+                    println!("{}", sink);
+                }
+            }
+            CodegenTarget::Segment => {
+                segment_serialization(&graph, sink.as_deref().unwrap_or_default())
+                    .map_err(|_| uninit_parse_context(&mut octx))?
+            }
+        }
     }
 
     /*
@@ -62,4 +619,221 @@ pub fn ffmpeg_parse_options(args: &[String]) {
     */
 
     uninit_parse_context(&mut octx);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Split the commandline, then run `f` on its context and on the captured
+    /// filtergraph.
+    fn with_split<T>(
+        args: &[&str],
+        f: impl FnOnce(&OptionParseContext, &mut Option<OsString>) -> T,
+    ) -> T {
+        let args: Vec<OsString> = args.iter().map(|&x| x.into()).collect();
+        let mut octx = init_parse_context(&*GROUPS);
+        let mut filtergraph = None;
+        split_commandline(&mut octx, &args, &*OPTIONS, &*GROUPS, &mut filtergraph).unwrap();
+        let ret = f(&octx, &mut filtergraph);
+        uninit_parse_context(&mut octx);
+        ret
+    }
+
+    #[test]
+    fn attachment_files() {
+        let mimetype = |x: &str| attachment_mimetype(Path::new(x));
+        assert_eq!(
+            mimetype("fonts/Arial.TTF"),
+            Some("application/x-truetype-font")
+        );
+        assert_eq!(mimetype("cover.jpeg"), Some("image/jpeg"));
+        assert_eq!(mimetype("font.woff2"), None);
+        assert_eq!(mimetype("README"), None);
+
+        let attach = |file: &str| {
+            with_split(
+                &["ffmpeg", "-i", "in.mkv", "-attach", file, "out.mkv"],
+                |octx, _| attachments(octx),
+            )
+        };
+        let dir = TempDir::new("attachment");
+        let font = dir.join("font.ttf");
+        std::fs::write(&font, b"\0\x01\0\0").unwrap();
+        assert!(attach(font.to_str().unwrap()).is_ok());
+        assert!(attach("/nonexistent/font.ttf").is_err());
+    }
+
+    #[test]
+    fn audio_output_formats() {
+        let format = |args: &[&str], filtergraph: Option<&str>| {
+            with_split(args, |octx, _| {
+                audio_output_format(octx, filtergraph.map(OsStr::new))
+            })
+        };
+        assert_eq!(
+            format(
+                &["ffmpeg", "-i", "in.wav", "-ar", "48000", "-ac", "2", "out.wav"],
+                None
+            ),
+            Ok(Some((0, "sample_rates=48000:channel_layouts=0x3:".into())))
+        );
+        // The layout sets the number of channels, another number the default
+        // layout.
+        assert_eq!(
+            format(
+                &[
+                    "ffmpeg",
+                    "-i",
+                    "in.wav",
+                    "-channel_layout",
+                    "5.1",
+                    "-ac",
+                    "1",
+                    "out.wav"
+                ],
+                None
+            ),
+            Ok(Some((0, "channel_layouts=0x4:".into())))
+        );
+        // The layout, not the default one of its number of channels.
+        let layout = |layout: &str| {
+            format(
+                &[
+                    "ffmpeg",
+                    "-i",
+                    "in.wav",
+                    "-channel_layout",
+                    layout,
+                    "out.wav",
+                ],
+                None,
+            )
+        };
+        assert_eq!(layout("5.1"), Ok(Some((0, "channel_layouts=0x3f:".into()))));
+        assert_eq!(
+            layout("5.1(side)"),
+            Ok(Some((0, "channel_layouts=0x60f:".into())))
+        );
+        assert_eq!(
+            format(
+                &[
+                    "ffmpeg",
+                    "-i",
+                    "in.wav",
+                    "-af",
+                    "volume=2",
+                    "-sample_fmt",
+                    "s16",
+                    "out.wav"
+                ],
+                Some("volume=2")
+            ),
+            Ok(Some((0, "sample_fmts=s16:".into())))
+        );
+        assert_eq!(
+            format(
+                &["ffmpeg", "-i", "in.mp4", "-vf", "hflip", "-ar", "8000", "out.mp4"],
+                Some("hflip")
+            ),
+            Ok(None)
+        );
+        assert_eq!(
+            format(
+                &["ffmpeg", "-i", "in.wav", "-ar:v", "8000", "out.wav"],
+                None
+            ),
+            Ok(None)
+        );
+        assert!(format(&["ffmpeg", "-i", "in.wav", "-ar", "0", "out.wav"], None).is_err());
+        assert!(format(
+            &["ffmpeg", "-i", "in.wav", "-sample_fmt", "s17", "out.wav"],
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn audio_sinks() {
+        let legacy = audio_sink_code(1, "sample_rates=8000:", CodegenTarget::Legacy);
+        assert!(legacy.contains("int ret;\nAVFilterContext *last_filter = (*outputs)->filt_ctx;"));
+        assert!(legacy.contains(
+            "\"format_out_1_a\", \"sample_rates=8000:\", NULL, ctx)) < 0)\n    return ret;"
+        ));
+        assert!(!legacy.contains("goto end;"));
+
+        let segment = audio_sink_code(1, "sample_rates=8000:", CodegenTarget::Segment);
+        assert!(!segment.contains("int ret;"));
+        assert!(segment.contains("\"out_1_a\", NULL, NULL, graph)) < 0)\n    goto end;"));
+        assert!(!segment.contains("return ret;"));
+    }
+
+    #[test]
+    fn channel_maps() {
+        let maps: Vec<_> = ["0.1.1", "-1", "0.1.0"]
+            .iter()
+            .map(|x| parse_map_channel(x).unwrap())
+            .collect();
+        assert_eq!(pan_filter(&maps), "pan=0xb|c0=c1|c2=c0");
+
+        let filtergraph = |args: &[&str]| {
+            with_split(args, |octx, filtergraph| {
+                channel_map_filter(octx, filtergraph).map(|_| filtergraph.take())
+            })
+        };
+        assert_eq!(
+            filtergraph(&[
+                "ffmpeg",
+                "-i",
+                "in.wav",
+                "-map_channel",
+                "0.0.1",
+                "-map_channel",
+                "0.0.0",
+                "out.wav"
+            ]),
+            Ok(Some("pan=0x3|c0=c1|c1=c0".into()))
+        );
+        assert_eq!(
+            filtergraph(&[
+                "ffmpeg",
+                "-i",
+                "in.wav",
+                "-af",
+                "volume=2",
+                "-map_channel",
+                "0.0.1",
+                "out.wav"
+            ]),
+            Ok(Some("volume=2,pan=0x4|c0=c1".into()))
+        );
+        // For another output file.
+        assert_eq!(
+            filtergraph(&[
+                "ffmpeg",
+                "-i",
+                "in.wav",
+                "-map_channel",
+                "0.0.1:1.0",
+                "out.wav"
+            ]),
+            Ok(None)
+        );
+        assert!(
+            filtergraph(&["ffmpeg", "-i", "in.wav", "-map_channel", "1.0.1", "out.wav"]).is_err()
+        );
+        assert!(filtergraph(&[
+            "ffmpeg",
+            "-i",
+            "in.wav",
+            "-map_channel",
+            "0.0.1",
+            "-map_channel",
+            "0.1.0",
+            "out.wav"
+        ])
+        .is_err());
+    }
 }
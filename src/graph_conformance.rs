@@ -0,0 +1,531 @@
+//! Differential conformance between `parse_graph()` and libavfilter's own
+//! `avfilter_graph_parse_ptr()`.
+//!
+//! Both parsers are run on the same graph description, and what they produce
+//! is compared: the filter instances with their options, the links with their
+//! pad indices, and the open inputs and outputs. The options are compared as
+//! libavfilter sets them, so ffgen's args are applied to a scratch filter
+//! before comparing.
+//!
+//! The graph descriptions come from the examples of FFmpeg's
+//! `doc/filters.texi`, see `texi_graphs()`.
+use libc::{c_char, c_void};
+use rusty_ffmpeg::ffi;
+
+use std::{
+    ffi::{CStr, CString},
+    fmt, ptr, slice,
+};
+
+use crate::{
+    graph_parser::{parse_graph, FilterContext, FilterInOut, ParsedGraph},
+    shell,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    inst_name: String,
+    filt_name: String,
+    options: String,
+}
+
+/// `(from_filter, from_pad_idx, to_filter, to_pad_idx)`
+type Link = (usize, usize, usize, usize);
+
+/// `(name, filter, pad_idx)`
+type InOut = (Option<String>, Option<usize>, usize);
+
+/// What both parsers are compared on.
+#[derive(Debug, Default, PartialEq)]
+struct Summary {
+    filters: Vec<Filter>,
+    /// Sorted, libavfilter doesn't keep the order in which links are created.
+    links: Vec<Link>,
+    open_inputs: Vec<InOut>,
+    open_outputs: Vec<InOut>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub what: String,
+    pub ffgen: String,
+    pub libavfilter: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: ffgen gives {}, libavfilter gives {}",
+            self.what, self.ffgen, self.libavfilter
+        )
+    }
+}
+
+/// Options set on the filter context and on its private context, as
+/// `key=value` pairs separated by `:` without the default values.
+unsafe fn serialize_options(ctx: *mut ffi::AVFilterContext) -> String {
+    let mut objects = vec![ctx as *mut c_void];
+    // The private context only begins with an `AVClass` when there is one.
+    if !(*(*ctx).filter).priv_class.is_null() {
+        objects.push((*ctx).priv_);
+    }
+
+    let mut options = vec![];
+    for &object in objects.iter() {
+        let mut buffer: *mut c_char = ptr::null_mut();
+        let ret = ffi::av_opt_serialize(
+            object,
+            ffi::AV_OPT_FLAG_FILTERING_PARAM as _,
+            ffi::AV_OPT_SERIALIZE_SKIP_DEFAULTS as _,
+            &mut buffer,
+            b'=' as _,
+            b':' as _,
+        );
+        if ret >= 0 && !buffer.is_null() {
+            let serialized = CStr::from_ptr(buffer).to_string_lossy();
+            if !serialized.is_empty() {
+                options.push(serialized.into_owned());
+            }
+            ffi::av_free(buffer as *mut c_void);
+        }
+    }
+    options.join(":")
+}
+
+/// Options libavfilter sets from the args which ffgen gives to the filter.
+fn ffgen_options(filter: &FilterContext) -> String {
+    let filt_name = CString::new(filter.filt_name.clone()).unwrap_or_default();
+    let inst_name = CString::new(filter.inst_name.clone()).unwrap_or_default();
+    let args = CString::new(filter.args.clone()).unwrap_or_default();
+    unsafe {
+        let mut graph = ffi::avfilter_graph_alloc();
+        if graph.is_null() {
+            return String::new();
+        }
+        let filt = ffi::avfilter_get_by_name(filt_name.as_ptr());
+        let ctx = if filt.is_null() {
+            ptr::null_mut()
+        } else {
+            ffi::avfilter_graph_alloc_filter(graph, filt, inst_name.as_ptr())
+        };
+        let options = if ctx.is_null() {
+            String::new()
+        } else if ffi::avfilter_init_str(ctx, args.as_ptr()) < 0 {
+            format!("(invalid args '{}')", String::from_utf8_lossy(&filter.args))
+        } else {
+            serialize_options(ctx)
+        };
+        ffi::avfilter_graph_free(&mut graph);
+        options
+    }
+}
+
+fn ffgen_summary(graph: &ParsedGraph) -> Summary {
+    let inout = |inout: &FilterInOut| {
+        (
+            inout.name.map(|x| String::from_utf8_lossy(x).into_owned()),
+            inout.filter_ctx,
+            inout.pad_idx,
+        )
+    };
+    let mut links: Vec<Link> = graph
+        .links
+        .iter()
+        .map(|link| {
+            (
+                link.from_filter,
+                link.from_pad_idx,
+                link.to_filter,
+                link.to_pad_idx,
+            )
+        })
+        .collect();
+    links.sort();
+
+    Summary {
+        filters: graph
+            .filters
+            .iter()
+            .map(|filter| Filter {
+                inst_name: filter.inst_name.clone(),
+                filt_name: filter.filt_name.clone(),
+                options: ffgen_options(filter),
+            })
+            .collect(),
+        links,
+        open_inputs: graph.open_inputs.iter().map(inout).collect(),
+        open_outputs: graph.open_outputs.iter().map(inout).collect(),
+    }
+}
+
+unsafe fn libavfilter_inouts(
+    filters: &[*mut ffi::AVFilterContext],
+    mut inout: *mut ffi::AVFilterInOut,
+) -> Vec<InOut> {
+    let mut inouts = vec![];
+    while let Some(x) = inout.as_ref() {
+        inouts.push((
+            x.name
+                .as_ref()
+                .map(|name| CStr::from_ptr(name).to_string_lossy().into_owned()),
+            filters.iter().position(|&filter| filter == x.filter_ctx),
+            x.pad_idx as usize,
+        ));
+        inout = x.next;
+    }
+    inouts
+}
+
+unsafe fn libavfilter_summary(description: &str) -> Result<Summary, ()> {
+    let description = CString::new(description).map_err(|_| ())?;
+    let mut graph = ffi::avfilter_graph_alloc();
+    if graph.is_null() {
+        return Err(());
+    }
+    let mut inputs = ptr::null_mut();
+    let mut outputs = ptr::null_mut();
+    let ret = ffi::avfilter_graph_parse_ptr(
+        graph,
+        description.as_ptr(),
+        &mut inputs,
+        &mut outputs,
+        ptr::null_mut(),
+    );
+
+    let summary = if ret < 0 {
+        Err(())
+    } else {
+        let filters = match (*graph).nb_filters as usize {
+            0 => &[][..],
+            nb_filters => slice::from_raw_parts((*graph).filters, nb_filters),
+        };
+
+        let mut links = vec![];
+        for (from, &ctx) in filters.iter().enumerate() {
+            for from_pad in 0..(*ctx).nb_outputs as usize {
+                let link = *(*ctx).outputs.add(from_pad);
+                if link.is_null() {
+                    continue;
+                }
+                let dst = (*link).dst;
+                let to = filters.iter().position(|&filter| filter == dst);
+                let to_pad =
+                    (0..(*dst).nb_inputs as usize).find(|&i| *(*dst).inputs.add(i) == link);
+                if let (Some(to), Some(to_pad)) = (to, to_pad) {
+                    links.push((from, from_pad, to, to_pad));
+                }
+            }
+        }
+        links.sort();
+
+        Ok(Summary {
+            filters: filters
+                .iter()
+                .map(|&ctx| Filter {
+                    inst_name: CStr::from_ptr((*ctx).name).to_string_lossy().into_owned(),
+                    filt_name: CStr::from_ptr((*(*ctx).filter).name)
+                        .to_string_lossy()
+                        .into_owned(),
+                    options: serialize_options(ctx),
+                })
+                .collect(),
+            links,
+            open_inputs: libavfilter_inouts(filters, inputs),
+            open_outputs: libavfilter_inouts(filters, outputs),
+        })
+    };
+
+    ffi::avfilter_inout_free(&mut inputs);
+    ffi::avfilter_inout_free(&mut outputs);
+    ffi::avfilter_graph_free(&mut graph);
+    summary
+}
+
+fn divergences(ffgen: &Summary, libavfilter: &Summary) -> Vec<Divergence> {
+    let mut divergences = vec![];
+    let mut diverge = |what: String, ffgen: String, libavfilter: String| {
+        if ffgen != libavfilter {
+            divergences.push(Divergence {
+                what,
+                ffgen,
+                libavfilter,
+            })
+        }
+    };
+
+    diverge(
+        "number of filters".into(),
+        ffgen.filters.len().to_string(),
+        libavfilter.filters.len().to_string(),
+    );
+    for (i, (x, y)) in ffgen
+        .filters
+        .iter()
+        .zip(libavfilter.filters.iter())
+        .enumerate()
+    {
+        diverge(
+            format!("name of filter {}", i),
+            format!("'{}'", x.filt_name),
+            format!("'{}'", y.filt_name),
+        );
+        diverge(
+            format!("instance name of filter {}", i),
+            format!("'{}'", x.inst_name),
+            format!("'{}'", y.inst_name),
+        );
+        diverge(
+            format!("options of filter {}", i),
+            format!("'{}'", x.options),
+            format!("'{}'", y.options),
+        );
+    }
+    diverge(
+        "links".into(),
+        format!("{:?}", ffgen.links),
+        format!("{:?}", libavfilter.links),
+    );
+    diverge(
+        "open inputs".into(),
+        format!("{:?}", ffgen.open_inputs),
+        format!("{:?}", libavfilter.open_inputs),
+    );
+    diverge(
+        "open outputs".into(),
+        format!("{:?}", ffgen.open_outputs),
+        format!("{:?}", libavfilter.open_outputs),
+    );
+    divergences
+}
+
+/// Parse the graph description with both ffgen and libavfilter, and report
+/// where they diverge. Both failing to parse it is not a divergence.
+pub fn compare(description: &str) -> Vec<Divergence> {
+    let ffgen = parse_graph(description)
+        .map(|graph| ffgen_summary(&graph))
+        .map_err(|_| ());
+    let libavfilter = unsafe { libavfilter_summary(description) };
+    let result = |x: &Result<Summary, ()>| if x.is_ok() { "success" } else { "failure" };
+    match (&ffgen, &libavfilter) {
+        (Ok(ffgen), Ok(libavfilter)) => divergences(ffgen, libavfilter),
+        (Err(_), Err(_)) => vec![],
+        _ => vec![Divergence {
+            what: "parsing".into(),
+            ffgen: result(&ffgen).into(),
+            libavfilter: result(&libavfilter).into(),
+        }],
+    }
+}
+
+/// The options of the ffmpeg and ffplay commandlines taking a graph
+/// description.
+const GRAPH_OPTIONS: [&str; 7] = [
+    "-vf",
+    "-af",
+    "-filter",
+    "-filter:v",
+    "-filter:a",
+    "-filter_complex",
+    "-lavfi",
+];
+
+/// Whether a line of an example, which is not a commandline, looks like a
+/// graph description: optional labels, then a filter name.
+fn is_graph_line(line: &str) -> bool {
+    let mut rest = line;
+    while rest.starts_with('[') {
+        match rest.find(']') {
+            Some(end) => rest = rest[end + 1..].trim_start(),
+            None => return false,
+        }
+    }
+    let name = rest
+        .split(|c: char| "=,;[".contains(c) || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+    let ident = |x: &str| !x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    // An instance name may follow the filter name after a `@`.
+    name.split('@').count() <= 2 && name.split('@').all(ident)
+}
+
+/// The graph descriptions in the `@example` blocks of FFmpeg's
+/// `doc/filters.texi`: the arguments of the graph options of the ffmpeg and
+/// ffplay commandlines, the `-f lavfi` inputs, and the lines which are graph
+/// descriptions themselves. Without duplicates, in order.
+pub fn texi_graphs(texi: &str) -> Vec<String> {
+    let mut graphs: Vec<String> = vec![];
+    let mut in_example = false;
+    let mut line = String::new();
+    for texi_line in texi.lines() {
+        let texi_line = texi_line.trim_end();
+        match texi_line.trim_start() {
+            "@example" => {
+                in_example = true;
+                continue;
+            }
+            "@end example" => {
+                in_example = false;
+                continue;
+            }
+            _ if !in_example => continue,
+            _ => {}
+        }
+        let unescaped = texi_line
+            .replace("@{", "{")
+            .replace("@}", "}")
+            .replace("@@", "@");
+        line.push_str(&unescaped);
+        // Commandlines are continued on the next line after a `\`, which
+        // `shell::split()` removes with the newline.
+        if unescaped.ends_with('\\') {
+            line.push('\n');
+            continue;
+        }
+        let full = std::mem::take(&mut line);
+        let full = full.trim();
+
+        let is_command = matches!(
+            full.split_whitespace().next(),
+            Some("ffmpeg") | Some("ffplay") | Some("ffprobe")
+        );
+        let found = match shell::split(full.as_bytes()) {
+            // Pipes, substitutions or broken quotes: not a commandline
+            // which can be checked.
+            Err(_) if is_command => vec![],
+            Ok(words) if is_command => {
+                let words: Vec<String> = words
+                    .iter()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .collect();
+                words
+                    .iter()
+                    .zip(words.iter().skip(1))
+                    .enumerate()
+                    .filter(|&(i, (opt, _))| {
+                        GRAPH_OPTIONS.contains(&opt.as_str())
+                            || (opt == "-i" && i > 0 && words[i - 1] == "lavfi")
+                    })
+                    .map(|(_, (_, arg))| arg.clone())
+                    .collect()
+            }
+            _ if is_graph_line(full) => vec![full.to_owned()],
+            _ => vec![],
+        };
+        for graph in found {
+            if !graphs.contains(&graph) {
+                graphs.push(graph);
+            }
+        }
+    }
+    graphs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// One graph description per line, `#` begins a comment line.
+    const CORPUS: &str = include_str!("../tests/filtergraphs.txt");
+
+    #[test]
+    fn report_divergences() {
+        let filter = |options: &str| Filter {
+            inst_name: "Parsed_scale_0".into(),
+            filt_name: "scale".into(),
+            options: options.into(),
+        };
+        let ffgen = Summary {
+            filters: vec![filter("w=320:h=240")],
+            open_inputs: vec![(Some("in".into()), Some(0), 0)],
+            ..Default::default()
+        };
+        let libavfilter = Summary {
+            filters: vec![filter("w=320:h=240")],
+            open_inputs: vec![(Some("in".into()), Some(0), 0)],
+            ..Default::default()
+        };
+        assert!(divergences(&ffgen, &libavfilter).is_empty());
+
+        let libavfilter = Summary {
+            filters: vec![filter("w=240:h=320")],
+            open_outputs: vec![(None, Some(0), 0)],
+            ..Default::default()
+        };
+        let report: Vec<String> = divergences(&ffgen, &libavfilter)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                "options of filter 0: ffgen gives 'w=320:h=240', libavfilter gives 'w=240:h=320'",
+                "open inputs: ffgen gives [(Some(\"in\"), Some(0), 0)], libavfilter gives []",
+                "open outputs: ffgen gives [], libavfilter gives [(None, Some(0), 0)]",
+            ]
+        );
+    }
+
+    #[test]
+    fn texi_examples() {
+        let texi = r#"@example
+ffmpeg -i input -vf "scale=w=iw/2:h=ih/2, hflip" output
+@end example
+Not an example: -vf "ignored"
+@example
+ffmpeg -i in.mp4 -filter_complex '[0:v]split[a][b];[a][b]overlay' out.mp4
+nullsrc=s=hd720,lutrgb='r=val*2'
+between(t,10,20)
+@end example
+@example
+ffplay -f lavfi -i "color=c=red@@0.2:s=qcif"
+ffmpeg -i in -vf drawtext="text='@{localtime@}'" \
+  -af \"volume=2\" out
+[in]scale=720:480 [out]
+ffmpeg -i input -vf "scale=w=iw/2:h=ih/2, hflip" output
+ffmpeg -i in -vf "unterminated out
+ffmpeg -i in -vf vflip -f nut - | ffplay -
+@end example
+"#;
+        assert_eq!(
+            texi_graphs(texi),
+            [
+                "scale=w=iw/2:h=ih/2, hflip",
+                "[0:v]split[a][b];[a][b]overlay",
+                "nullsrc=s=hd720,lutrgb='r=val*2'",
+                "color=c=red@0.2:s=qcif",
+                "drawtext=text='{localtime}'",
+                "\"volume=2\"",
+                "[in]scale=720:480 [out]",
+            ]
+        );
+    }
+
+    /// The checked-in corpus, and the examples of FFmpeg's
+    /// `doc/filters.texi` when `FFMPEG_FILTERS_TEXI` is its path.
+    fn corpus() -> Vec<String> {
+        let mut corpus: Vec<String> = CORPUS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        if let Some(path) = std::env::var_os("FFMPEG_FILTERS_TEXI") {
+            let texi = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Cannot read {:?}: {}", path, e));
+            corpus.extend(texi_graphs(&texi));
+        }
+        corpus
+    }
+
+    #[test]
+    fn conformance() {
+        let mut report = vec![];
+        for description in corpus().iter() {
+            for divergence in compare(description) {
+                report.push(format!("{}\n    {}", description, divergence));
+            }
+        }
+        assert!(report.is_empty(), "\n{}", report.join("\n"));
+    }
+}
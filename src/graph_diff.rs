@@ -0,0 +1,314 @@
+//! Structural comparison of two parsed filtergraphs.
+//!
+//! Filters with an instance name given with `@` are matched by that name,
+//! the other filters are matched by their position among the filters of the
+//! same kind, e.g. the second unnamed `scale` of one graph is matched with the
+//! second unnamed `scale` of the other one.
+use log::error;
+
+use std::{collections::HashMap, fmt};
+
+use crate::graph_parser::{parse_graph, FilterContext, FilterInOut, ParsedGraph};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// The `sws_flags` of the graph, `None` when they are not set.
+    SwsFlags(Option<String>, Option<String>),
+    /// A filter only in the old graph.
+    RemovedFilter(String),
+    /// A filter only in the new graph.
+    AddedFilter(String),
+    /// Arguments of a filter in both graphs, named as in the new graph.
+    ChangedArgs {
+        filter: String,
+        old: String,
+        new: String,
+    },
+    /// A connection only in the old graph.
+    RemovedConnection(String),
+    /// A connection only in the new graph.
+    AddedConnection(String),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let or_none = |x: &Option<String>| x.clone().unwrap_or_else(|| "(none)".into());
+        match self {
+            Difference::SwsFlags(old, new) => {
+                write!(f, "~ sws_flags: {} -> {}", or_none(old), or_none(new))
+            }
+            Difference::RemovedFilter(filter) => write!(f, "- filter {}", filter),
+            Difference::AddedFilter(filter) => write!(f, "+ filter {}", filter),
+            Difference::ChangedArgs { filter, old, new } => {
+                write!(f, "~ args of {}: '{}' -> '{}'", filter, old, new)
+            }
+            Difference::RemovedConnection(connection) => write!(f, "- link {}", connection),
+            Difference::AddedConnection(connection) => write!(f, "+ link {}", connection),
+        }
+    }
+}
+
+/// One end of a connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum End<'a> {
+    /// Pad of a filter, the filter is an index in the new graph when it's used
+    /// for the matching.
+    Pad(usize, usize),
+    /// Open input or output, with its label if it has one.
+    Open(Option<&'a [u8]>),
+}
+
+/// The instance name given with `@`, None if the parser generated it.
+fn explicit_name(filter: &FilterContext) -> Option<&str> {
+    if filter.inst_name == format!("Parsed_{}_{}", filter.filt_name, filter.index) {
+        None
+    } else {
+        Some(&filter.inst_name)
+    }
+}
+
+/// Name of a filter in the report, the position is the one among the
+/// filters of the same kind which is used for the matching.
+fn display_name(filters: &[FilterContext], index: usize) -> String {
+    let filter = &filters[index];
+    match explicit_name(filter) {
+        Some(name) => name.into(),
+        None => {
+            let position = filters[..index]
+                .iter()
+                .filter(|x| x.filt_name == filter.filt_name && explicit_name(x).is_none())
+                .count();
+            format!("{}#{}", filter.filt_name, position)
+        }
+    }
+}
+
+/// Index in `new` of the filter matching each filter of `old`.
+fn match_filters(old: &[FilterContext], new: &[FilterContext]) -> Vec<Option<usize>> {
+    let mut named = HashMap::new();
+    let mut unnamed: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, filter) in new.iter().enumerate() {
+        match explicit_name(filter) {
+            Some(name) => {
+                named.insert(name, i);
+            }
+            None => unnamed.entry(&filter.filt_name).or_default().push(i),
+        }
+    }
+
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    old.iter()
+        .map(|filter| match explicit_name(filter) {
+            Some(name) => named
+                .get(name)
+                .copied()
+                .filter(|&i| new[i].filt_name == filter.filt_name),
+            None => {
+                let position = positions.entry(&filter.filt_name).or_insert(0);
+                *position += 1;
+                unnamed
+                    .get(filter.filt_name.as_str())
+                    .and_then(|x| x.get(*position - 1))
+                    .copied()
+            }
+        })
+        .collect()
+}
+
+/// Every connection of the graph: links between filters, open inputs and
+/// open outputs.
+fn connections<'a>(graph: &ParsedGraph<'a>) -> Vec<(End<'a>, End<'a>)> {
+    let open = |inout: &FilterInOut<'a>| {
+        inout
+            .filter_ctx
+            .map(|filter| (End::Open(inout.name), End::Pad(filter, inout.pad_idx)))
+    };
+    let links = graph.links.iter().map(|link| {
+        (
+            End::Pad(link.from_filter, link.from_pad_idx),
+            End::Pad(link.to_filter, link.to_pad_idx),
+        )
+    });
+    let inputs = graph.open_inputs.iter().filter_map(open);
+    let outputs = graph
+        .open_outputs
+        .iter()
+        .filter_map(open)
+        .map(|(label, pad)| (pad, label));
+    links.chain(inputs).chain(outputs).collect()
+}
+
+fn display_connection(filters: &[FilterContext], (from, to): (End, End)) -> String {
+    let end = |end: End| match end {
+        End::Pad(filter, pad) => format!("{}:{}", display_name(filters, filter), pad),
+        End::Open(Some(name)) => format!("[{}]", String::from_utf8_lossy(name)),
+        End::Open(None) => "(open)".into(),
+    };
+    format!("{} -> {}", end(from), end(to))
+}
+
+pub fn diff_graphs<'a>(old: &ParsedGraph<'a>, new: &ParsedGraph<'a>) -> Vec<Difference> {
+    let mut differences = vec![];
+
+    let sws_flags = |graph: &ParsedGraph| {
+        graph
+            .graph
+            .scale_sws_opts
+            .map(|x| String::from_utf8_lossy(x).into())
+    };
+    if old.graph.scale_sws_opts != new.graph.scale_sws_opts {
+        differences.push(Difference::SwsFlags(sws_flags(old), sws_flags(new)));
+    }
+
+    let matching = match_filters(&old.filters, &new.filters);
+    for (i, matched) in matching.iter().enumerate() {
+        match *matched {
+            Some(j) if old.filters[i].args != new.filters[j].args => {
+                differences.push(Difference::ChangedArgs {
+                    filter: display_name(&new.filters, j),
+                    old: String::from_utf8_lossy(&old.filters[i].args).into_owned(),
+                    new: String::from_utf8_lossy(&new.filters[j].args).into_owned(),
+                })
+            }
+            Some(_) => (),
+            None => differences.push(Difference::RemovedFilter(display_name(&old.filters, i))),
+        }
+    }
+    for j in 0..new.filters.len() {
+        if !matching.contains(&Some(j)) {
+            differences.push(Difference::AddedFilter(display_name(&new.filters, j)));
+        }
+    }
+
+    // Connections of the old graph are compared in terms of the new graph, a
+    // connection of a removed filter cannot be in the new graph.
+    let new_connections = connections(new);
+    let to_new = |end: End<'a>| match end {
+        End::Pad(filter, pad) => matching[filter].map(|filter| End::Pad(filter, pad)),
+        End::Open(name) => Some(End::Open(name)),
+    };
+    let mut kept = vec![false; new_connections.len()];
+    for connection in connections(old) {
+        let found = match (to_new(connection.0), to_new(connection.1)) {
+            (Some(from), Some(to)) => new_connections
+                .iter()
+                .enumerate()
+                .position(|(k, &x)| !kept[k] && x == (from, to)),
+            _ => None,
+        };
+        match found {
+            Some(k) => kept[k] = true,
+            None => differences.push(Difference::RemovedConnection(display_connection(
+                &old.filters,
+                connection,
+            ))),
+        }
+    }
+    for (k, &connection) in new_connections.iter().enumerate() {
+        if !kept[k] {
+            differences.push(Difference::AddedConnection(display_connection(
+                &new.filters,
+                connection,
+            )));
+        }
+    }
+
+    differences
+}
+
+/// Entry of `ffgen diff <old_graph> <new_graph>`, which prints the structural
+/// differences between the two filtergraphs, nothing if they are the same.
+pub fn diff(args: &[String]) -> Result<(), ()> {
+    let (old, new) = match args {
+        [old, new] => (old, new),
+        _ => {
+            eprintln!("Usage: ffgen diff <old_graph_description> <new_graph_description>");
+            return Err(());
+        }
+    };
+    let old = parse_graph(old).map_err(|_| error!("Failed to parse the old filtergraph."))?;
+    let new = parse_graph(new).map_err(|_| error!("Failed to parse the new filtergraph."))?;
+    for difference in diff_graphs(&old, &new) {
+        println!("{}", difference);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn differences(old: &str, new: &str) -> Vec<String> {
+        let old = parse_graph(old).unwrap();
+        let new = parse_graph(new).unwrap();
+        diff_graphs(&old, &new)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn same_structure() {
+        // Formatting and link labels don't matter.
+        assert!(differences(
+            "[in]scale=720:480, split [main][tmp]; [tmp] vflip [flip]; [main][flip] overlay [out]",
+            "[in]scale=720:480,split[a][b];[b]vflip[c];[a][c]overlay[out]",
+        )
+        .is_empty());
+        assert!(differences("null@x, null@y", "null@x, null@y").is_empty());
+    }
+
+    #[test]
+    fn changed_args() {
+        assert_eq!(
+            differences(
+                "sws_flags=bicubic;scale=720:480,hflip",
+                "scale=320:240,hflip"
+            ),
+            vec![
+                "~ sws_flags: flags=bicubic -> (none)",
+                "~ args of scale#0: '720:480:flags=bicubic' -> '320:240'",
+            ]
+        );
+        // Named filters are matched by name wherever they are.
+        assert_eq!(
+            differences("scale@a=720:480;scale@b=1:1", "scale@b=2:2;scale@a=720:480"),
+            vec!["~ args of scale@b: '1:1' -> '2:2'"]
+        );
+    }
+
+    #[test]
+    fn added_and_removed() {
+        assert_eq!(
+            differences("[in]scale=720:480[out]", "[in]scale=720:480,hflip[out]"),
+            vec![
+                "+ filter hflip#0",
+                "- link scale#0:0 -> [out]",
+                "+ link scale#0:0 -> hflip#0:0",
+                "+ link hflip#0:0 -> [out]",
+            ]
+        );
+        assert_eq!(
+            differences("[in]hflip,scale=1:1[out]", "[in]scale=1:1[out]"),
+            vec![
+                "- filter hflip#0",
+                "- link hflip#0:0 -> scale#0:0",
+                "- link [in] -> hflip#0:0",
+                "+ link [in] -> scale#0:0",
+            ]
+        );
+    }
+
+    #[test]
+    fn rewired() {
+        assert_eq!(
+            differences("split[a][b];[a][b]overlay", "split[a][b];[b][a]overlay"),
+            vec![
+                "- link split#0:0 -> overlay#0:0",
+                "- link split#0:1 -> overlay#0:1",
+                "+ link split#0:1 -> overlay#0:0",
+                "+ link split#0:0 -> overlay#0:1",
+            ]
+        );
+    }
+}
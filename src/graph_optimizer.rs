@@ -0,0 +1,563 @@
+//! Optional transforms on the parsed filtergraph, done before any code is
+//! generated from it.
+use bitflags::bitflags;
+use log::debug;
+
+use std::{fmt, str};
+
+use crate::graph_parser::ParsedGraph;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct OptimizePass: u32 {
+        const NONE          = 0x00;
+        /// Remove `null` and `anull` filters.
+        const REMOVE_NULL   = 0x01;
+        /// Remove a `scale` whose only output goes to another `scale`, when
+        /// both of them scale to a constant size with the same options.
+        const FOLD_SCALE    = 0x02;
+        /// Merge a `format` whose only output goes to another `format`.
+        const MERGE_FORMAT  = 0x04;
+        /// Drop `split` and `asplit` outputs connected to nothing, which are
+        /// left by `REMOVE_DEAD`.
+        const PRUNE_SPLIT   = 0x08;
+        /// Remove filters which don't reach any output of the graph.
+        const REMOVE_DEAD   = 0x10;
+        const ALL           = 0x1f;
+    }
+}
+
+const PASS_NAMES: [(&str, OptimizePass); 6] = [
+    ("remove_null", OptimizePass::REMOVE_NULL),
+    ("fold_scale", OptimizePass::FOLD_SCALE),
+    ("merge_format", OptimizePass::MERGE_FORMAT),
+    ("prune_split", OptimizePass::PRUNE_SPLIT),
+    ("remove_dead", OptimizePass::REMOVE_DEAD),
+    ("all", OptimizePass::ALL),
+];
+
+impl OptimizePass {
+    /// Parse a `+` separated pass list, e.g. `remove_null+fold_scale`.
+    pub fn parse(passes: &str) -> Result<Self, String> {
+        passes.split('+').filter(|name| !name.is_empty()).try_fold(
+            OptimizePass::NONE,
+            |passes, name| match PASS_NAMES.iter().find(|(x, _)| *x == name) {
+                Some(&(_, pass)) => Ok(passes | pass),
+                None => Err(format!("Unknown optimization pass '{}'", name)),
+            },
+        )
+    }
+
+    fn name(self) -> &'static str {
+        PASS_NAMES
+            .iter()
+            .find(|&&(_, pass)| pass == self)
+            .map_or("unknown", |&(name, _)| name)
+    }
+}
+
+/// What a pass changed in the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub pass: OptimizePass,
+    pub message: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pass.name(), self.message)
+    }
+}
+
+/// What is connected to a pad, the index of the link or open input/output.
+enum Connection {
+    Link(usize),
+    Open(usize),
+    Nothing,
+}
+
+fn input_of(graph: &ParsedGraph, filter: usize, pad: usize) -> Connection {
+    if let Some(i) = graph
+        .links
+        .iter()
+        .position(|link| link.to_filter == filter && link.to_pad_idx == pad)
+    {
+        Connection::Link(i)
+    } else if let Some(i) = graph
+        .open_inputs
+        .iter()
+        .position(|input| input.filter_ctx == Some(filter) && input.pad_idx == pad)
+    {
+        Connection::Open(i)
+    } else {
+        Connection::Nothing
+    }
+}
+
+fn output_of(graph: &ParsedGraph, filter: usize, pad: usize) -> Connection {
+    if let Some(i) = graph
+        .links
+        .iter()
+        .position(|link| link.from_filter == filter && link.from_pad_idx == pad)
+    {
+        Connection::Link(i)
+    } else if let Some(i) = graph
+        .open_outputs
+        .iter()
+        .position(|output| output.filter_ctx == Some(filter) && output.pad_idx == pad)
+    {
+        Connection::Open(i)
+    } else {
+        Connection::Nothing
+    }
+}
+
+/// Remove the filter and everything connected to it, the pads connected to
+/// the filter are left connected to nothing.
+fn remove_filter(graph: &mut ParsedGraph, index: usize) {
+    graph
+        .links
+        .retain(|link| link.from_filter != index && link.to_filter != index);
+    graph
+        .open_inputs
+        .retain(|input| input.filter_ctx != Some(index));
+    graph
+        .open_outputs
+        .retain(|output| output.filter_ctx != Some(index));
+    graph.filters.remove(index);
+
+    let reindex = |i: usize| if i > index { i - 1 } else { i };
+    for filter in graph.filters.iter_mut().skip(index) {
+        if filter.inst_name == format!("Parsed_{}_{}", filter.filt_name, filter.index) {
+            filter.inst_name = format!("Parsed_{}_{}", filter.filt_name, filter.index - 1);
+        }
+        filter.index -= 1;
+    }
+    for link in graph.links.iter_mut() {
+        link.from_filter = reindex(link.from_filter);
+        link.to_filter = reindex(link.to_filter);
+    }
+    for inout in graph
+        .open_inputs
+        .iter_mut()
+        .chain(graph.open_outputs.iter_mut())
+    {
+        inout.filter_ctx = inout.filter_ctx.map(reindex);
+    }
+}
+
+/// Remove a filter with one input and one output, connecting its input to
+/// where its output goes. Returns false when it cannot be removed, which is
+/// when it is the only filter between an open input and an open output, or
+/// when its output is linked back into its input.
+fn bypass_filter(graph: &mut ParsedGraph, index: usize) -> bool {
+    if graph.filters[index].nb_inputs != 1 || graph.filters[index].nb_outputs != 1 {
+        return false;
+    }
+    match (input_of(graph, index, 0), output_of(graph, index, 0)) {
+        (Connection::Link(from), Connection::Link(to)) if from != to => {
+            let from = graph.links[from].clone();
+            let to = &mut graph.links[to];
+            to.from_filter = from.from_filter;
+            to.from_pad_idx = from.from_pad_idx;
+        }
+        (Connection::Open(input), Connection::Link(to)) => {
+            let to = graph.links[to].clone();
+            let input = &mut graph.open_inputs[input];
+            input.filter_ctx = Some(to.to_filter);
+            input.pad_idx = to.to_pad_idx;
+            graph.links.retain(|link| *link != to);
+        }
+        (Connection::Link(from), Connection::Open(output)) => {
+            let from = graph.links[from].clone();
+            let output = &mut graph.open_outputs[output];
+            output.filter_ctx = Some(from.from_filter);
+            output.pad_idx = from.from_pad_idx;
+            graph.links.retain(|link| *link != from);
+        }
+        _ => return false,
+    }
+    remove_filter(graph, index);
+    true
+}
+
+/// The filter which the only output of `index` goes to.
+fn only_successor(graph: &ParsedGraph, index: usize) -> Option<usize> {
+    if graph.filters[index].nb_outputs != 1 {
+        return None;
+    }
+    match output_of(graph, index, 0) {
+        Connection::Link(i) if graph.links[i].to_pad_idx == 0 => Some(graph.links[i].to_filter),
+        _ => None,
+    }
+}
+
+/// Split filter arguments into `key=value` pairs, positional ones have no
+/// key. Returns None for escaped or quoted arguments which are not handled.
+fn split_args(args: &str) -> Option<Vec<(Option<&str>, &str)>> {
+    if args.contains('\\') || args.contains('\'') {
+        return None;
+    }
+    Some(
+        args.split(':')
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| match arg.find('=') {
+                Some(i) => (Some(&arg[..i]), &arg[i + 1..]),
+                None => (None, arg),
+            })
+            .collect(),
+    )
+}
+
+/// Options of a `scale` except its output size, None if the size isn't constant.
+fn scale_options(args: &str) -> Option<Vec<String>> {
+    let (mut w, mut h) = (None, None);
+    let mut rest = vec![];
+    for (i, (key, value)) in split_args(args)?.into_iter().enumerate() {
+        match (key, i) {
+            (Some("w"), _) | (Some("width"), _) | (None, 0) => w = value.parse::<u32>().ok(),
+            (Some("h"), _) | (Some("height"), _) | (None, 1) => h = value.parse::<u32>().ok(),
+            (Some("s"), _) | (Some("size"), _) => return None,
+            (Some(key), _) => rest.push(format!("{}={}", key, value)),
+            (None, _) => rest.push(value.to_owned()),
+        }
+    }
+    match (w, h) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some(rest),
+        _ => None,
+    }
+}
+
+/// Pixel formats of a `format`.
+fn format_pix_fmts(args: &str) -> Option<Vec<&str>> {
+    match split_args(args)?.as_slice() {
+        [(None, pix_fmts)] | [(Some("pix_fmts"), pix_fmts)] => Some(pix_fmts.split('|').collect()),
+        _ => None,
+    }
+}
+
+fn remove_null(graph: &mut ParsedGraph) -> Option<String> {
+    for i in 0..graph.filters.len() {
+        let filter = &graph.filters[i];
+        if filter.filt_name != "null" && filter.filt_name != "anull" {
+            continue;
+        }
+        let inst_name = filter.inst_name.clone();
+        if bypass_filter(graph, i) {
+            return Some(format!("removed '{}'", inst_name));
+        }
+    }
+    None
+}
+
+fn fold_scale(graph: &mut ParsedGraph) -> Option<String> {
+    let (first, second) = (0..graph.filters.len()).find_map(|i| {
+        if graph.filters[i].filt_name != "scale" {
+            return None;
+        }
+        let next = only_successor(graph, i)?;
+        if graph.filters[next].filt_name != "scale" {
+            return None;
+        }
+        let first_options = scale_options(str::from_utf8(&graph.filters[i].args).ok()?)?;
+        let second_options = scale_options(str::from_utf8(&graph.filters[next].args).ok()?)?;
+        (first_options == second_options).then_some((i, next))
+    })?;
+    let message = format!(
+        "folded '{}' into '{}'",
+        graph.filters[first].inst_name, graph.filters[second].inst_name
+    );
+    bypass_filter(graph, first).then_some(message)
+}
+
+fn merge_format(graph: &mut ParsedGraph) -> Option<String> {
+    let candidate = |graph: &ParsedGraph, i: usize| {
+        if graph.filters[i].filt_name != "format" {
+            return None;
+        }
+        let next = only_successor(graph, i)?;
+        if graph.filters[next].filt_name != "format" {
+            return None;
+        }
+        let second_pix_fmts = format_pix_fmts(str::from_utf8(&graph.filters[next].args).ok()?)?;
+        let pix_fmts: Vec<&str> = format_pix_fmts(str::from_utf8(&graph.filters[i].args).ok()?)?
+            .into_iter()
+            .filter(|pix_fmt| second_pix_fmts.contains(pix_fmt))
+            .collect();
+        // Without any common pixel format, the conversion in between matters.
+        (!pix_fmts.is_empty()).then_some((next, pix_fmts.join("|")))
+    };
+    for first in 0..graph.filters.len() {
+        let (second, pix_fmts) = match candidate(graph, first) {
+            Some(candidate) => candidate,
+            None => continue,
+        };
+        let message = format!(
+            "merged '{}' into '{}' as '{}'",
+            graph.filters[first].inst_name, graph.filters[second].inst_name, pix_fmts
+        );
+        // Another pair may still be merged.
+        if !bypass_filter(graph, first) {
+            continue;
+        }
+        let second = if second > first { second - 1 } else { second };
+        graph.filters[second].args = pix_fmts.into_bytes();
+        return Some(message);
+    }
+    None
+}
+
+fn prune_split(graph: &mut ParsedGraph) -> Option<String> {
+    let index = (0..graph.filters.len()).find(|&i| {
+        let filter = &graph.filters[i];
+        (filter.filt_name == "split" || filter.filt_name == "asplit")
+            && (0..filter.nb_outputs)
+                .any(|pad| matches!(output_of(graph, i, pad), Connection::Nothing))
+            && (0..filter.nb_outputs)
+                .any(|pad| !matches!(output_of(graph, i, pad), Connection::Nothing))
+    })?;
+
+    // Renumber connected output pads.
+    let mut nb_outputs = 0;
+    for pad in 0..graph.filters[index].nb_outputs {
+        match output_of(graph, index, pad) {
+            Connection::Link(i) => graph.links[i].from_pad_idx = nb_outputs,
+            Connection::Open(i) => graph.open_outputs[i].pad_idx = nb_outputs,
+            Connection::Nothing => continue,
+        }
+        nb_outputs += 1;
+    }
+
+    let filter = &mut graph.filters[index];
+    let message = format!(
+        "dropped {} unconnected output(s) of '{}'",
+        filter.nb_outputs - nb_outputs,
+        filter.inst_name
+    );
+    filter.nb_outputs = nb_outputs;
+    filter.args = nb_outputs.to_string().into_bytes();
+    if nb_outputs == 1 && bypass_filter(graph, index) {
+        return Some(format!("{}, then removed it", message));
+    }
+    Some(message)
+}
+
+fn remove_dead(graph: &mut ParsedGraph, prune_split: bool) -> Option<String> {
+    let nb_filters = graph.filters.len();
+
+    // Filters reaching an open output, found backwards from the outputs.
+    let mut live = vec![false; nb_filters];
+    let mut stack: Vec<usize> = graph
+        .open_outputs
+        .iter()
+        .filter_map(|output| output.filter_ctx)
+        .collect();
+    while let Some(i) = stack.pop() {
+        if i >= nb_filters || live[i] {
+            continue;
+        }
+        live[i] = true;
+        stack.extend(
+            graph
+                .links
+                .iter()
+                .filter(|link| link.to_filter == i)
+                .map(|link| link.from_filter),
+        );
+    }
+
+    // Nothing reaches an open output when the graph ends with sinks only, in
+    // which case it's used as is, and removing everything isn't a valid graph.
+    if !live.contains(&true) {
+        return None;
+    }
+
+    // A dead filter is kept when removing it leaves a pad of a kept filter
+    // connected to nothing, except for the outputs of split.
+    let mut removable: Vec<bool> = live.iter().map(|&live| !live).collect();
+    loop {
+        let kept = (0..nb_filters).find(|&i| {
+            removable[i]
+                && graph.links.iter().any(|link| {
+                    let from = &graph.filters[link.from_filter];
+                    let from_split = from.filt_name == "split" || from.filt_name == "asplit";
+                    link.to_filter == i
+                        && !removable[link.from_filter]
+                        && !(prune_split && from_split)
+                })
+        });
+        match kept {
+            Some(i) => removable[i] = false,
+            None => break,
+        }
+    }
+
+    let removed: Vec<String> = (0..nb_filters)
+        .rev()
+        .filter(|&i| removable[i])
+        .map(|i| {
+            let inst_name = graph.filters[i].inst_name.clone();
+            remove_filter(graph, i);
+            inst_name
+        })
+        .collect();
+    (!removed.is_empty()).then(|| {
+        let removed: Vec<String> = removed.into_iter().rev().collect();
+        format!("removed '{}'", removed.join("', '"))
+    })
+}
+
+/// Apply the passes until nothing changes, returning what has been changed.
+pub fn optimize(graph: &mut ParsedGraph, passes: OptimizePass) -> Vec<Change> {
+    let mut changes = vec![];
+    loop {
+        let change = [
+            OptimizePass::REMOVE_NULL,
+            OptimizePass::FOLD_SCALE,
+            OptimizePass::MERGE_FORMAT,
+            OptimizePass::REMOVE_DEAD,
+            OptimizePass::PRUNE_SPLIT,
+        ]
+        .iter()
+        .filter(|&&pass| passes.contains(pass))
+        .find_map(|&pass| {
+            let message = match pass {
+                OptimizePass::REMOVE_NULL => remove_null(graph),
+                OptimizePass::FOLD_SCALE => fold_scale(graph),
+                OptimizePass::MERGE_FORMAT => merge_format(graph),
+                OptimizePass::REMOVE_DEAD => {
+                    remove_dead(graph, passes.contains(OptimizePass::PRUNE_SPLIT))
+                }
+                OptimizePass::PRUNE_SPLIT => prune_split(graph),
+                _ => unreachable!(),
+            };
+            message.map(|message| Change { pass, message })
+        });
+        match change {
+            Some(change) => {
+                debug!("Optimized the filtergraph, {}.", change);
+                changes.push(change);
+            }
+            None => break,
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        graph_parser::parse_graph,
+        graph_printer::{print_graph, PrintStyle},
+    };
+
+    fn optimized(description: &str, passes: OptimizePass) -> (String, usize) {
+        let mut graph = parse_graph(description).unwrap();
+        let changes = optimize(&mut graph, passes);
+        (print_graph(&graph, PrintStyle::Compact), changes.len())
+    }
+
+    #[test]
+    fn parse_passes() {
+        assert_eq!(
+            OptimizePass::parse("remove_null+fold_scale"),
+            Ok(OptimizePass::REMOVE_NULL | OptimizePass::FOLD_SCALE)
+        );
+        assert_eq!(OptimizePass::parse("all"), Ok(OptimizePass::ALL));
+        assert_eq!(OptimizePass::parse(""), Ok(OptimizePass::NONE));
+        assert!(OptimizePass::parse("remove_null+foo").is_err());
+    }
+
+    #[test]
+    fn remove_null() {
+        let passes = OptimizePass::REMOVE_NULL;
+        assert_eq!(
+            optimized("[in]null,scale=320:240,null[out]", passes),
+            ("[in]scale=320:240[out]".into(), 2)
+        );
+        assert_eq!(
+            optimized("split[a][b];[a]null[c];[b][c]overlay", passes),
+            ("split[link0][link1];[link1][link0]overlay".into(), 1)
+        );
+        // Nothing is left between the input and the output.
+        assert_eq!(
+            optimized("[in]null[out]", passes),
+            ("[in]null[out]".into(), 0)
+        );
+        assert_eq!(
+            optimized("[a]null[a]", passes),
+            ("[link0]null[link0]".into(), 0)
+        );
+        assert_eq!(
+            optimized("[in]null,scale=320:240[out]", OptimizePass::FOLD_SCALE),
+            ("[in]null,scale=320:240[out]".into(), 0)
+        );
+    }
+
+    #[test]
+    fn fold_scale() {
+        let passes = OptimizePass::FOLD_SCALE;
+        assert_eq!(
+            optimized("scale=640:480,scale=w=320:h=240,scale=160:120", passes),
+            ("scale=160:120".into(), 2)
+        );
+        assert_eq!(
+            optimized("sws_flags=bicubic;scale=640:480,scale=320:240", passes),
+            ("sws_flags=bicubic;scale=320:240:flags=bicubic".into(), 1)
+        );
+        assert_eq!(
+            optimized("scale=640:480:flags=lanczos,scale=320:240", passes),
+            ("scale=640:480:flags=lanczos,scale=320:240".into(), 0)
+        );
+        assert_eq!(
+            optimized("scale=iw/2:-1,scale=320:240", passes),
+            ("scale=iw/2:-1,scale=320:240".into(), 0)
+        );
+    }
+
+    #[test]
+    fn merge_format() {
+        let passes = OptimizePass::MERGE_FORMAT;
+        assert_eq!(
+            optimized("format=yuv420p|rgb24,format=pix_fmts=gray|rgb24", passes),
+            ("format=rgb24".into(), 1)
+        );
+        // Converting to gray in between drops the colors.
+        assert_eq!(
+            optimized("format=gray,format=yuv420p", passes),
+            ("format=gray,format=yuv420p".into(), 0)
+        );
+    }
+
+    #[test]
+    fn remove_dead() {
+        let description = "[in]split[a][b];[a]scale=320:240[out];[b]null,nullsink";
+        assert_eq!(
+            optimized(
+                description,
+                OptimizePass::REMOVE_DEAD | OptimizePass::PRUNE_SPLIT
+            ),
+            ("[in]scale=320:240[out]".into(), 2)
+        );
+        // Without pruning, split outputs cannot be left unconnected.
+        assert_eq!(
+            optimized(description, OptimizePass::REMOVE_DEAD),
+            (
+                "[in]split[link0][link1];[link0]scale=320:240[out];[link1]null,nullsink".into(),
+                0
+            )
+        );
+        assert_eq!(
+            optimized(
+                "[in]scale=320:240[out];[x]null,nullsink",
+                OptimizePass::REMOVE_DEAD
+            ),
+            ("[in]scale=320:240[out]".into(), 1)
+        );
+        assert_eq!(
+            optimized("[in]null,nullsink", OptimizePass::REMOVE_DEAD),
+            ("[in]null,nullsink".into(), 0)
+        );
+    }
+}
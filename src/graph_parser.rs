@@ -1,62 +1,116 @@
 use log::{debug, error};
 use rusty_ffmpeg::ffi;
 
-use std::{ffi::CString, marker::PhantomData, slice};
+use std::{ffi::CString, fmt, fs, marker::PhantomData, path::PathBuf, slice};
 
-struct GraphParser<'buffer> {
+use crate::{
+    cmdutils::{did_you_mean, escape_c_bytes, escape_c_string, os_string, suggestions},
+    show,
+};
+
+pub(crate) struct GraphParser<'buffer> {
     ptr: *const u8,
     end: *const u8,
     _marker: PhantomData<&'buffer u8>,
 }
 
-#[derive(Debug, Default)]
-struct FilterGraph<'buffer> {
+#[derive(Debug, Default, PartialEq)]
+pub struct FilterGraph<'buffer> {
     // Used in filter creation
-    scale_sws_opts: Option<&'buffer [u8]>,
+    pub scale_sws_opts: Option<&'buffer [u8]>,
 }
 
-#[derive(Debug, Default)]
-struct FilterContext {
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FilterContext {
     /// index of the filter(0..num_filter)
-    index: usize,
+    pub index: usize,
 
     /// name of the filter
-    filt_name: String,
+    pub filt_name: String,
 
     /// name of the filter instance
-    inst_name: String,
+    pub inst_name: String,
 
     /// currently not used, maybe used later when graph is lazy initialized.
-    args: String,
+    /// Bytes, as FFmpeg doesn't require the arguments to be UTF-8.
+    pub args: Vec<u8>,
 
     /// Used in input and output linking
-    nb_inputs: usize,
-    nb_outputs: usize,
+    pub nb_inputs: usize,
+    pub nb_outputs: usize,
 }
 
-struct FilterLink {
-    from_filter: usize,
-    from_pad_idx: usize,
-    to_filter: usize,
-    to_pad_idx: usize,
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterLink {
+    pub from_filter: usize,
+    pub from_pad_idx: usize,
+    pub to_filter: usize,
+    pub to_pad_idx: usize,
 }
 
 /// Customized version of `AVFilterInOut` for convenient purpose
-#[derive(Debug, Clone)]
-struct FilterInOut<'buffer> {
-    name: Option<&'buffer [u8]>,
-    pad_idx: usize,
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterInOut<'buffer> {
+    pub name: Option<&'buffer [u8]>,
+    pub pad_idx: usize,
     /// Index of filter in the filter array, is None when it is an unlinked input
-    filter_ctx: Option<usize>,
+    pub filter_ctx: Option<usize>,
+}
+
+/// Everything `avfilter_graph_parse2()` produces, kept around so that it can be
+/// serialized into code or back into filtergraph text.
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedGraph<'buffer> {
+    pub graph: FilterGraph<'buffer>,
+    pub filters: Vec<FilterContext>,
+    pub links: Vec<FilterLink>,
+    pub open_inputs: Vec<FilterInOut<'buffer>>,
+    pub open_outputs: Vec<FilterInOut<'buffer>>,
+}
+
+/// Why `parse_graph()` rejected the filtergraph description.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    /// The filter, and the registered filters close to its name.
+    NoSuchFilter(String, Vec<String>),
+    /// Any other error, with its message.
+    Invalid(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NoSuchFilter(filt_name, suggestions) => write!(
+                f,
+                "No such filter: '{}'.{}",
+                filt_name,
+                did_you_mean(suggestions)
+            ),
+            GraphError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Log the error before returning it, as FFmpeg logs where it fails.
+fn graph_error(err: GraphError) -> GraphError {
+    error!("{}", err);
+    err
+}
+
+/// The unknown filter with the registered filters close to its name, logged.
+pub(crate) fn no_such_filter(filt_name: &str) -> GraphError {
+    let filters = unsafe { show::filters() };
+    let suggestions = suggestions(filt_name, filters.iter().map(|x| x.name.as_str()));
+    graph_error(GraphError::NoSuchFilter(filt_name.into(), suggestions))
 }
 
 impl<'buffer> GraphParser<'buffer> {
-    fn new(bytes: &'buffer str) -> Self {
+    pub(crate) fn new<S: AsRef<[u8]> + ?Sized>(bytes: &'buffer S) -> Self {
+        let bytes = bytes.as_ref();
         let ptr = bytes.as_ptr();
         unsafe {
             Self {
                 ptr,
-                // length of &str is length of inner bytes array
                 end: ptr.add(bytes.len()),
                 _marker: PhantomData,
             }
@@ -71,14 +125,15 @@ impl<'buffer> GraphParser<'buffer> {
         })
     }
 
-    fn peek(&self) -> Option<u8> {
-        (self.ptr < self.end).then_some(unsafe { *self.ptr })
+    pub(crate) fn peek(&self) -> Option<u8> {
+        // `then()` rather than `then_some()`: `self.ptr` cannot be dereferenced at the end.
+        (self.ptr < self.end).then(|| unsafe { *self.ptr })
     }
 
-    fn peek_len(&self, len: usize) -> Option<&'buffer [u8]> {
+    pub(crate) fn peek_len(&self, len: usize) -> Option<&'buffer [u8]> {
         unsafe {
             (self.end.offset_from(self.ptr) as usize >= len)
-                .then_some(slice::from_raw_parts(self.ptr, len))
+                .then(|| slice::from_raw_parts(self.ptr, len))
         }
     }
 
@@ -101,7 +156,7 @@ impl<'buffer> GraphParser<'buffer> {
         })
     }
 
-    fn peek_until_end<F>(&self, f: F) -> &'buffer [u8]
+    pub(crate) fn peek_until_end<F>(&self, f: F) -> &'buffer [u8]
     where
         F: Fn(u8) -> bool,
     {
@@ -113,11 +168,11 @@ impl<'buffer> GraphParser<'buffer> {
         unsafe { slice::from_raw_parts(self.ptr, it.offset_from(self.ptr) as usize) }
     }
 
-    fn remaining(&self) -> &'buffer [u8] {
+    pub(crate) fn remaining(&self) -> &'buffer [u8] {
         self.peek_until_end(|_| false)
     }
 
-    fn skip_ws(&mut self) {
+    pub(crate) fn skip_ws(&mut self) {
         let mut it = self.ptr;
         while it < self.end {
             match unsafe { *it } {
@@ -128,12 +183,53 @@ impl<'buffer> GraphParser<'buffer> {
         self.ptr = it;
     }
 
-    fn skip(&mut self, i: usize) {
+    pub(crate) fn skip(&mut self, i: usize) {
         let dest = unsafe { self.ptr.add(i) };
         self.ptr = if dest <= self.end { dest } else { self.end };
     }
 
-    fn parse_sws_flags(&mut self, graph: &mut FilterGraph<'buffer>) -> Result<(), ()> {
+    /// Equivalent of `av_get_token()`: read until one of `term` is met, with
+    /// `\` escaping the next char and `'` quoting everything until the next
+    /// `'`. Leading and trailing whitespaces which are not escaped or quoted
+    /// are removed.
+    pub(crate) fn get_token(&mut self, term: &[u8]) -> Vec<u8> {
+        self.skip_ws();
+
+        let mut token = vec![];
+        // Whitespaces before this are escaped or quoted, so they are kept.
+        let mut end = 0;
+        while let Some(x) = self.peek() {
+            if term.contains(&x) {
+                break;
+            }
+            self.skip(1);
+            match x {
+                b'\\' if self.peek().is_some() => {
+                    token.push(self.get().unwrap());
+                    end = token.len();
+                }
+                b'\'' => {
+                    let quoted = self.peek_until_end(|x| x == b'\'');
+                    token.extend_from_slice(quoted);
+                    self.skip(quoted.len());
+                    if self.get().is_some() {
+                        end = token.len();
+                    }
+                }
+                _ => token.push(x),
+            }
+        }
+
+        while token.len() > end {
+            match token.last() {
+                Some(b' ') | Some(b'\n') | Some(b'\r') | Some(b'\t') => token.pop(),
+                _ => break,
+            };
+        }
+        token
+    }
+
+    fn parse_sws_flags(&mut self, graph: &mut FilterGraph<'buffer>) -> Result<(), GraphError> {
         // IMPROVEMENT reorganize the processing flow than the original FFmpeg
         if self.peek_len(10) != Some(b"sws_flags=") {
             return Ok(());
@@ -145,8 +241,9 @@ impl<'buffer> GraphParser<'buffer> {
         let p = if let Some(x) = self.peek_until(|x| x == b';') {
             x
         } else {
-            error!("sws_flags not terminated with ';'.");
-            return Err(());
+            return Err(graph_error(GraphError::Invalid(
+                "sws_flags not terminated with ';'.".into(),
+            )));
         };
 
         graph.scale_sws_opts = Some(p);
@@ -159,7 +256,7 @@ impl<'buffer> GraphParser<'buffer> {
         &mut self,
         curr_inputs: &mut Vec<FilterInOut<'buffer>>,
         open_outputs: &mut Vec<FilterInOut<'buffer>>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), GraphError> {
         let mut parsed_inputs = vec![];
 
         for pad in 0.. {
@@ -170,7 +267,7 @@ impl<'buffer> GraphParser<'buffer> {
 
             let name = match self.peek_until(|x| x == b']') {
                 Some(x) => x,
-                None => return Err(()),
+                None => return Err(unterminated_label(self.remaining())),
             };
 
             self.skip(name.len() + 1);
@@ -203,7 +300,7 @@ impl<'buffer> GraphParser<'buffer> {
         name: &[u8],
         args: &[u8],
         index: usize,
-    ) -> Option<FilterContext> {
+    ) -> Result<FilterContext, GraphError> {
         let mut inst_name = format!("Parsed_{}_{}", String::from_utf8_lossy(name), index);
         let mut filt_name = String::from(String::from_utf8_lossy(name));
         if let Some(index) = name
@@ -219,30 +316,48 @@ impl<'buffer> GraphParser<'buffer> {
             }
         }
 
+        // Strings with inner NUL bytes cannot be passed to FFmpeg.
+        let (filt_name_c, inst_name_c) = match (
+            CString::new(filt_name.clone()),
+            CString::new(inst_name.clone()),
+        ) {
+            (Ok(filt_name_c), Ok(inst_name_c)) => (filt_name_c, inst_name_c),
+            _ => {
+                return Err(graph_error(GraphError::Invalid(format!(
+                    "Filter name '{}' contains a NUL byte.",
+                    inst_name
+                ))));
+            }
+        };
+
         let filt = {
-            let filt_name_c = CString::new(filt_name.clone()).unwrap();
             let filt = unsafe { ffi::avfilter_get_by_name(filt_name_c.as_ptr()) };
             if filt.is_null() {
-                error!("No such filter: '{}'", filt_name);
-                return None;
+                return Err(no_such_filter(&filt_name));
             }
             filt
         };
 
-        let args = {
-            let args: String = String::from_utf8_lossy(args).into();
-            if filt_name == "scale"
-                && (args.is_empty() || !args.contains("flags"))
-                && ctx.scale_sws_opts.is_some()
+        let args = match ctx.scale_sws_opts {
+            Some(scale_sws_opts)
+                if filt_name == "scale" && !args.windows(5).any(|x| x == b"flags") =>
             {
-                let scale_sws_opts = String::from_utf8_lossy(ctx.scale_sws_opts.unwrap());
                 if args.is_empty() {
-                    scale_sws_opts.into()
+                    scale_sws_opts.to_vec()
                 } else {
-                    format!("{}:{}", args, scale_sws_opts)
+                    [args, b":", scale_sws_opts].concat()
                 }
-            } else {
-                args
+            }
+            _ => args.to_vec(),
+        };
+
+        let args_c = match CString::new(without_file_options(&args)) {
+            Ok(x) => x,
+            Err(_) => {
+                return Err(graph_error(GraphError::Invalid(format!(
+                    "Args of filter '{}' contain a NUL byte.",
+                    filt_name
+                ))));
             }
         };
 
@@ -255,16 +370,15 @@ impl<'buffer> GraphParser<'buffer> {
         // the nb_inputs and nb_outputs can be changed with `avfilter_init_str`
         // with or without specific args.
         let (nb_inputs, nb_outputs) = unsafe {
-            let inst_name_c = CString::new(inst_name.clone()).unwrap();
-            let args_c = CString::new(args.clone()).unwrap();
-
             let graph = ffi::avfilter_graph_alloc().as_mut().unwrap();
             graph.nb_threads = 1;
             let filt_ctx =
                 ffi::avfilter_graph_alloc_filter(graph as *mut _, filt, inst_name_c.as_ptr());
             if filt_ctx.is_null() {
-                error!("Error creating filter '{}'\n", filt_name);
-                return None;
+                return Err(graph_error(GraphError::Invalid(format!(
+                    "Error creating filter '{}'",
+                    filt_name
+                ))));
             }
             let ret = ffi::avfilter_init_str(filt_ctx, args_c.as_ptr());
             if ret < 0 {
@@ -273,14 +387,15 @@ impl<'buffer> GraphParser<'buffer> {
                 } else {
                     error!(
                         "Error initializing filter '{}' with args '{}'",
-                        filt_name, args
+                        filt_name,
+                        String::from_utf8_lossy(&args)
                     );
                 }
             }
             let filt_ctx = filt_ctx.as_ref().unwrap();
             (filt_ctx.nb_inputs as usize, filt_ctx.nb_outputs as usize)
         };
-        Some(FilterContext {
+        Ok(FilterContext {
             index,
             filt_name,
             inst_name: inst_name.clone(),
@@ -295,49 +410,17 @@ impl<'buffer> GraphParser<'buffer> {
         index: usize,
         filt_ctx: &mut FilterContext,
         graph: &mut FilterGraph,
-    ) -> Result<(), ()> {
-        let name = self.peek_until_end(|x| match x {
-            b'=' | b',' | b';' | b'[' => true,
-            _ => false,
-        });
-        self.skip(name.len());
+    ) -> Result<(), GraphError> {
+        let name = self.get_token(b"=,;[");
 
         let opts = if self.peek() == Some(b'=') {
             self.skip(1);
-
-            let opts = self.peek_until_end(|x| match x {
-                b'[' | b']' | b',' | b';' => true,
-                _ => false,
-            });
-
-            self.skip(opts.len());
-
-            opts
+            self.get_token(b"[],;")
         } else {
-            b""
-        };
-
-        let trim = |s: &[u8]| {
-            let begin = (0..s.len()).find(|&i| match s[i] {
-                b' ' | b'\n' | b'\t' => false,
-                _ => true,
-            });
-            let end = (0..s.len()).rev().find(|&i| match s[i] {
-                b' ' | b'\n' | b'\t' => false,
-                _ => true,
-            });
-            match (begin, end) {
-                (Some(begin), Some(end)) => s[begin..=end].to_vec(),
-                _ => vec![],
-            }
+            vec![]
         };
 
-        let (name, opts) = (trim(name), trim(opts));
-
-        *filt_ctx = match Self::create_filter(graph, &name, &opts, index) {
-            Some(x) => x,
-            None => return Err(()),
-        };
+        *filt_ctx = Self::create_filter(graph, &name, &opts, index)?;
 
         Ok(())
     }
@@ -348,7 +431,7 @@ impl<'buffer> GraphParser<'buffer> {
         filt_ctx: &mut FilterContext,
         curr_inputs: &mut Vec<FilterInOut<'buffer>>,
         open_inputs: &mut Vec<FilterInOut<'buffer>>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), GraphError> {
         for pad in 0..filt_ctx.nb_inputs {
             let mut p = if curr_inputs.is_empty() {
                 FilterInOut {
@@ -375,11 +458,10 @@ impl<'buffer> GraphParser<'buffer> {
         }
 
         if !curr_inputs.is_empty() {
-            error!(
+            return Err(graph_error(GraphError::Invalid(format!(
                 r#"Too many inputs specified for the "{}" filter."#,
                 filt_ctx.filt_name
-            );
-            return Err(());
+            ))));
         }
 
         for pad in 0..filt_ctx.nb_outputs {
@@ -400,7 +482,7 @@ impl<'buffer> GraphParser<'buffer> {
         curr_inputs: &mut Vec<FilterInOut<'buffer>>,
         open_inputs: &mut Vec<FilterInOut<'buffer>>,
         open_outputs: &mut Vec<FilterInOut<'buffer>>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), GraphError> {
         // BTW, the `curr_inputs` is actually `curr_outputs`.
         loop {
             if self.peek() != Some(b'[') {
@@ -410,17 +492,16 @@ impl<'buffer> GraphParser<'buffer> {
 
             let name = match self.peek_until(|x| x == b']') {
                 Some(x) => x,
-                None => return Err(()),
+                None => return Err(unterminated_label(self.remaining())),
             };
 
             self.skip(name.len() + 1);
 
             let mut input = if curr_inputs.is_empty() {
-                error!(
+                return Err(graph_error(GraphError::Invalid(format!(
                     "No output pad can be associated to link label '{}'.",
                     String::from_utf8_lossy(name)
-                );
-                return Err(());
+                ))));
             } else {
                 curr_inputs.remove(0)
             };
@@ -453,7 +534,129 @@ impl<'buffer> GraphParser<'buffer> {
     }
 }
 
-pub fn avfilter_graph_parse2(filters: &str) -> Result<(), ()> {
+/// A `[` without its `]`, followed by `remaining`.
+fn unterminated_label(remaining: &[u8]) -> GraphError {
+    graph_error(GraphError::Invalid(format!(
+        r#"Mismatched '[' found in the following: "{}"."#,
+        String::from_utf8_lossy(remaining)
+    )))
+}
+
+/// Split at the `sep` which are neither escaped with `\\` nor quoted with `'`.
+fn split_unescaped(bytes: &[u8], sep: u8) -> Vec<&[u8]> {
+    let mut parts = vec![];
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, &x) in bytes.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if x == b'\\' && !quoted {
+            escaped = true;
+        } else if x == b'\'' {
+            quoted = !quoted;
+        } else if x == sep && !quoted {
+            parts.push(&bytes[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&bytes[start..]);
+    parts
+}
+
+/// Escape an option value so that the option parser of the filter reads it
+/// back exactly.
+fn escape_option_value(value: &[u8]) -> Vec<u8> {
+    let is_ws = |x: u8| matches!(x, b' ' | b'\n' | b'\r' | b'\t');
+    let mut escaped = Vec::with_capacity(value.len());
+    for (i, &x) in value.iter().enumerate() {
+        // Whitespaces are only removed at both ends.
+        if matches!(x, b'\\' | b'\'' | b':') || (is_ws(x) && (i == 0 || i + 1 == value.len())) {
+            escaped.push(b'\\');
+        }
+        escaped.push(x);
+    }
+    escaped
+}
+
+/// The key and the path of an option whose value is read from a file. Since
+/// FFmpeg 6.1, that is an option name prefixed with `/`, e.g.
+/// `drawtext=/text=caption.txt`. Values without key are never loaded from
+/// files.
+fn file_option(opt: &[u8]) -> Option<(&[u8], &[u8])> {
+    let trimmed = match opt.iter().position(|x| !b" \n\r\t".contains(x)) {
+        Some(start) if opt[start] == b'/' => &opt[start + 1..],
+        _ => return None,
+    };
+    match split_unescaped(trimmed, b'=')[..] {
+        [key, ..] if key.len() < trimmed.len() => Some((key, &trimmed[key.len() + 1..])),
+        _ => None,
+    }
+}
+
+/// The args without the options read from files, which libavfilter doesn't
+/// know when the filter is created to count its pads.
+fn without_file_options(args: &[u8]) -> Vec<u8> {
+    let opts: Vec<&[u8]> = split_unescaped(args, b':')
+        .into_iter()
+        .filter(|opt| file_option(opt).is_none())
+        .collect();
+    opts.join(&b':')
+}
+
+/// Read the files of the options of the args, and replace each option with
+/// one setting the content of its file.
+fn load_files(args: &[u8]) -> Result<Vec<u8>, GraphError> {
+    let mut loaded = vec![];
+    for (i, opt) in split_unescaped(args, b':').into_iter().enumerate() {
+        if i > 0 {
+            loaded.push(b':');
+        }
+        let (key, path) = match file_option(opt) {
+            Some(x) => x,
+            None => {
+                loaded.extend_from_slice(opt);
+                continue;
+            }
+        };
+
+        let key = GraphParser::new(key).get_token(b"");
+        let path = PathBuf::from(os_string(GraphParser::new(path).get_token(b"")));
+        let content = match fs::read(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                return Err(graph_error(GraphError::Invalid(format!(
+                    "Cannot load the value of option '{}' from file '{}': {}",
+                    String::from_utf8_lossy(&key),
+                    path.display(),
+                    e
+                ))));
+            }
+        };
+        debug!(
+            "Loaded the value of option '{}' from file '{}'.",
+            String::from_utf8_lossy(&key),
+            path.display()
+        );
+        loaded.extend_from_slice(&key);
+        loaded.push(b'=');
+        loaded.extend_from_slice(&escape_option_value(&content));
+    }
+    Ok(loaded)
+}
+
+/// Load the values of the options prefixed with `/` from their files, as
+/// FFmpeg 6.1+ does, so that the generated code sets their content. Only done
+/// when generating code, `parse_graph()` keeps the options as written for
+/// `fmt` and `diff`.
+pub fn load_file_options(parsed: &mut ParsedGraph) -> Result<(), GraphError> {
+    for filter in parsed.filters.iter_mut() {
+        filter.args = load_files(&filter.args)?;
+    }
+    Ok(())
+}
+
+/// Parse a filtergraph description, which like for FFmpeg doesn't have to be
+/// UTF-8.
+pub fn parse_graph<S: AsRef<[u8]> + ?Sized>(filters: &S) -> Result<ParsedGraph<'_>, GraphError> {
     let mut graph = FilterGraph::default();
 
     let mut parser = GraphParser::new(filters);
@@ -463,7 +666,7 @@ pub fn avfilter_graph_parse2(filters: &str) -> Result<(), ()> {
 
     parser.skip_ws();
 
-    parser.parse_sws_flags(&mut graph).unwrap();
+    parser.parse_sws_flags(&mut graph)?;
 
     let mut curr_inputs = vec![];
     let mut open_inputs = vec![];
@@ -506,11 +709,10 @@ pub fn avfilter_graph_parse2(filters: &str) -> Result<(), ()> {
                 parser.skip(1)
             }
             Some(_) => {
-                error!(
+                return Err(graph_error(GraphError::Invalid(format!(
                     r#"Unable to parse graph description substring: "{}""#,
                     String::from_utf8_lossy(parser.remaining())
-                );
-                return Err(());
+                ))));
             }
             None => break,
         }
@@ -518,6 +720,33 @@ pub fn avfilter_graph_parse2(filters: &str) -> Result<(), ()> {
 
     open_outputs.append(&mut curr_inputs);
 
+    Ok(ParsedGraph {
+        graph,
+        filters,
+        links,
+        open_inputs,
+        open_outputs,
+    })
+}
+
+pub fn avfilter_graph_parse2(filters: &str) -> Result<(), GraphError> {
+    let mut parsed = parse_graph(filters)?;
+    load_file_options(&mut parsed)?;
+    graph_serialization(&parsed);
+    Ok(())
+}
+
+/// Print the code creating the parsed graph, which can be modified after
+/// parsing, e.g. optimized.
+pub fn graph_serialization(parsed: &ParsedGraph) {
+    let ParsedGraph {
+        graph,
+        filters,
+        links,
+        open_inputs,
+        open_outputs,
+    } = parsed;
+
     let scale_sws_opts_serialization = |graph: &FilterGraph| {
         if let Some(scale_sws_opts) = graph.scale_sws_opts {
             let size = scale_sws_opts.len() + 1;
@@ -529,7 +758,7 @@ if (!(graph->scale_sws_opts = av_mallocz({})))
 av_strlcpy(graph->scale_sws_opts, "{}", {});
 "#,
                 size,
-                String::from_utf8_lossy(scale_sws_opts),
+                escape_c_bytes(scale_sws_opts),
                 size,
             );
         }
@@ -549,12 +778,12 @@ if (!{}) {{
 avfilter_init_str({}, "{}");
 "#,
             code_name,
-            filter.filt_name,
-            filter.inst_name,
+            escape_c_string(&filter.filt_name),
+            escape_c_string(&filter.inst_name),
             code_name,
-            filter.filt_name,
+            escape_c_string(&filter.filt_name),
             code_name,
-            filter.args,
+            escape_c_bytes(&filter.args),
         );
     };
 
@@ -599,7 +828,9 @@ if (!({} = av_mallocz(sizeof(AVFilterInOut)))) {{
                 code_name,
                 inout.pad_idx,
                 code_name,
-                filters_code_name[inout.filter_ctx.unwrap()]
+                inout
+                    .filter_ctx
+                    .map_or("NULL", |i| filters_code_name[i].as_str())
             );
         };
 
@@ -612,7 +843,7 @@ if (!({} = av_mallocz(sizeof(AVFilterInOut)))) {{
         );
     };
 
-    scale_sws_opts_serialization(&graph);
+    scale_sws_opts_serialization(graph);
 
     let filters_code_name = filters
         .iter()
@@ -665,7 +896,9 @@ if (!({} = av_mallocz(sizeof(AVFilterInOut)))) {{
 *inputs = {};
 *outputs = {};
 "#,
-        inputs_code_name[0], outputs_code_name[0]
+        // The graph can have no open input or output, e.g. `nullsrc,nullsink`.
+        inputs_code_name.first().map_or("NULL", String::as_str),
+        outputs_code_name.first().map_or("NULL", String::as_str),
     );
 
     for i in 1..inputs_code_name.len() {
@@ -676,13 +909,12 @@ if (!({} = av_mallocz(sizeof(AVFilterInOut)))) {{
     for i in 1..outputs_code_name.len() {
         inout_link_serialization(&outputs_code_name[i - 1], &outputs_code_name[i]);
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn get() {
@@ -782,6 +1014,28 @@ mod test {
         assert_eq!(Some(b'a'), p.peek());
     }
 
+    #[test]
+    fn get_token() {
+        let mut p = GraphParser::new("  scale = 720:480 [out]");
+        assert_eq!(p.get_token(b"=,;["), b"scale");
+        p.skip(1);
+        assert_eq!(p.get_token(b"[],;"), b"720:480");
+        assert_eq!(p.peek(), Some(b'['));
+
+        let mut p = GraphParser::new("select='eq(n,0)' , null");
+        p.skip(7);
+        assert_eq!(p.get_token(b"[],;"), b"eq(n,0)");
+        assert_eq!(p.peek(), Some(b','));
+
+        let mut p = GraphParser::new(r"a\,b\ ' c ' ;");
+        assert_eq!(p.get_token(b"[],;"), b"a,b  c ");
+        assert_eq!(p.peek(), Some(b';'));
+
+        let mut p = GraphParser::new(r"'unterminated, \");
+        assert_eq!(p.get_token(b"[],;"), br"unterminated, \");
+        assert_eq!(p.peek(), None);
+    }
+
     #[test]
     fn sws_flags() {
         let graph = &mut FilterGraph::default();
@@ -847,7 +1101,7 @@ mod test {
         assert_eq!(filter.index, 42);
         assert_eq!(filter.filt_name, "split");
         assert_eq!(filter.inst_name, "Parsed_split_42");
-        assert_eq!(filter.args, "");
+        assert_eq!(filter.args, b"");
         assert_eq!(filter.nb_inputs, 1);
         assert_eq!(filter.nb_outputs, 2);
     }
@@ -863,7 +1117,7 @@ mod test {
         assert_eq!(filter.index, 0);
         assert_eq!(filter.filt_name, "scale");
         assert_eq!(filter.inst_name, "Parsed_scale_0");
-        assert_eq!(filter.args, "flags=+accurate_rnd+bitexact");
+        assert_eq!(filter.args, b"flags=+accurate_rnd+bitexact");
         assert_eq!(filter.nb_inputs, 1);
         assert_eq!(filter.nb_outputs, 1);
     }
@@ -877,7 +1131,7 @@ mod test {
         assert_eq!(filter.index, 666);
         assert_eq!(filter.filt_name, "overlay");
         assert_eq!(filter.inst_name, "Parsed_overlay_666");
-        assert_eq!(filter.args, "5:5");
+        assert_eq!(filter.args, b"5:5");
         assert_eq!(filter.nb_inputs, 2);
         assert_eq!(filter.nb_outputs, 1);
 
@@ -890,7 +1144,7 @@ mod test {
         assert_eq!(filter.index, 666);
         assert_eq!(filter.filt_name, "scale");
         assert_eq!(filter.inst_name, "Parsed_scale_666");
-        assert_eq!(filter.args, "5:5:flags=+accurate_rnd+bitexact");
+        assert_eq!(filter.args, b"5:5:flags=+accurate_rnd+bitexact");
         assert_eq!(filter.nb_inputs, 1);
         assert_eq!(filter.nb_outputs, 1);
     }
@@ -914,7 +1168,7 @@ mod test {
         assert_eq!(filter.index, 666);
         assert_eq!(filter.filt_name, "overlay");
         assert_eq!(filter.inst_name, "Parsed_overlay_666");
-        assert_eq!(filter.args, "5:5");
+        assert_eq!(filter.args, b"5:5");
         assert_eq!(filter.nb_inputs, 2);
         assert_eq!(filter.nb_outputs, 1);
 
@@ -1031,4 +1285,84 @@ mod test {
         )
         .is_err());
     }
+
+    #[test]
+    fn unknown_filters() {
+        match parse_graph("[in]scal=720:480[out]") {
+            Err(GraphError::NoSuchFilter(filt_name, suggestions)) => {
+                assert_eq!(filt_name, "scal");
+                assert!(suggestions.iter().any(|x| x == "scale"));
+            }
+            ret => panic!("{:?}", ret),
+        }
+        assert!(matches!(parse_graph("[in"), Err(GraphError::Invalid(_))));
+
+        let error = GraphError::NoSuchFilter("scal".into(), vec!["scale".into()]);
+        assert_eq!(
+            error.to_string(),
+            "No such filter: 'scal'. Did you mean 'scale'?"
+        );
+    }
+
+    #[test]
+    fn fuzz_regressions() {
+        // Used to panic on unwrapping the error of `parse_sws_flags()`.
+        assert!(parse_graph("sws_flags=bicubic").is_err());
+
+        // Used to panic on converting them into `CString`.
+        assert!(parse_graph("nu\0ll").is_err());
+        assert!(parse_graph("null@\0").is_err());
+        assert!(parse_graph("scale=1\0:1").is_err());
+        assert!(parse_graph("sws_flags=\0;scale").is_err());
+
+        // Used to read past the end of the buffer, which crashes when it's empty.
+        assert!(parse_graph("").is_err());
+        let p = GraphParser::new("");
+        assert_eq!(p.peek(), None);
+        assert_eq!(p.peek_len(10), None);
+
+        // Used to panic on indexing the empty open inputs and outputs.
+        assert!(avfilter_graph_parse2("nullsrc,nullsink").is_ok());
+
+        // Used to panic on unwrapping the `filter_ctx`.
+        let mut graph = parse_graph("null").unwrap();
+        graph.open_inputs[0].filter_ctx = None;
+        graph_serialization(&graph);
+    }
+
+    #[test]
+    fn file_options() {
+        for &value in ["it's 10:00\n", " a\\b ", ""].iter() {
+            let escaped = escape_option_value(value.as_bytes());
+            assert_eq!(GraphParser::new(&escaped).get_token(b":"), value.as_bytes());
+        }
+
+        let dir = TempDir::new("file_options");
+        let path = dir.join("expr.txt");
+        fs::write(&path, "it's 10:00\n").unwrap();
+        let description = format!("select=e=1:/expr={}", path.display());
+        let mut graph = parse_graph(&description).unwrap();
+        // Parsing keeps the option as written, the file is read for the code.
+        assert_eq!(graph.filters[0].args, &description.as_bytes()[7..]);
+        assert!(load_file_options(&mut graph).is_ok());
+        assert_eq!(graph.filters[0].args, b"e=1:expr=it\\'s 10\\:00\\\n");
+
+        let mut graph = parse_graph("select=/expr=/nonexistent/ffgen_file_options.txt").unwrap();
+        assert!(load_file_options(&mut graph).is_err());
+        // Only the values of options with a key are loaded from files.
+        let mut graph = parse_graph("movie=/nonexistent/video.mp4").unwrap();
+        assert!(load_file_options(&mut graph).is_ok());
+        assert_eq!(graph.filters[0].args, b"/nonexistent/video.mp4");
+
+        assert_eq!(without_file_options(b"e=1:/expr=a.txt:n=2"), b"e=1:n=2");
+        assert_eq!(without_file_options(b"/expr=a.txt"), b"");
+    }
+
+    #[test]
+    fn non_utf8_args() {
+        // "é" in Latin-1, which FFmpeg gives to the filter as it is.
+        let graph = parse_graph(&b"select=expr=\xe9"[..]).unwrap();
+        assert_eq!(graph.filters[0].args, b"expr=\xe9");
+        assert_eq!(escape_c_bytes(&graph.filters[0].args), "expr=\\351");
+    }
 }
@@ -0,0 +1,359 @@
+//! Serialize a parsed filtergraph back into filtergraph text.
+//!
+//! Parsing the printed text with `parse_graph()` gives a graph identical to
+//! the one which is printed: same filters, same links and same open inputs and
+//! outputs, all in the same order. Labels of the links between filters are not
+//! kept by the parser, so they are generated.
+use log::{error, info};
+
+use std::collections::HashSet;
+
+use crate::{
+    graph_optimizer::{optimize, OptimizePass},
+    graph_parser::{parse_graph, FilterContext, FilterInOut, FilterLink, ParsedGraph},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrintStyle {
+    /// The whole graph in one line without any whitespace.
+    Compact,
+    /// One filter per line, with the filters following the first one of a
+    /// chain indented.
+    Pretty,
+}
+
+/// What a filter pad is connected to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pad<'a> {
+    /// Index of the link in `ParsedGraph::links`.
+    Link(usize),
+    /// Open input or output, with its label if it has one.
+    Open(Option<&'a [u8]>),
+}
+
+fn is_ws(x: u8) -> bool {
+    matches!(x, b' ' | b'\n' | b'\r' | b'\t')
+}
+
+/// Quote `token` so that `av_get_token()` with the `term` terminators reads
+/// exactly `token` back.
+fn quote(token: &str, term: &[u8]) -> String {
+    let special = |x: u8| term.contains(&x) || x == b'\\' || x == b'\'';
+    let bytes = token.as_bytes();
+    let need_quote = bytes.iter().any(|&x| special(x))
+        || matches!(bytes.first(), Some(&x) if is_ws(x))
+        || matches!(bytes.last(), Some(&x) if is_ws(x));
+
+    if !need_quote {
+        token.into()
+    } else if !token.contains('\'') {
+        format!("'{}'", token)
+    } else {
+        let mut quoted = String::with_capacity(token.len() * 2);
+        for c in token.chars() {
+            if c.is_ascii() && (special(c as u8) || is_ws(c as u8)) {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted
+    }
+}
+
+/// Name used for the filter in the graph description, which is the instance
+/// name when it's given with `@`.
+fn filter_name(filter: &FilterContext) -> &str {
+    if filter.inst_name == format!("Parsed_{}_{}", filter.filt_name, filter.index) {
+        &filter.filt_name
+    } else {
+        &filter.inst_name
+    }
+}
+
+/// Number of unlabeled pads at the end of `pads`.
+fn unlabeled_tail(pads: &[Pad]) -> usize {
+    pads.iter()
+        .rev()
+        .take_while(|&&pad| pad == Pad::Open(None))
+        .count()
+}
+
+/// Mark the pads connected to open inputs or outputs.
+fn mark_open<'a>(pads: &mut [Vec<Pad<'a>>], inouts: &[FilterInOut<'a>]) {
+    for inout in inouts.iter() {
+        if let Some(pad) = inout
+            .filter_ctx
+            .and_then(|i| pads.get_mut(i))
+            .and_then(|pads| pads.get_mut(inout.pad_idx))
+        {
+            *pad = Pad::Open(inout.name);
+        }
+    }
+}
+
+/// Number of output pads of a filter which can be chained with `,` into the
+/// first input pads of the `next` filter, None if they cannot be chained.
+///
+/// When chaining, the unlabeled outputs are linked to the first inputs of the
+/// next filter, and the inputs left become unlabeled open inputs. The next
+/// filter can have labeled inputs too, but it's confusing so we don't do that.
+fn chained_pads(
+    links: &[FilterLink],
+    next: usize,
+    outputs: &[Pad],
+    inputs: &[Pad],
+) -> Option<usize> {
+    if outputs.contains(&Pad::Open(None)) {
+        return None;
+    }
+    let n = outputs
+        .iter()
+        .rev()
+        .take(inputs.len())
+        .take_while(|&&pad| match pad {
+            Pad::Link(i) => links[i].to_filter == next,
+            Pad::Open(_) => false,
+        })
+        .count();
+    let tail = &outputs[outputs.len() - n..];
+
+    let consecutive = tail.iter().enumerate().all(|(k, pad)| match *pad {
+        Pad::Link(i) => links[i].to_pad_idx == k,
+        Pad::Open(_) => false,
+    });
+    let rest_unlabeled = inputs[n..].iter().all(|&pad| pad == Pad::Open(None));
+
+    (n > 0 && consecutive && rest_unlabeled).then_some(n)
+}
+
+pub fn print_graph(graph: &ParsedGraph, style: PrintStyle) -> String {
+    let filters = &graph.filters;
+    let links = &graph.links;
+
+    let mut inputs: Vec<Vec<Pad>> = filters
+        .iter()
+        .map(|filter| vec![Pad::Open(None); filter.nb_inputs])
+        .collect();
+    let mut outputs: Vec<Vec<Pad>> = filters
+        .iter()
+        .map(|filter| vec![Pad::Open(None); filter.nb_outputs])
+        .collect();
+
+    for (i, link) in links.iter().enumerate() {
+        if let Some(pad) = outputs
+            .get_mut(link.from_filter)
+            .and_then(|pads| pads.get_mut(link.from_pad_idx))
+        {
+            *pad = Pad::Link(i);
+        }
+        if let Some(pad) = inputs
+            .get_mut(link.to_filter)
+            .and_then(|pads| pads.get_mut(link.to_pad_idx))
+        {
+            *pad = Pad::Link(i);
+        }
+    }
+    mark_open(&mut inputs, &graph.open_inputs);
+    mark_open(&mut outputs, &graph.open_outputs);
+
+    // chained[i] is the number of output pads of filter i chained into filter i + 1.
+    let chained: Vec<Option<usize>> = (0..filters.len())
+        .map(|i| {
+            if i + 1 < filters.len() {
+                chained_pads(links, i + 1, &outputs[i], &inputs[i + 1])
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Generated labels shouldn't be mistaken for labels of open inputs and outputs.
+    let used: HashSet<&[u8]> = graph
+        .open_inputs
+        .iter()
+        .chain(graph.open_outputs.iter())
+        .filter_map(|inout| inout.name)
+        .collect();
+    let mut counter = 0;
+    let mut new_label = || loop {
+        let label = format!("link{}", counter);
+        counter += 1;
+        if !used.contains(label.as_bytes()) {
+            break label;
+        }
+    };
+    // Labels of links are generated when they are firstly printed.
+    let mut link_labels: Vec<Option<String>> = vec![None; links.len()];
+    let mut label = |pad: &Pad| match *pad {
+        Pad::Link(i) => format!("[{}]", link_labels[i].get_or_insert_with(&mut new_label)),
+        Pad::Open(Some(name)) => format!("[{}]", String::from_utf8_lossy(name)),
+        // Only happens when the graph is not created by the parser.
+        Pad::Open(None) => format!("[{}]", new_label()),
+    };
+
+    let pretty = style == PrintStyle::Pretty;
+    let mut text = String::new();
+
+    if let Some(scale_sws_opts) = graph.graph.scale_sws_opts {
+        text.push_str("sws_");
+        text.push_str(&String::from_utf8_lossy(scale_sws_opts));
+        text.push(';');
+        if pretty {
+            text.push('\n');
+        }
+    }
+
+    for (i, filter) in filters.iter().enumerate() {
+        let nb_labeled_inputs = if i > 0 && chained[i - 1].is_some() {
+            0
+        } else {
+            inputs[i].len() - unlabeled_tail(&inputs[i])
+        };
+        let nb_labeled_outputs = match chained[i] {
+            Some(n) => outputs[i].len() - n,
+            None => outputs[i].len() - unlabeled_tail(&outputs[i]),
+        };
+
+        let input_labels: String = inputs[i][..nb_labeled_inputs]
+            .iter()
+            .map(&mut label)
+            .collect();
+        let output_labels: String = outputs[i][..nb_labeled_outputs]
+            .iter()
+            .map(&mut label)
+            .collect();
+
+        text.push_str(&input_labels);
+        if pretty && !input_labels.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&quote(filter_name(filter), b"=,;["));
+        if !filter.args.is_empty() {
+            text.push('=');
+            text.push_str(&quote(&String::from_utf8_lossy(&filter.args), b"[],;"));
+        }
+        if pretty && !output_labels.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&output_labels);
+
+        if i + 1 == filters.len() {
+            if pretty {
+                text.push('\n');
+            }
+        } else if chained[i].is_some() {
+            text.push_str(if pretty { ",\n    " } else { "," });
+        } else {
+            text.push_str(if pretty { ";\n" } else { ";" });
+        }
+    }
+
+    text
+}
+
+/// Entry of `ffgen fmt [--compact] [--optimize[=passes]] <graph_description>`,
+/// which prints the canonical form of the given filtergraph.
+pub fn fmt(args: &[String]) -> Result<(), ()> {
+    let usage = || {
+        eprintln!("Usage: ffgen fmt [--compact] [--optimize[=passes]] <graph_description>");
+        Err(())
+    };
+    let (graph, flags) = match args.split_last() {
+        Some(x) => x,
+        None => return usage(),
+    };
+
+    let mut style = PrintStyle::Pretty;
+    let mut passes = OptimizePass::NONE;
+    for flag in flags.iter() {
+        if flag == "--compact" {
+            style = PrintStyle::Compact;
+        } else if flag == "--optimize" {
+            passes = OptimizePass::ALL;
+        } else if let Some(names) = flag.strip_prefix("--optimize=") {
+            passes = OptimizePass::parse(names).map_err(|e| {
+                error!("{}", e);
+            })?;
+        } else {
+            return usage();
+        }
+    }
+
+    let mut graph = parse_graph(graph).map_err(|_| ())?;
+    for change in optimize(&mut graph, passes) {
+        info!("{}", change);
+    }
+    print!("{}", print_graph(&graph, style));
+    if style == PrintStyle::Compact {
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quote_token() {
+        assert_eq!(quote("scale", b"=,;["), "scale");
+        assert_eq!(quote("720:480", b"[],;"), "720:480");
+        assert_eq!(quote("eq(n,0)", b"[],;"), "'eq(n,0)'");
+        assert_eq!(quote(" padded ", b"[],;"), "' padded '");
+        assert_eq!(quote("text=it's", b"[],;"), "text=it\\'s");
+        assert_eq!(quote("it's a,b", b"[],;"), "it\\'s\\ a\\,b");
+        assert_eq!(quote("a=b", b"=,;["), "'a=b'");
+        assert_eq!(quote("a=b", b"[],;"), "a=b");
+    }
+
+    #[test]
+    fn print_compact() {
+        let graph = parse_graph(
+            "sws_flags=+accurate_rnd+bitexact;[in]scale=720:480, split [main][tmp]; [tmp] crop=iw:ih/2:0:0, vflip [flip]; [main][flip] overlay=0:H/2[out]",
+        )
+        .unwrap();
+        assert_eq!(
+            print_graph(&graph, PrintStyle::Compact),
+            "sws_flags=+accurate_rnd+bitexact;[in]scale=720:480:flags=+accurate_rnd+bitexact,split[link0],crop=iw:ih/2:0:0,vflip[link1];[link0][link1]overlay=0:H/2[out]"
+        );
+    }
+
+    #[test]
+    fn print_pretty() {
+        let graph = parse_graph(
+            "[in]scale=720:480, split [main][tmp]; [tmp] crop=iw:ih/2:0:0, vflip [flip]; [main][flip] overlay=0:H/2[out]",
+        )
+        .unwrap();
+        assert_eq!(
+            print_graph(&graph, PrintStyle::Pretty),
+            "[in] scale=720:480,
+    split [link0],
+    crop=iw:ih/2:0:0,
+    vflip [link1];
+[link0][link1] overlay=0:H/2 [out]
+"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let descriptions = [
+            "scale=320:240",
+            "split [main][tmp]; [tmp] crop=iw:ih/2:0:0, vflip [flip]; [main][flip] overlay=0:H/2",
+            "sws_flags=+accurate_rnd+bitexact;[0:0]scale=720:480[v];[v][1:0]overlay[v2]",
+            "[link0]split[a][link1];[a]null",
+            "[1:v]negate[b]; [0:v][b]hstack=inputs=2[top]; null[x]; [x][top]vstack=inputs=2[out]",
+            "[0:v]trim=start=0:duration=90[a];[0:v]trim=start=90:duration=30,setpts=PTS-STARTPTS[b];[b]hflip[c];[a][c]concat[d]",
+            "[c]null[d]; [a][b]overlay[c]",
+            "null@first, select='eq(n\\,0)', null@'second one'",
+            "null@it\\'s, null",
+        ];
+        for &description in descriptions.iter() {
+            let graph = parse_graph(description).unwrap();
+            for &style in [PrintStyle::Compact, PrintStyle::Pretty].iter() {
+                let text = print_graph(&graph, style);
+                assert_eq!(parse_graph(&text).unwrap(), graph, "{}", text);
+            }
+        }
+    }
+}
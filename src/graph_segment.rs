@@ -0,0 +1,332 @@
+//! Code generation with the graph segment API of libavfilter, which FFmpeg
+//! 6.0 added.
+//!
+//! The generated code doesn't parse the description again with
+//! `avfilter_graph_segment_parse()`, it fills the segment with what ffgen
+//! parsed. Every filter is alone in its chain and the links are made with
+//! labels, so `avfilter_graph_segment_link()` links the same pads as
+//! `ParsedGraph::links`. The args of each filter become the option dictionary
+//! `avfilter_graph_segment_apply_opts()` expects, with the shorthand options
+//! named as libavfilter names them.
+use libc::c_void;
+use log::error;
+use rusty_ffmpeg::ffi;
+
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+};
+
+use crate::{
+    cmdutils::{escape_c_bytes, escape_c_string},
+    graph_parser::{no_such_filter, FilterContext, GraphParser, ParsedGraph},
+};
+
+/// Names of the options which can be given without a key, in order: the
+/// options of the private class, without the constants and the aliases of the
+/// previous option.
+fn shorthand_options(filt_name: &str) -> Result<Vec<String>, ()> {
+    let filt_name_c = CString::new(filt_name).map_err(|_| ())?;
+    let filt = unsafe { ffi::avfilter_get_by_name(filt_name_c.as_ptr()) };
+    if filt.is_null() {
+        no_such_filter(filt_name);
+        return Err(());
+    }
+
+    let mut shorthands = vec![];
+    unsafe {
+        if (*filt).priv_class.is_null() {
+            return Ok(shorthands);
+        }
+        // `av_opt_next()` takes an object beginning with its class.
+        let obj = &(*filt).priv_class as *const *const ffi::AVClass as *mut c_void;
+        let mut offset = None;
+        let mut opt = ffi::av_opt_next(obj, ptr::null());
+        while let Some(x) = opt.as_ref() {
+            if x.type_ != ffi::AVOptionType_AV_OPT_TYPE_CONST && offset != Some(x.offset) {
+                offset = Some(x.offset);
+                shorthands.push(CStr::from_ptr(x.name).to_string_lossy().into_owned());
+            }
+            opt = ffi::av_opt_next(obj, opt);
+        }
+    }
+    Ok(shorthands)
+}
+
+/// The args of the filter as `(key, value)` pairs, split like libavfilter
+/// splits them. Values without a key are given to the shorthand options,
+/// until the first value with a key. The values are bytes, like the args.
+fn filter_options(filter: &FilterContext) -> Result<Vec<(String, Vec<u8>)>, ()> {
+    let mut options = vec![];
+    if filter.args.is_empty() {
+        return Ok(options);
+    }
+
+    let mut shorthands = shorthand_options(&filter.filt_name)?.into_iter();
+    let mut parser = GraphParser::new(&filter.args);
+    while parser.peek().is_some() {
+        parser.skip_ws();
+        let key = parser.peek_until_end(|x| !(x.is_ascii_alphanumeric() || b"_-/.".contains(&x)));
+        let has_key = !key.is_empty()
+            && parser.remaining()[key.len()..]
+                .iter()
+                .find(|x| !b" \n\r\t".contains(x))
+                == Some(&b'=');
+
+        let key = if has_key {
+            parser.skip(key.len());
+            parser.skip_ws();
+            parser.skip(1);
+            // Values after a key cannot be given to the shorthand options.
+            shorthands.by_ref().for_each(drop);
+            String::from_utf8_lossy(key).into_owned()
+        } else {
+            match shorthands.next() {
+                Some(shorthand) => shorthand,
+                None => {
+                    error!(
+                        "No option name near '{}' in the args of '{}'.",
+                        String::from_utf8_lossy(parser.remaining()),
+                        filter.inst_name
+                    );
+                    return Err(());
+                }
+            }
+        };
+        let value = parser.get_token(b":");
+        options.push((key, value));
+        parser.skip(1);
+    }
+    Ok(options)
+}
+
+/// Label of each pad of each filter, `None` for unlabeled pads.
+type PadLabels = Vec<Vec<Option<String>>>;
+
+/// Labels of the pads for the inputs and the outputs. Links get labels of
+/// their own, open pads keep their label.
+fn pad_labels(parsed: &ParsedGraph) -> (PadLabels, PadLabels) {
+    let mut inputs: PadLabels = parsed
+        .filters
+        .iter()
+        .map(|filter| vec![None; filter.nb_inputs])
+        .collect();
+    let mut outputs: PadLabels = parsed
+        .filters
+        .iter()
+        .map(|filter| vec![None; filter.nb_outputs])
+        .collect();
+
+    let open = || parsed.open_inputs.iter().chain(parsed.open_outputs.iter());
+    // The labels of the links must differ from the ones of the open pads.
+    let mut prefix = String::from("link");
+    while open()
+        .filter_map(|x| x.name)
+        .any(|name| name.starts_with(prefix.as_bytes()))
+    {
+        prefix.push('_');
+    }
+
+    for (i, link) in parsed.links.iter().enumerate() {
+        let label = format!("{}{}", prefix, i);
+        outputs[link.from_filter][link.from_pad_idx] = Some(label.clone());
+        inputs[link.to_filter][link.to_pad_idx] = Some(label);
+    }
+    let label = |name: Option<&[u8]>| name.map(|x| String::from_utf8_lossy(x).into_owned());
+    for input in parsed.open_inputs.iter() {
+        if let Some(filter) = input.filter_ctx {
+            inputs[filter][input.pad_idx] = label(input.name);
+        }
+    }
+    for output in parsed.open_outputs.iter() {
+        if let Some(filter) = output.filter_ctx {
+            outputs[filter][output.pad_idx] = label(output.name);
+        }
+    }
+    (inputs, outputs)
+}
+
+/// Print the code creating the parsed graph with the graph segment API, with
+/// `linked` run once the graph is linked, before the segment is freed. Fails
+/// when the args of a filter cannot be split into options.
+pub fn segment_serialization(parsed: &ParsedGraph, linked: &str) -> Result<(), ()> {
+    let options = parsed
+        .filters
+        .iter()
+        .map(filter_options)
+        .collect::<Result<Vec<_>, ()>>()?;
+    let (inputs, outputs) = pad_labels(parsed);
+
+    let pads_serialization = |params: &str, kind: &str, labels: &[Option<String>]| {
+        if labels.is_empty() {
+            return;
+        }
+        println!(
+            r#"
+if (!({}->{} = av_calloc({}, sizeof(*{}->{}))))
+    goto end;"#,
+            params,
+            kind,
+            labels.len(),
+            params,
+            kind
+        );
+        for label in labels.iter() {
+            println!(
+                r#"{{
+    AVFilterPadParams *pad = av_mallocz(sizeof(AVFilterPadParams));
+    if (!pad)
+        goto end;
+    {}->{}[{}->nb_{}++] = pad;"#,
+                params, kind, params, kind
+            );
+            // Unlabeled pads are left open.
+            if let Some(label) = label {
+                println!(
+                    r#"    if (!(pad->label = av_strdup("{}")))
+        goto end;"#,
+                    escape_c_string(label)
+                );
+            }
+            println!("}}");
+        }
+    };
+
+    println!(
+        r#"
+AVFilterGraphSegment *seg;
+if (!(seg = av_mallocz(sizeof(AVFilterGraphSegment))))
+    return AVERROR(ENOMEM);
+seg->graph = graph;
+ret = AVERROR(ENOMEM);
+"#
+    );
+
+    if let Some(scale_sws_opts) = parsed.graph.scale_sws_opts {
+        println!(
+            r#"if (!(seg->scale_sws_opts = av_strdup("{}")))
+    goto end;
+"#,
+            escape_c_bytes(scale_sws_opts)
+        );
+    }
+
+    println!(
+        r#"if (!(seg->chains = av_calloc({}, sizeof(*seg->chains))))
+    goto end;"#,
+        parsed.filters.len()
+    );
+
+    for (i, filter) in parsed.filters.iter().enumerate() {
+        let chain = format!("chain_{}_{}", filter.filt_name, i);
+        let params = format!("params_{}_{}", filter.filt_name, i);
+        println!(
+            r#"
+AVFilterChain *{chain} = av_mallocz(sizeof(AVFilterChain));
+if (!{chain})
+    goto end;
+seg->chains[seg->nb_chains++] = {chain};
+if (!({chain}->filters = av_calloc(1, sizeof(*{chain}->filters))))
+    goto end;
+AVFilterParams *{params} = av_mallocz(sizeof(AVFilterParams));
+if (!{params})
+    goto end;
+{chain}->filters[{chain}->nb_filters++] = {params};
+if (!({params}->filter_name = av_strdup("{filt_name}")) ||
+    !({params}->instance_name = av_strdup("{inst_name}")))
+    goto end;"#,
+            chain = chain,
+            params = params,
+            filt_name = escape_c_string(&filter.filt_name),
+            inst_name = escape_c_string(&filter.inst_name),
+        );
+        for (key, value) in options[i].iter() {
+            println!(
+                r#"if (av_dict_set(&{}->opts, "{}", "{}", AV_DICT_MULTIKEY) < 0)
+    goto end;"#,
+                params,
+                escape_c_string(key),
+                escape_c_bytes(value)
+            );
+        }
+        pads_serialization(&params, "inputs", &inputs[i]);
+        pads_serialization(&params, "outputs", &outputs[i]);
+    }
+
+    println!(
+        r#"
+if ((ret = avfilter_graph_segment_create_filters(seg, 0)) < 0 ||
+    (ret = avfilter_graph_segment_apply_opts(seg, 0)) < 0 ||
+    (ret = avfilter_graph_segment_init(seg, 0)) < 0 ||
+    (ret = avfilter_graph_segment_link(seg, 0, inputs, outputs)) < 0)
+    goto end;
+{}
+end:
+avfilter_graph_segment_free(&seg);
+return ret;
+"#,
+        linked
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph_parser::parse_graph;
+
+    fn options(description: &str) -> Result<Vec<(String, Vec<u8>)>, ()> {
+        let parsed = parse_graph(description).map_err(|_| ())?;
+        filter_options(&parsed.filters[0])
+    }
+
+    fn pair(key: &str, value: &str) -> (String, Vec<u8>) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn shorthand_and_named_options() {
+        assert_eq!(options("scale"), Ok(vec![]));
+        assert_eq!(
+            options("scale=320:240"),
+            Ok(vec![pair("w", "320"), pair("h", "240")])
+        );
+        assert_eq!(
+            options("scale=iw/2:h = 'ih\\:2' : flags=bicubic"),
+            Ok(vec![
+                pair("w", "iw/2"),
+                pair("h", "ih:2"),
+                pair("flags", "bicubic")
+            ])
+        );
+        // No more shorthand options after a named one.
+        assert!(options("scale=w=320:240").is_err());
+    }
+
+    #[test]
+    fn labels_of_pads() {
+        let parsed = parse_graph("[in]split[a][b];[a][b]overlay[out];[link0]null").unwrap();
+        let (inputs, outputs) = pad_labels(&parsed);
+        let labels = |x: &[&str]| -> Vec<Option<String>> {
+            x.iter()
+                .map(|&x| if x.is_empty() { None } else { Some(x.into()) })
+                .collect()
+        };
+        assert_eq!(
+            inputs,
+            vec![
+                labels(&["in"]),
+                labels(&["link_0", "link_1"]),
+                labels(&["link0"])
+            ]
+        );
+        assert_eq!(
+            outputs,
+            vec![
+                labels(&["link_0", "link_1"]),
+                labels(&["out"]),
+                labels(&[""])
+            ]
+        );
+    }
+}
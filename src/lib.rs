@@ -0,0 +1,26 @@
+// For the `&raw *` used in the macro of options.rs, will be stabilized later
+#![feature(raw_ref_op)]
+// For the half open range in match in `split_commandline()`'s AVOption part
+#![feature(exclusive_range_pattern)]
+#![feature(half_open_range_patterns)]
+#![feature(ptr_offset_from)]
+#![feature(bool_to_option)]
+// The modules are public for the fuzz targets in `fuzz/`.
+pub mod cmdutils;
+pub mod completion;
+pub mod ffmpeg;
+pub mod ffmpeg_opt;
+pub mod graph_conformance;
+pub mod graph_diff;
+pub mod graph_optimizer;
+pub mod graph_parser;
+pub mod graph_printer;
+pub mod graph_segment;
+pub mod logging;
+pub mod options;
+pub mod preset;
+pub mod shell;
+pub mod show;
+pub mod target;
+#[cfg(test)]
+mod test_util;
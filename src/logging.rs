@@ -0,0 +1,610 @@
+//! Logging: `-loglevel`, `-v` and `-report` of FFmpeg's cmdutils, applied to
+//! the output of ffgen and to `av_log_set_level()` of the libraries.
+//!
+//! The messages of the libraries are logged too, with the `libav` target.
+//! `RUST_LOG` still filters what is printed, e.g. by module.
+use libc::{c_char, c_int, c_void};
+use log::{error, info, Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use rusty_ffmpeg::ffi;
+
+use std::{
+    env,
+    ffi::{CStr, OsString},
+    fs::File,
+    io::Write,
+    mem, ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    cmdutils::{locate_option, os_bytes, OptionDef},
+    graph_parser::GraphParser,
+};
+
+/// The named levels of libavutil/log.h.
+const LEVELS: [(&str, c_int); 9] = [
+    ("quiet", -8),
+    ("panic", 0),
+    ("fatal", 8),
+    ("error", 16),
+    ("warning", 24),
+    ("info", 32),
+    ("verbose", 40),
+    ("debug", 48),
+    ("trace", 56),
+];
+
+pub const AV_LOG_INFO: c_int = 32;
+pub const AV_LOG_DEBUG: c_int = 48;
+
+pub const AV_LOG_SKIP_REPEATED: c_int = 1;
+pub const AV_LOG_PRINT_LEVEL: c_int = 2;
+
+/// The file of `-report`.
+struct Report {
+    file: File,
+    level: LevelFilter,
+}
+
+struct Logger {
+    stderr: env_logger::Logger,
+    /// The `LevelFilter` of `-loglevel`, as `usize`.
+    level: AtomicUsize,
+    report: Mutex<Option<Report>>,
+}
+
+static LOGGER: Lazy<Logger> = Lazy::new(|| {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(LevelFilter::Trace);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    Logger {
+        stderr: builder.build(),
+        level: AtomicUsize::new(level_filter(AV_LOG_INFO) as usize),
+        report: Mutex::new(None),
+    }
+});
+
+impl Logger {
+    fn level(&self) -> LevelFilter {
+        const FILTERS: [LevelFilter; 6] = [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ];
+        FILTERS[self.level.load(Ordering::Relaxed)]
+    }
+
+    /// Nothing below the levels of stderr and of the report is formatted.
+    fn update_max_level(&self) {
+        let report = self.report.lock().unwrap().as_ref().map(|x| x.level);
+        log::set_max_level(self.level().max(report.unwrap_or(LevelFilter::Off)));
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.level() && self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+        if let Some(report) = self.report.lock().unwrap().as_mut() {
+            if record.level() <= report.level {
+                // A report which cannot be written is not worth an error.
+                let _ = writeln!(report.file, "[{}] {}", record.target(), record.args());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(report) = self.report.lock().unwrap().as_mut() {
+            let _ = report.file.flush();
+        }
+    }
+}
+
+/// Install the logger, at the `info` level FFmpeg starts with, and the
+/// `av_log()` callback.
+pub fn init() {
+    if log::set_logger(&*LOGGER).is_ok() {
+        LOGGER.update_max_level();
+        unsafe {
+            // As ffmpeg does, unless `-loglevel` says `repeat`.
+            ffi::av_log_set_flags(AV_LOG_SKIP_REPEATED);
+            ffi::av_log_set_callback(Some(av_log_callback));
+        }
+    }
+}
+
+/// What is printed of the messages of an `av_log()` level.
+pub fn level_filter(level: c_int) -> LevelFilter {
+    match level {
+        c_int::MIN..=-1 => LevelFilter::Off,
+        0..=16 => LevelFilter::Error,
+        17..=24 => LevelFilter::Warn,
+        25..=32 => LevelFilter::Info,
+        33..=48 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// `opt_loglevel()`: the level and flags of `arg`, given the current ones.
+///
+/// The level is a name or a number, optionally after `repeat` and `level`
+/// flags. Flags prefixed by `+` or `-` are set or unset, otherwise the flags
+/// are reset before the first one is set.
+pub fn parse_level(
+    arg: &str,
+    level: c_int,
+    current_flags: c_int,
+) -> Result<(c_int, c_int), String> {
+    let (mut arg, mut flags, mut i) = (arg, current_flags, 0);
+    while !arg.is_empty() {
+        let (cmd, token) = match arg.as_bytes()[0] {
+            cmd @ b'+' | cmd @ b'-' => (Some(cmd), &arg[1..]),
+            _ => (None, arg),
+        };
+        if i == 0 && cmd.is_none() {
+            flags = 0;
+        }
+        if let Some(rest) = token.strip_prefix("repeat") {
+            if cmd == Some(b'-') {
+                flags |= AV_LOG_SKIP_REPEATED;
+            } else {
+                flags &= !AV_LOG_SKIP_REPEATED;
+            }
+            arg = rest;
+        } else if let Some(rest) = token.strip_prefix("level") {
+            if cmd == Some(b'-') {
+                flags &= !AV_LOG_PRINT_LEVEL;
+            } else {
+                flags |= AV_LOG_PRINT_LEVEL;
+            }
+            arg = rest;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    if arg.is_empty() {
+        return Ok((level, flags));
+    } else if let Some(rest) = arg.strip_prefix('+') {
+        arg = rest;
+    } else if i == 0 {
+        // A level without flags keeps the current flags.
+        flags = current_flags;
+    }
+    if let Some(&(_, level)) = LEVELS.iter().find(|(name, _)| *name == arg) {
+        return Ok((level, flags));
+    }
+    match arg.parse() {
+        Ok(level) => Ok((level, flags)),
+        Err(_) => {
+            let names: Vec<_> = LEVELS
+                .iter()
+                .map(|(name, _)| format!("\"{}\"", name))
+                .collect();
+            Err(format!(
+                "Invalid loglevel \"{}\". Possible levels are numbers or:\n{}",
+                arg,
+                names.join("\n")
+            ))
+        }
+    }
+}
+
+/// Apply the level and flags to ffgen and to the libraries.
+pub fn set_level(level: c_int, flags: c_int) {
+    unsafe {
+        ffi::av_log_set_flags(flags);
+        ffi::av_log_set_level(level);
+    }
+    LOGGER
+        .level
+        .store(level_filter(level) as usize, Ordering::Relaxed);
+    LOGGER.update_max_level();
+}
+
+/// `-loglevel` and `-v`.
+pub fn set_loglevel(arg: &str) -> Result<(), ()> {
+    let (level, flags) = unsafe { (ffi::av_log_get_level(), ffi::av_log_get_flags()) };
+    let (level, flags) = parse_level(arg, level, flags).map_err(|e| error!("{}", e))?;
+    set_level(level, flags);
+    Ok(())
+}
+
+/// The `av_log()` messages being logged, for the callback.
+#[derive(Debug, Default)]
+struct Bridge {
+    /// The beginning of a line not ended yet, with the context and the level
+    /// of its first message.
+    partial: String,
+    context: Option<String>,
+    level: c_int,
+    /// The last line logged, and how many times it was repeated since.
+    prev: String,
+    count: usize,
+}
+
+impl Bridge {
+    /// `av_log_default_callback()`: the lines ended by the message `text`,
+    /// with their level, prefixed as `[scale @ 0x5581f2c0] `. With
+    /// `AV_LOG_SKIP_REPEATED`, repeated lines are counted instead.
+    fn push(
+        &mut self,
+        context: Option<String>,
+        level: c_int,
+        text: &str,
+        flags: c_int,
+    ) -> Vec<(c_int, String)> {
+        if self.partial.is_empty() {
+            self.context = context;
+            self.level = level;
+        }
+        self.partial.push_str(text);
+        let mut lines = vec![];
+        while let Some(n) = self.partial.find(&['\n', '\r'][..]) {
+            // Progress lines are ended by `\r`, and never counted.
+            let progress =
+                self.partial[n..].starts_with('\r') && !self.partial[n..].starts_with("\r\n");
+            let end = if self.partial[n..].starts_with("\r\n") {
+                n + 2
+            } else {
+                n + 1
+            };
+            let rest = self.partial.split_off(end);
+            let text = mem::replace(&mut self.partial, rest);
+
+            let mut line = String::new();
+            if let Some(context) = &self.context {
+                line.push_str(&format!("[{}] ", context));
+            }
+            if flags & AV_LOG_PRINT_LEVEL != 0 {
+                if let Some((name, _)) = LEVELS.iter().find(|(_, x)| *x == self.level) {
+                    line.push_str(&format!("[{}] ", name));
+                }
+            }
+            line.push_str(&text[..n]);
+            if flags & AV_LOG_SKIP_REPEATED != 0 && !progress && line == self.prev {
+                self.count += 1;
+                continue;
+            }
+            if self.count > 0 {
+                let repeated = format!("    Last message repeated {} times", self.count);
+                lines.push((self.level, repeated));
+                self.count = 0;
+            }
+            self.prev = line.clone();
+            lines.push((self.level, line));
+        }
+        lines
+    }
+}
+
+static BRIDGE: Lazy<Mutex<Bridge>> = Lazy::new(Default::default);
+
+/// The `item_name()` of the `AVClass` of a logging context, like the name of
+/// a filter instance.
+unsafe fn item_name(avcl: *mut c_void) -> Option<String> {
+    if avcl.is_null() {
+        return None;
+    }
+    let class = (*(avcl as *const *const ffi::AVClass)).as_ref()?;
+    let name = match class.item_name {
+        Some(item_name) => item_name(avcl),
+        None => class.class_name,
+    };
+    if name.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(name).to_string_lossy().into_owned())
+}
+
+/// The `av_log()` callback: the messages of the libraries, like the ones of
+/// the filters created for the filtergraph, are logged with the `libav`
+/// target instead of being printed to stderr.
+unsafe extern "C" fn av_log_callback(
+    avcl: *mut c_void,
+    level: c_int,
+    fmt: *const c_char,
+    vl: *mut ffi::__va_list_tag,
+) {
+    // The upper bits are the color of the message.
+    let level = if level >= 0 { level & 0xff } else { level };
+    if level > ffi::av_log_get_level() {
+        return;
+    }
+    // The size of the lines of `av_log_default_callback()`.
+    let mut text: [c_char; 1024] = [0; 1024];
+    // Without prefix, which is added per line.
+    let mut print_prefix = 0;
+    ffi::av_log_format_line2(
+        ptr::null_mut(),
+        level,
+        fmt,
+        vl,
+        text.as_mut_ptr(),
+        text.len() as c_int,
+        &mut print_prefix,
+    );
+    let text = CStr::from_ptr(text.as_ptr()).to_string_lossy();
+    let context = item_name(avcl).map(|name| format!("{} @ {:p}", name, avcl));
+
+    // The codecs and the filters log from their threads too. Panicking in
+    // the callback would abort, so a poisoned lock is used anyway.
+    let mut bridge = BRIDGE.lock().unwrap_or_else(|e| e.into_inner());
+    for (level, line) in bridge.push(context, level, &text, ffi::av_log_get_flags()) {
+        let level = level_filter(level).to_level().unwrap_or(Level::Error);
+        if level <= log::max_level() {
+            // Not at the module of the callback.
+            log::logger().log(
+                &Record::builder()
+                    .args(format_args!("{}", line))
+                    .level(level)
+                    .target("libav")
+                    .module_path(Some("libav"))
+                    .build(),
+            );
+        }
+    }
+}
+
+/// The `key=value` pairs of `FFREPORT`, separated by `:`.
+fn parse_ffreport(env: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = vec![];
+    let mut parser = GraphParser::new(env);
+    while parser.peek().is_some() {
+        let key = parser.get_token(b"=:");
+        if parser.peek() != Some(b'=') {
+            return Err(format!(
+                "Failed to parse FFREPORT environment variable: {}",
+                env
+            ));
+        }
+        parser.skip(1);
+        let value = parser.get_token(b":");
+        pairs.push((
+            String::from_utf8_lossy(&key).into_owned(),
+            String::from_utf8_lossy(&value).into_owned(),
+        ));
+        parser.skip(1);
+    }
+    Ok(pairs)
+}
+
+fn local_time() -> libc::tm {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    }
+}
+
+/// `expand_filename_template()`: `%p` is the program name, `%t` the time
+/// and `%%` a `%`.
+fn expand_filename_template(template: &str, program: &str, tm: &libc::tm) -> String {
+    let mut filename = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            filename.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('p') => filename.push_str(program),
+            Some('t') => filename.push_str(&format!(
+                "{:04}{:02}{:02}-{:02}{:02}{:02}",
+                tm.tm_year + 1900,
+                tm.tm_mon + 1,
+                tm.tm_mday,
+                tm.tm_hour,
+                tm.tm_min,
+                tm.tm_sec
+            )),
+            Some('%') => filename.push('%'),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    filename
+}
+
+/// `init_report()`: log to a file too, as configured by `FFREPORT`.
+pub fn init_report(env: Option<&str>) -> Result<(), ()> {
+    if LOGGER.report.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    let tm = local_time();
+
+    let mut template = None;
+    let mut env_level = None;
+    for (key, value) in parse_ffreport(env.unwrap_or("")).map_err(|e| error!("{}", e))? {
+        match key.as_str() {
+            "file" => template = Some(value),
+            "level" => {
+                env_level = Some(
+                    value
+                        .parse()
+                        .map_err(|_| error!("Invalid report file level"))?,
+                )
+            }
+            _ => error!("Unknown key '{}' in FFREPORT", key),
+        }
+    }
+    let level = env_level.unwrap_or_else(|| AV_LOG_DEBUG.max(unsafe { ffi::av_log_get_level() }));
+    let filename =
+        expand_filename_template(template.as_deref().unwrap_or("%p-%t.log"), "ffgen", &tm);
+    let file = match File::create(&filename) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open report \"{}\": {}", filename, e);
+            return Ok(());
+        }
+    };
+    *LOGGER.report.lock().unwrap() = Some(Report {
+        file,
+        level: level_filter(level),
+    });
+    LOGGER.update_max_level();
+    info!(
+        "ffgen started on {:04}-{:02}-{:02} at {:02}:{:02}:{:02}\n\
+         Report written to \"{}\"\n\
+         Log level: {}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        filename,
+        level
+    );
+    Ok(())
+}
+
+/// `dump_argument()`: quoted for the shell when needed.
+fn dump_argument(arg: &[u8]) -> String {
+    let plain = |c: &u8| c.is_ascii_alphanumeric() || b"+-.,/_:=".contains(c);
+    if !arg.is_empty() && arg.iter().all(plain) {
+        return String::from_utf8_lossy(arg).into_owned();
+    }
+    let mut quoted = String::from("\"");
+    for &c in arg {
+        match c {
+            b'\\' | b'"' | b'$' | b'`' => {
+                quoted.push('\\');
+                quoted.push(c as char);
+            }
+            b' '..=b'~' => quoted.push(c as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `parse_loglevel()`: apply `-loglevel` and `-report`, or `FFREPORT`,
+/// before the commandline is split, so that splitting is logged too.
+pub fn parse_loglevel(args: &[OsString], options: &[OptionDef]) -> Result<(), ()> {
+    let index =
+        locate_option(args, options, "loglevel").or_else(|| locate_option(args, options, "v"));
+    if let Some(arg) = index.and_then(|i| args.get(i + 1)) {
+        set_loglevel(&arg.to_string_lossy())?;
+    }
+    let env = env::var("FFREPORT").ok();
+    if env.is_some() || locate_option(args, options, "report").is_some() {
+        init_report(env.as_deref())?;
+        if let Some(report) = LOGGER.report.lock().unwrap().as_mut() {
+            let args: Vec<_> = args.iter().map(|x| dump_argument(&os_bytes(x))).collect();
+            let _ = writeln!(report.file, "Command line:\n{}", args.join(" "));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn loglevel_syntax() {
+        let flags = AV_LOG_SKIP_REPEATED | AV_LOG_PRINT_LEVEL;
+        assert_eq!(parse_level("debug", 32, flags), Ok((48, flags)));
+        assert_eq!(parse_level("level+debug", 32, 1), Ok((48, 2)));
+        assert_eq!(parse_level("repeat+level+verbose", 32, 0), Ok((40, 2)));
+        assert_eq!(
+            parse_level("-repeat", 32, AV_LOG_PRINT_LEVEL),
+            Ok((32, flags))
+        );
+        assert_eq!(parse_level("+level-repeat+warning", 32, 0), Ok((24, flags)));
+        assert_eq!(parse_level("-level", 32, flags), Ok((32, 1)));
+        assert_eq!(parse_level("56", 32, 0), Ok((56, 0)));
+        assert_eq!(parse_level("+16", 32, 1), Ok((16, 1)));
+        assert_eq!(parse_level("-8", 32, 0), Ok((-8, 0)));
+        assert!(parse_level("loud", 32, 0)
+            .unwrap_err()
+            .starts_with("Invalid loglevel \"loud\""));
+        assert!(parse_level("32x", 32, 0).is_err());
+
+        assert_eq!(level_filter(-8), LevelFilter::Off);
+        assert_eq!(level_filter(24), LevelFilter::Warn);
+        assert_eq!(level_filter(40), LevelFilter::Debug);
+        assert_eq!(level_filter(56), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn av_log_lines() {
+        let mut bridge = Bridge::default();
+        let context = || Some("scale @ 0x1".to_owned());
+        assert_eq!(bridge.push(context(), 32, "w:2 ", 0), []);
+        // The context and level of a line are the ones of its beginning.
+        assert_eq!(
+            bridge.push(None, 48, "h:2\nfmt:", 0),
+            [(32, "[scale @ 0x1] w:2 h:2".to_owned())]
+        );
+        assert_eq!(
+            bridge.push(None, 48, "yuv420p\r\n", AV_LOG_PRINT_LEVEL),
+            [(32, "[scale @ 0x1] [info] fmt:yuv420p".to_owned())]
+        );
+
+        let repeated = AV_LOG_SKIP_REPEATED;
+        assert_eq!(bridge.push(None, 24, "again\n", repeated).len(), 1);
+        assert_eq!(bridge.push(None, 24, "again\nagain\n", repeated), []);
+        assert_eq!(
+            bridge.push(None, 24, "frame=1\r", repeated),
+            [
+                (24, "    Last message repeated 2 times".to_owned()),
+                (24, "frame=1".to_owned())
+            ]
+        );
+        assert_eq!(bridge.push(None, 24, "frame=1\r", repeated).len(), 1);
+        // Without the flag, every line is logged.
+        assert_eq!(bridge.push(None, 24, "frame=1\rframe=1\r", 0).len(), 2);
+    }
+
+    #[test]
+    fn reports() {
+        assert_eq!(
+            parse_ffreport(r"file=C\:/logs/%p.log:level=32"),
+            Ok(vec![
+                ("file".into(), "C:/logs/%p.log".into()),
+                ("level".into(), "32".into())
+            ])
+        );
+        assert_eq!(
+            parse_ffreport("file='a:b'"),
+            Ok(vec![("file".into(), "a:b".into())])
+        );
+        assert!(parse_ffreport("level").is_err());
+
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        tm.tm_year = 120;
+        tm.tm_mon = 0;
+        tm.tm_mday = 2;
+        tm.tm_hour = 3;
+        tm.tm_min = 4;
+        tm.tm_sec = 5;
+        assert_eq!(
+            expand_filename_template("%p-%t-100%%.log%", "ffgen", &tm),
+            "ffgen-20200102-030405-100%.log"
+        );
+
+        assert_eq!(dump_argument(b"-c:v"), "-c:v");
+        assert_eq!(dump_argument(b""), "\"\"");
+        assert_eq!(dump_argument(b"a \"$x\""), r#""a \"\$x\"""#);
+        assert_eq!(dump_argument(b"\xff"), r#""\xff""#);
+    }
+}
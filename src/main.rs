@@ -1,22 +1,40 @@
-// For the `&raw *` used in the macro of options.rs, will be stabilized later
-#![feature(raw_ref_op)]
-// For the half open range in match in `split_commandline()`'s AVOption part
-#![feature(exclusive_range_pattern)]
-#![feature(half_open_range_patterns)]
-#![feature(ptr_offset_from)]
-#![feature(bool_to_option)]
-mod cmdutils;
-mod ffmpeg;
-mod ffmpeg_opt;
-mod graph_parser;
-mod options;
+use ffgen::{completion, ffmpeg, graph_diff, graph_printer, logging, show};
 
-use env_logger;
-
-use std::env;
+use std::{env, process};
 
 fn main() {
-    env::set_var("RUST_LOG", "debug");
-    env_logger::init();
-    ffmpeg::ffmpeg();
+    logging::init();
+
+    // The subcommands take filtergraphs and names, only the ffmpeg arguments
+    // are kept as they are, since file names are not always UTF-8.
+    let args: Vec<String> = env::args_os()
+        .map(|x| x.to_string_lossy().into_owned())
+        .collect();
+    match args.get(1).map(String::as_str) {
+        Some("diff") => {
+            if graph_diff::diff(&args[2..]).is_err() {
+                process::exit(1);
+            }
+        }
+        Some("fmt") => {
+            if graph_printer::fmt(&args[2..]).is_err() {
+                process::exit(1);
+            }
+        }
+        Some("completion") => {
+            if completion::completion(&args[2..]).is_err() {
+                process::exit(1);
+            }
+        }
+        Some("list") => {
+            if show::list(&args[2..]).is_err() {
+                process::exit(1);
+            }
+        }
+        _ => {
+            if ffmpeg::ffmpeg().is_err() {
+                process::exit(1);
+            }
+        }
+    }
 }
@@ -2,16 +2,28 @@
 #![allow(non_upper_case_globals)]
 // This will be finally removed, but in development stage it's useful
 #![allow(unused_variables)]
-use libc::{c_char, c_void};
+use bitflags::bitflags;
+use libc::{c_char, c_int, c_uint, c_void};
+use log::{error, warn};
 use memoffset::offset_of;
 use once_cell::sync::Lazy;
+use rusty_ffmpeg::{avutil::error::AVERROR, ffi};
+
+use std::{
+    ffi::{CString, OsStr},
+    ptr, str,
+    sync::Mutex,
+};
 
 use crate::{
     cmdutils::{
-        OptionDef, OptionFlag, OptionGroup, OptionGroupDef, OptionGroupList, OptionKV,
-        OptionOperation, OptionParseContext,
+        codec_opts, format_opts, opt_default, parse_number, parse_option, OptionDef, OptionFlag,
+        OptionGroup, OptionGroupDef, OptionGroupList, OptionKV, OptionOperation,
+        OptionParseContext,
     },
-    ffmpeg::OptionsContext,
+    ffmpeg::{AudioChannelMap, OptionsContext},
+    logging,
+    show::{self, Listing},
 };
 
 macro_rules! void {
@@ -321,8 +333,314 @@ pub static OPTIONS: Lazy<[OptionDef; 179]> = Lazy::new(|| {
     ]
 });
 
-// TODO need this be enum?
-const VSYNC_AUTO: isize = -1;
+/// FFmpeg releases whose command line can be split, from the oldest one.
+///
+/// `OPTIONS` is the table of 4.3, the tables of the later releases are built
+/// from it with the options each release added and removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FFmpegVersion {
+    V4_3,
+    V4_4,
+    V5_0,
+    V5_1,
+    V6_0,
+    V6_1,
+    V7_0,
+}
+
+impl FFmpegVersion {
+    pub const ALL: [FFmpegVersion; 7] = [
+        FFmpegVersion::V4_3,
+        FFmpegVersion::V4_4,
+        FFmpegVersion::V5_0,
+        FFmpegVersion::V5_1,
+        FFmpegVersion::V6_0,
+        FFmpegVersion::V6_1,
+        FFmpegVersion::V7_0,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FFmpegVersion::V4_3 => "4.3",
+            FFmpegVersion::V4_4 => "4.4",
+            FFmpegVersion::V5_0 => "5.0",
+            FFmpegVersion::V5_1 => "5.1",
+            FFmpegVersion::V6_0 => "6.0",
+            FFmpegVersion::V6_1 => "6.1",
+            FFmpegVersion::V7_0 => "7.0",
+        }
+    }
+
+    /// `(major, minor)` of the libavutil shipped with the release.
+    fn libavutil_version(self) -> (u32, u32) {
+        match self {
+            FFmpegVersion::V4_3 => (56, 51),
+            FFmpegVersion::V4_4 => (56, 70),
+            FFmpegVersion::V5_0 => (57, 17),
+            FFmpegVersion::V5_1 => (57, 28),
+            FFmpegVersion::V6_0 => (58, 2),
+            FFmpegVersion::V6_1 => (58, 29),
+            FFmpegVersion::V7_0 => (59, 8),
+        }
+    }
+
+    /// The newest release not newer than the given `AV_VERSION_INT` of
+    /// libavutil, the oldest one when the libavutil is older than all of them.
+    pub fn from_libavutil(version: u32) -> Self {
+        let linked = (version >> 16, (version >> 8) & 0xff);
+        Self::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|x| x.libavutil_version() <= linked)
+            .unwrap_or(FFmpegVersion::V4_3)
+    }
+
+    /// Release of the libavutil ffgen is linked with.
+    pub fn linked() -> Self {
+        Self::from_libavutil(unsafe { ffi::avutil_version() })
+    }
+
+    /// Options added and removed by the release, relative to the previous one.
+    fn changes(self) -> (Vec<OptionDef<'static>>, &'static [&'static str]) {
+        match self {
+            FFmpegVersion::V4_3 => (vec![], &[]),
+            FFmpegVersion::V4_4 => (
+                vec![
+                    option_def!("stats_period", HAS_ARG | OPT_EXPERT, func_arg => opt_stats_period, "set the period at which ffmpeg updates stats and -progress output", "time"),
+                    option_def!("autoscale", OPT_BOOL | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT, off => autoscale, "Automatically insert a scale filter at the end of the filter graph"),
+                    option_def!("fpsmax", OPT_VIDEO | HAS_ARG | OPT_STRING | OPT_EXPERT | OPT_SPEC | OPT_OUTPUT, off => max_frame_rates, "set max frame rate (Hz value, fraction or abbreviation)", "rate"),
+                ],
+                &[],
+            ),
+            FFmpegVersion::V5_0 => (
+                vec![
+                    option_def!("readrate", OPT_FLOAT | HAS_ARG | OPT_OFFSET | OPT_EXPERT | OPT_INPUT, off => readrate, "read input at specified rate", "speed"),
+                ],
+                &["sameq", "same_quant", "deinterlace", "vc", "tvstd", "isync"],
+            ),
+            FFmpegVersion::V5_1 => (
+                vec![
+                    option_def!("fps_mode", OPT_VIDEO | HAS_ARG | OPT_STRING | OPT_EXPERT | OPT_SPEC | OPT_OUTPUT, off => fps_mode, "set framerate mode for matching video streams; overrides vsync"),
+                ],
+                &[],
+            ),
+            FFmpegVersion::V6_0 => (
+                vec![
+                    option_def!("fix_sub_duration_heartbeat", OPT_VIDEO | OPT_BOOL | OPT_EXPERT | OPT_SPEC | OPT_OUTPUT, off => fix_sub_duration_heartbeat, "set this video output stream to be a heartbeat stream for fix_sub_duration, according to which subtitles should be split at random access points"),
+                    option_def!("stats_enc_pre", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => enc_stats_pre, "write encoding stats before encoding"),
+                    option_def!("stats_enc_post", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => enc_stats_post, "write encoding stats after encoding"),
+                    option_def!("stats_mux_pre", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => mux_stats, "write packets stats before muxing"),
+                    option_def!("stats_enc_pre_fmt", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => enc_stats_pre_fmt, "format of the stats written with -stats_enc_pre"),
+                    option_def!("stats_enc_post_fmt", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => enc_stats_post_fmt, "format of the stats written with -stats_enc_post"),
+                    option_def!("stats_mux_pre_fmt", HAS_ARG | OPT_SPEC | OPT_EXPERT | OPT_OUTPUT | OPT_STRING, off => mux_stats_fmt, "format of the stats written with -stats_mux_pre"),
+                    option_def!("shortest_buf_duration", HAS_ARG | OPT_FLOAT | OPT_EXPERT | OPT_OFFSET | OPT_OUTPUT, off => shortest_buf_duration, "maximum buffering duration (in seconds) for the -shortest option"),
+                ],
+                &[],
+            ),
+            FFmpegVersion::V6_1 => (
+                vec![
+                    option_def!("display_rotation", OPT_VIDEO | HAS_ARG | OPT_DOUBLE | OPT_SPEC | OPT_INPUT, off => display_rotations, "set pure counter-clockwise rotation in degrees for stream(s)", "angle"),
+                    option_def!("display_hflip", OPT_VIDEO | OPT_BOOL | OPT_SPEC | OPT_INPUT, off => display_hflips, "set display horizontal flip for stream(s) (overrides any display rotation if it is not set)"),
+                    option_def!("display_vflip", OPT_VIDEO | OPT_BOOL | OPT_SPEC | OPT_INPUT, off => display_vflips, "set display vertical flip for stream(s) (overrides any display rotation if it is not set)"),
+                ],
+                &[],
+            ),
+            FFmpegVersion::V7_0 => (
+                vec![
+                    option_def!("stream_group", HAS_ARG | OPT_EXPERT | OPT_PERFILE | OPT_OUTPUT, func_arg => opt_stream_group, "add stream group", "id=number:st=number..."),
+                ],
+                &["psnr", "map_channel", "qphist"],
+            ),
+        }
+    }
+
+    /// Options accepted by the release, in the order of `OPTIONS` with the
+    /// added ones at the end.
+    pub fn options(self) -> &'static [OptionDef<'static>] {
+        static TABLES: Lazy<Vec<Vec<OptionDef<'static>>>> = Lazy::new(|| {
+            let mut tables: Vec<Vec<OptionDef>> = vec![];
+            let mut table = OPTIONS.to_vec();
+            for version in FFmpegVersion::ALL.iter() {
+                let (added, removed) = version.changes();
+                table.retain(|x| !removed.contains(&x.name));
+                table.extend(added);
+                tables.push(table.clone());
+            }
+            tables
+        });
+        &TABLES[self as usize]
+    }
+}
+
+impl str::FromStr for FFmpegVersion {
+    type Err = ();
+
+    /// Parse a release like `6.1`, a bare major version means its first
+    /// release.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let name = if s.contains('.') {
+            s.to_owned()
+        } else {
+            format!("{}.0", s)
+        };
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.name() == name)
+            .ok_or(())
+    }
+}
+
+/// `-vsync`, how the timestamps of the video frames are kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoSyncMethod {
+    /// `vfr` for the formats with variable frame rate, else `cfr`.
+    Auto,
+    Passthrough,
+    Cfr,
+    Vfr,
+    Drop,
+}
+
+impl Default for VideoSyncMethod {
+    fn default() -> Self {
+        VideoSyncMethod::Auto
+    }
+}
+
+impl VideoSyncMethod {
+    /// The names, in the order of the numbers FFmpeg still takes, from -1.
+    const ALL: [(&'static str, Self); 5] = [
+        ("auto", VideoSyncMethod::Auto),
+        ("passthrough", VideoSyncMethod::Passthrough),
+        ("cfr", VideoSyncMethod::Cfr),
+        ("vfr", VideoSyncMethod::Vfr),
+        ("drop", VideoSyncMethod::Drop),
+    ];
+
+    /// The value in the generated code.
+    pub fn c_name(self) -> &'static str {
+        match self {
+            VideoSyncMethod::Auto => "VSYNC_AUTO",
+            VideoSyncMethod::Passthrough => "VSYNC_PASSTHROUGH",
+            VideoSyncMethod::Cfr => "VSYNC_CFR",
+            VideoSyncMethod::Vfr => "VSYNC_VFR",
+            VideoSyncMethod::Drop => "VSYNC_DROP",
+        }
+    }
+}
+
+/// `-vsync`: a name, in any case, or one of the deprecated numbers from -1
+/// (`auto`) to 2 (`vfr`).
+pub fn parse_vsync(arg: &str) -> Result<VideoSyncMethod, String> {
+    let named = VideoSyncMethod::ALL
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(arg));
+    if let Some(&(_, method)) = named {
+        return Ok(method);
+    }
+    let n = parse_number("vsync", arg, OptionFlag::OPT_INT, -1., 2.)?;
+    warn!(
+        "Passing a number to -vsync is deprecated, use a string argument as \
+         described in the manual."
+    );
+    Ok(VideoSyncMethod::ALL[(n as i64 + 1) as usize].1)
+}
+
+bitflags! {
+    /// `-abort_on`, the conditions ffmpeg aborts on.
+    #[derive(Default)]
+    pub struct AbortOnFlags: i64 {
+        const EMPTY_OUTPUT          = 0x0001;
+        const EMPTY_OUTPUT_STREAM   = 0x0002;
+    }
+}
+
+impl AbortOnFlags {
+    const NAMES: [(&'static str, &'static str, Self); 2] = [
+        (
+            "empty_output",
+            "ABORT_ON_FLAG_EMPTY_OUTPUT",
+            AbortOnFlags::EMPTY_OUTPUT,
+        ),
+        (
+            "empty_output_stream",
+            "ABORT_ON_FLAG_EMPTY_OUTPUT_STREAM",
+            AbortOnFlags::EMPTY_OUTPUT_STREAM,
+        ),
+    ];
+
+    /// The value in the generated code.
+    pub fn c_expr(self) -> String {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|(_, _, flag)| self.contains(*flag))
+            .map(|(_, c_name, _)| *c_name)
+            .collect();
+        if names.is_empty() {
+            "0".into()
+        } else {
+            names.join(" | ")
+        }
+    }
+}
+
+/// `-abort_on`, read as `av_opt_eval_flags()` does: flag names or numbers,
+/// separated by `+` or `-` which set or unset them. A first flag without
+/// sign replaces the `current` flags.
+pub fn parse_abort_on(arg: &str, current: AbortOnFlags) -> Result<AbortOnFlags, String> {
+    let invalid = || format!("Unable to parse option value \"{}\"", arg);
+    let mut flags = current;
+    let mut rest = arg;
+    loop {
+        let (cmd, token) = match rest.as_bytes().first() {
+            Some(b'+') => (Some('+'), &rest[1..]),
+            Some(b'-') => (Some('-'), &rest[1..]),
+            _ => (None, rest),
+        };
+        let end = token.find(&['+', '-'][..]).unwrap_or(token.len());
+        let name = &token[..end];
+        let flag = match AbortOnFlags::NAMES.iter().find(|(x, _, _)| *x == name) {
+            Some(&(_, _, flag)) => flag,
+            None => name
+                .parse()
+                .ok()
+                .and_then(AbortOnFlags::from_bits)
+                .ok_or_else(invalid)?,
+        };
+        flags = match cmd {
+            Some('+') => flags | flag,
+            Some(_) => flags - flag,
+            None => flag,
+        };
+        rest = &token[end..];
+        if rest.is_empty() {
+            return Ok(flags);
+        }
+    }
+}
+
+/// The global options of ffmpeg_opt.c which have types of their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalOptions {
+    pub video_sync_method: VideoSyncMethod,
+    pub abort_on_flags: AbortOnFlags,
+    /// `-stats_period`, in microseconds.
+    pub stats_period: i64,
+}
+
+impl Default for GlobalOptions {
+    fn default() -> Self {
+        Self {
+            video_sync_method: Default::default(),
+            abort_on_flags: Default::default(),
+            stats_period: 500000,
+        }
+    }
+}
+
+pub static GLOBAL_OPTIONS: Lazy<Mutex<GlobalOptions>> = Lazy::new(Default::default);
 
 // In ffmpeg.h as extern value, TODO extern it
 pub static mut videotoolbox_pixfmt: *mut c_char = std::ptr::null_mut();
@@ -350,7 +668,6 @@ pub static mut dts_error_threshold: f32 = 3600. * 30.;
 
 pub static mut audio_volume: isize = 256;
 pub static mut audio_sync_method: isize = 0;
-pub static mut video_sync_method: isize = VSYNC_AUTO;
 pub static mut frame_drop_threshold: f32 = 0.;
 pub static mut do_deinterlace: isize = 0;
 pub static mut do_benchmark: isize = 0;
@@ -362,7 +679,6 @@ pub static mut start_at_zero: isize = 0;
 pub static mut copy_tb: isize = -1;
 pub static mut debug_ts: isize = 0;
 pub static mut exit_on_error: isize = 0;
-pub static mut abort_on_flags: isize = 0;
 pub static mut print_stats: isize = -1;
 pub static mut qp_hist: isize = 0;
 pub static mut stdin_interaction: isize = 1;
@@ -371,6 +687,8 @@ pub static mut max_error_rate: f32 = 2. / 3.;
 pub static mut filter_nbthreads: isize = 0;
 pub static mut filter_complex_nbthreads: isize = 0;
 pub static mut vstats_version: isize = 2;
+/// Table the commandline is split with, which the help is printed from.
+pub static mut option_table: Option<&'static [OptionDef<'static>]> = None;
 
 // In cmdutils.c in random order
 fn show_license(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
@@ -393,161 +711,371 @@ fn show_license(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
 }
 
 fn show_help(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<help message>");
+    let options = unsafe { option_table }.unwrap_or(&*OPTIONS);
+    print!("{}", show::help(options, arg));
     0
 }
 
 fn show_version(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<version message>");
+    print!("{}", show::version());
     0
 }
 
 fn show_buildconf(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<buildconf message>");
+    print!("{}", show::buildconf());
     0
 }
 
 fn show_formats(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<formats message>");
+    print!("{}", Listing::Formats.text());
     0
 }
 
 fn show_muxers(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<muxers message>");
+    print!("{}", Listing::Muxers.text());
     0
 }
 
 fn show_demuxers(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<demuxers message>");
+    print!("{}", Listing::Demuxers.text());
     0
 }
 
 fn show_devices(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<devices message>");
+    print!("{}", Listing::Devices.text());
     0
 }
 
 fn show_codecs(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<codecs message>");
+    print!("{}", Listing::Codecs.text());
     0
 }
 
 fn show_decoders(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<decoders message>");
+    print!("{}", Listing::Decoders.text());
     0
 }
 
 fn show_encoders(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<encoders message>");
+    print!("{}", Listing::Encoders.text());
     0
 }
 
 fn show_bsfs(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<bsfs message>");
+    print!("{}", Listing::Bsfs.text());
     0
 }
 
 fn show_protocols(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<protocols message>");
+    print!("{}", Listing::Protocols.text());
     0
 }
 
 fn show_filters(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<filers message>");
+    print!("{}", Listing::Filters.text());
     0
 }
 
 fn show_pix_fmts(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<pix_fmts message>");
+    print!("{}", Listing::PixFmts.text());
     0
 }
 
 fn show_layouts(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<layouts message>");
+    print!("{}", Listing::Layouts.text());
     0
 }
 
 fn show_sample_fmts(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<sample_fmts message>");
+    print!("{}", Listing::SampleFmts.text());
     0
 }
 
 fn show_colors(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<colors message>");
+    print!("{}", Listing::Colors.text());
     0
 }
 
 fn opt_loglevel(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<loglevel message>");
-    0
+    match logging::set_loglevel(arg) {
+        Ok(()) => 0,
+        Err(()) => AVERROR(libc::EINVAL) as i64,
+    }
 }
 
+/// Runs with the global options, after `parse_loglevel()` has opened the
+/// report for the same `-report`, so `init_report()` returns right away.
 fn opt_report(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<report message>");
-    0
+    match logging::init_report(None) {
+        Ok(()) => 0,
+        Err(()) => AVERROR(libc::EINVAL) as i64,
+    }
+}
+
+/// The number of bytes of `-max_alloc`, a decimal number as `strtol()` reads
+/// it.
+pub fn parse_max_alloc(arg: &str) -> Result<usize, String> {
+    let digits = arg.trim_start();
+    let digits = digits.strip_prefix('+').unwrap_or(digits);
+    if digits.is_empty() || !digits.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(format!("Invalid max_alloc \"{}\".", arg));
+    }
+    digits
+        .parse()
+        .map_err(|_| format!("Invalid max_alloc \"{}\", too large.", arg))
+}
+
+/// The CPU flags of `-cpuflags`, like `-sse+mmx` relative to the detected
+/// ones or `sse2` alone, as `av_parse_cpu_caps()` reads them.
+pub fn parse_cpuflags(arg: &str) -> Result<c_uint, String> {
+    let invalid = || format!("Invalid cpuflags \"{}\".", arg);
+    let arg_c = CString::new(arg).map_err(|_| invalid())?;
+    let mut flags = unsafe { ffi::av_get_cpu_flags() } as c_uint;
+    if unsafe { ffi::av_parse_cpu_caps(&mut flags, arg_c.as_ptr()) } < 0 {
+        return Err(invalid());
+    }
+    Ok(flags)
+}
+
+/// The seconds of `-timelimit`.
+pub fn parse_timelimit(arg: &str) -> Result<i64, String> {
+    parse_number(
+        "timelimit",
+        arg,
+        OptionFlag::OPT_INT64,
+        0.,
+        c_int::MAX as f64,
+    )
+    .map(|x| x as i64)
+}
+
+/// The return of the callbacks of `-max_alloc`, `-cpuflags` and `-timelimit`,
+/// which only check their argument. The generated code applies them, ffgen
+/// itself runs without them.
+fn check_limit<T>(parsed: Result<T, String>) -> i64 {
+    match parsed {
+        Ok(_) => 0,
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
 }
 
 fn opt_max_alloc(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<max_alloc message>");
-    0
+    check_limit(parse_max_alloc(arg))
 }
 
 fn opt_cpuflags(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<cpuflags message>");
-    0
+    check_limit(parse_cpuflags(arg))
 }
 
 fn show_sources(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<sources message>");
-    0
+    match show::device_list(arg, false) {
+        Ok(text) => {
+            print!("{}", text);
+            0
+        }
+        Err(ret) => ret as i64,
+    }
 }
 
 fn show_sinks(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    println!("<sinks message>");
-    0
+    match show::device_list(arg, true) {
+        Ok(text) => {
+            print!("{}", text);
+            0
+        }
+        Err(ret) => ret as i64,
+    }
 }
 
 fn opt_timelimit(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    check_limit(parse_timelimit(arg))
 }
 
 // In ffmpeg_opt.c, in corresponding order
 fn show_hwaccels(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    print!("{}", Listing::Hwaccels.text());
+    0
 }
 
 fn opt_abort_on(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    let mut global = GLOBAL_OPTIONS.lock().unwrap();
+    match parse_abort_on(arg, global.abort_on_flags) {
+        Ok(flags) => {
+            global.abort_on_flags = flags;
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
 }
 fn opt_sameq(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    error!(
+        "Option '{}' was removed. If you are looking for an option to preserve \
+         the quality (which is not what -{} was for), use -qscale 0 or an \
+         equivalent quality factor option.",
+        opt, opt
+    );
+    AVERROR(libc::EINVAL) as i64
 }
 fn opt_video_channel(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    warn!("This option is deprecated, use -channel.");
+    opt_default(optctx, "channel", OsStr::new(arg)) as i64
 }
 fn opt_video_standard(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    warn!("This option is deprecated, use -standard.");
+    opt_default(optctx, "standard", OsStr::new(arg)) as i64
 }
+/// `-acodec`, rewritten into `-codec:a` by `rewrite_alias()`.
 fn opt_audio_codec(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
+}
+/// What an alias option is rewritten into by `split_commandline()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rewrite {
+    /// An option of the table, like `-codec:v` for `-vcodec`.
+    Option(String),
+    /// A codec option set without looking it up, like `b:v` for `-b`.
+    CodecOption(String),
 }
+
+/// The canonical form of the alias `opt`, as the `func_arg` of the alias
+/// passes it on in FFmpeg, with its warnings about the ambiguous ones.
+pub fn rewrite_alias(opt: &str) -> Option<Rewrite> {
+    let (name, specifier) = match opt.find(':') {
+        Some(n) => (&opt[..n], &opt[n..]),
+        None => (opt, ""),
+    };
+    let option = match name {
+        "vcodec" | "acodec" | "scodec" | "dcodec" => format!("codec:{}", &name[..1]),
+        "vtag" | "atag" | "stag" | "vbsf" | "absf" => format!("{}:{}", &name[1..], &name[..1]),
+        "vf" => "filter:v".into(),
+        "af" => "filter:a".into(),
+        "aq" => "q:a".into(),
+        "vframes" | "aframes" | "dframes" => format!("frames:{}", &name[..1]),
+        "qscale" if specifier.is_empty() => {
+            warn!("Please use -q:a or -q:v, -qscale is ambiguous");
+            "q:v".into()
+        }
+        "qscale" => format!("q{}", specifier),
+        "b" | "profile" if specifier.is_empty() => {
+            warn!("Please use -{0}:a or -{0}:v, -{0} is ambiguous", name);
+            return Some(Rewrite::CodecOption(format!("{}:v", name)));
+        }
+        "ab" if specifier.is_empty() => return Some(Rewrite::CodecOption("b:a".into())),
+        "b" | "ab" | "profile" => return Some(Rewrite::CodecOption(opt.into())),
+        _ => return None,
+    };
+    Some(Rewrite::Option(option))
+}
+
+/// Set the canonical option of an alias, for the aliases which reach their
+/// `func_arg` without being rewritten by `split_commandline()`.
+fn forward_alias(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() };
+    let ret = match (rewrite_alias(opt), o) {
+        (Some(Rewrite::Option(key)), o) => parse_option(o, &key, arg, &*OPTIONS),
+        (Some(Rewrite::CodecOption(key)), Some(o)) => {
+            let (key, arg) = (CString::new(key).unwrap(), CString::new(arg).unwrap());
+            unsafe { ffi::av_dict_set(&mut o.g.codec_opts, key.as_ptr(), arg.as_ptr(), 0) };
+            Ok(())
+        }
+        _ => {
+            error!("Option {} is not an alias", opt);
+            Err(())
+        }
+    };
+    match ret {
+        Ok(()) => 0,
+        Err(()) => AVERROR(libc::EINVAL) as i64,
+    }
+}
+
+/// `-vcodec`, rewritten into `-codec:v` by `rewrite_alias()`.
 fn opt_video_codec(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-scodec`, rewritten into `-codec:s` by `rewrite_alias()`.
 fn opt_subtitle_codec(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-dcodec`, rewritten into `-codec:d` by `rewrite_alias()`.
 fn opt_data_codec(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
 fn opt_map(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
 }
+/// The attachment streams are created with the output file, when the file is
+/// read.
 fn opt_attach(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() }.unwrap();
+    o.attachments.push(arg.into());
+    0
+}
+/// `[file.stream.channel|-1][?][:ofile.ostream]` of `-map_channel`, where
+/// `-1` mutes the channel. Whether the input stream is an audio stream with
+/// the channel is only known once the inputs are opened, and the `?` makes
+/// that check fail silently, so it is only accepted here.
+pub fn parse_map_channel(arg: &str) -> Result<AudioChannelMap, String> {
+    let syntax_error = || {
+        format!(
+            "Syntax error in '{}', mapchan usage: \
+             [file.stream.channel|-1][?][:ofile.ostream]",
+            arg
+        )
+    };
+    let (input, output) = match arg.find(':') {
+        Some(n) => (&arg[..n], Some(&arg[n + 1..])),
+        None => (arg, None),
+    };
+    // FFmpeg finds the `?` anywhere, it is usually put last.
+    let input = input.strip_suffix('?').unwrap_or(input);
+    let output = output.map(|x| x.strip_suffix('?').unwrap_or(x));
+    let indices = |x: &str| -> Option<Vec<isize>> {
+        x.split('.')
+            .map(|x| x.parse().ok().filter(|&x: &isize| x >= 0))
+            .collect()
+    };
+
+    let (file_idx, stream_idx, channel_idx) = if input == "-1" {
+        (-1, -1, -1)
+    } else {
+        match indices(input).as_deref() {
+            Some(&[file_idx, stream_idx, channel_idx]) => (file_idx, stream_idx, channel_idx),
+            _ => return Err(syntax_error()),
+        }
+    };
+    let (ofile_idx, ostream_idx) = match output.map(indices) {
+        None => (-1, -1),
+        Some(Some(x)) if x.len() == 2 => (x[0], x[1]),
+        Some(_) => return Err(syntax_error()),
+    };
+    Ok(AudioChannelMap {
+        file_idx,
+        stream_idx,
+        channel_idx,
+        ofile_idx,
+        ostream_idx,
+    })
 }
+
 fn opt_map_channel(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() }.unwrap();
+    match parse_map_channel(arg) {
+        Ok(m) => {
+            o.audio_channel_maps.push(m);
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
 }
 fn opt_sdp_file(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
@@ -570,8 +1098,14 @@ fn opt_streamid(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
 }
 
+/// Targets are expanded by `split_commandline()`, which puts their options
+/// in the group instead of this one. Anything running it anyway is rejected.
 fn opt_target(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    error!(
+        "-{} {} is applied while splitting the commandline, not by its option.",
+        opt, arg
+    );
+    AVERROR(libc::EINVAL) as i64
 }
 fn opt_vstats_file(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
@@ -579,50 +1113,137 @@ fn opt_vstats_file(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
 fn opt_vstats(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
 }
+/// `-vframes`, rewritten into `-frames:v` by `rewrite_alias()`.
 fn opt_video_frames(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-aframes`, rewritten into `-frames:a` by `rewrite_alias()`.
 fn opt_audio_frames(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-dframes`, rewritten into `-frames:d` by `rewrite_alias()`.
 fn opt_data_frames(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `opt_default()` into the codec and format options of the group of the
+/// OptionsContext instead of the global ones.
 fn opt_default_new(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() }.unwrap();
+    unsafe {
+        let (cbak, fbak) = (codec_opts, format_opts);
+        codec_opts = ptr::null_mut();
+        format_opts = ptr::null_mut();
+
+        let ret = opt_default(ptr::null_mut(), opt, OsStr::new(arg));
+        ffi::av_dict_copy(&mut o.g.codec_opts, codec_opts, 0);
+        ffi::av_dict_copy(&mut o.g.format_opts, format_opts, 0);
+        ffi::av_dict_free(&raw mut codec_opts);
+        ffi::av_dict_free(&raw mut format_opts);
+
+        codec_opts = cbak;
+        format_opts = fbak;
+        ret as i64
+    }
 }
+/// Presets are applied by `split_commandline()`, which puts their options in
+/// the group instead of this one. Anything running it anyway is rejected.
 fn opt_preset(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    error!(
+        "-{} {} is applied while splitting the commandline, not by its option.",
+        opt, arg
+    );
+    AVERROR(libc::EINVAL) as i64
 }
+/// `-vtag`, `-atag`, `-stag`, `-vbsf` and `-absf`, rewritten into `-tag` and
+/// `-bsf` with the stream type as specifier by `rewrite_alias()`.
 fn opt_old2new(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-b` and `-ab`, rewritten into the codec option `b:v` or `b:a` by
+/// `rewrite_alias()`.
 fn opt_bitrate(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-qscale[:spec]`, rewritten into `-q[:spec]` by `rewrite_alias()`.
 fn opt_qscale(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-profile`, rewritten into the codec option `profile:v` by
+/// `rewrite_alias()`.
 fn opt_profile(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-vf`, rewritten into `-filter:v` by `rewrite_alias()`.
 fn opt_video_filters(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
+/// `-af`, rewritten into `-filter:a` by `rewrite_alias()`.
 fn opt_audio_filters(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
 fn opt_vsync(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    match parse_vsync(arg) {
+        Ok(method) => {
+            GLOBAL_OPTIONS.lock().unwrap().video_sync_method = method;
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
 }
 fn opt_timecode(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
 }
+/// `-channel_layout`: the layout, by name like `5.1` or as a mask, and its
+/// number of channels.
+pub fn parse_channel_layout(arg: &str) -> Result<(u64, c_int), String> {
+    let unknown = || format!("Unknown channel layout: {}", arg);
+    let arg_c = CString::new(arg).map_err(|_| unknown())?;
+    let layout = unsafe { ffi::av_get_channel_layout(arg_c.as_ptr()) };
+    if layout == 0 {
+        return Err(unknown());
+    }
+    Ok((layout, unsafe {
+        ffi::av_get_channel_layout_nb_channels(layout)
+    }))
+}
+
+/// `-sample_fmt`, checked as `open_output_file()` does.
+pub fn parse_sample_fmt(arg: &str) -> Result<ffi::AVSampleFormat, String> {
+    let invalid = || format!("Invalid sample format '{}'", arg);
+    let arg_c = CString::new(arg).map_err(|_| invalid())?;
+    match unsafe { ffi::av_get_sample_fmt(arg_c.as_ptr()) } {
+        ffi::AVSampleFormat_AV_SAMPLE_FMT_NONE => Err(invalid()),
+        fmt => Ok(fmt),
+    }
+}
+
+/// Keeps the layout mask in the `channel_layout` codec option, and sets `-ac`
+/// too, from the number of channels of the layout.
 fn opt_channel_layout(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    let (layout, channels) = match parse_channel_layout(arg) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{}", e);
+            return AVERROR(libc::EINVAL) as i64;
+        }
+    };
+    let ret = opt_default_new(optctx, opt, &layout.to_string());
+    if ret < 0 {
+        return ret;
+    }
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() };
+    let ac = format!("ac{}", opt.find(':').map_or("", |n| &opt[n..]));
+    match parse_option(o, &ac, &channels.to_string(), &*OPTIONS) {
+        Ok(()) => 0,
+        Err(()) => AVERROR(libc::EINVAL) as i64,
+    }
 }
+/// `-aq`, rewritten into `-q:a` by `rewrite_alias()`.
 fn opt_audio_qscale(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
-    unimplemented!()
+    forward_alias(optctx, opt, arg)
 }
 fn opt_filter_complex(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
@@ -635,9 +1256,68 @@ fn opt_progress(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
     unimplemented!()
 }
 
+// Options of the later releases
+
+/// `-stats_period`: a positive duration, in microseconds.
+pub fn parse_stats_period(arg: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid duration specification for stats_period: {}", arg);
+    let arg_c = CString::new(arg).map_err(|_| invalid())?;
+    let mut period = 0;
+    if unsafe { ffi::av_parse_time(&mut period, arg_c.as_ptr(), 1) } < 0 {
+        return Err(invalid());
+    }
+    if period <= 0 {
+        return Err(format!("stats_period {} must be positive.", arg));
+    }
+    Ok(period)
+}
+
+fn opt_stats_period(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
+    match parse_stats_period(arg) {
+        Ok(period) => {
+            GLOBAL_OPTIONS.lock().unwrap().stats_period = period;
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
+}
+
+/// `type=<type>[:<key>=<value>...]` of `-stream_group`. The streams and
+/// options are only known once the output file is opened, so only the type is
+/// checked here.
+pub fn parse_stream_group(arg: &str) -> Result<&str, String> {
+    let group_type = arg
+        .split(':')
+        .filter_map(|x| x.strip_prefix("type="))
+        .next()
+        .ok_or_else(|| format!("No type specified for stream group in \"{}\"", arg))?;
+    match group_type {
+        "iamf_audio_element" | "iamf_mix_presentation" => Ok(group_type),
+        _ => Err(format!("Unknown stream group type: {}", group_type)),
+    }
+}
+
+fn opt_stream_group(optctx: *mut c_void, opt: &str, arg: &str) -> i64 {
+    let o = unsafe { (optctx as *mut OptionsContext).as_mut() }.unwrap();
+    match parse_stream_group(arg) {
+        Ok(_) => {
+            o.stream_groups.push(arg.into());
+            0
+        }
+        Err(e) => {
+            error!("{}", e);
+            AVERROR(libc::EINVAL) as i64
+        }
+    }
+}
+
 #[cfg(test)]
 mod command_tests {
     use super::*;
+    use crate::cmdutils::SpecifierOpt;
 
     fn opt_cpuflags(_: *mut c_void, _: &str, _: &str) -> i64 {
         0
@@ -659,9 +1339,272 @@ mod command_tests {
         assert_eq!(opt.argname, Some("flags"));
     }
 
+    #[test]
+    fn global_limits() {
+        assert_eq!(parse_max_alloc("1048576"), Ok(1048576));
+        assert_eq!(parse_max_alloc(" +16"), Ok(16));
+        assert!(parse_max_alloc("1M").is_err());
+        assert!(parse_max_alloc("-1").is_err());
+        assert!(parse_max_alloc("99999999999999999999999").is_err());
+
+        assert_eq!(parse_timelimit("60"), Ok(60));
+        assert!(parse_timelimit("1.5").is_err());
+        assert!(parse_timelimit("-1").is_err());
+        assert!(parse_timelimit("4294967296").is_err());
+
+        assert_eq!(parse_cpuflags("0"), Ok(0));
+        assert!(parse_cpuflags("+nonexistent").is_err());
+
+        // The callbacks only check the argument, a 1 byte `-max_alloc` would
+        // break ffgen otherwise.
+        let null = std::ptr::null_mut();
+        assert_eq!(opt_max_alloc(null, "max_alloc", "1"), 0);
+        assert!(opt_max_alloc(null, "max_alloc", "1M") < 0);
+        assert_eq!(opt_timelimit(null, "timelimit", "60"), 0);
+        assert!(opt_timelimit(null, "timelimit", "-1") < 0);
+        assert!(super::opt_cpuflags(null, "cpuflags", "+nonexistent") < 0);
+    }
+
+    #[test]
+    fn split_only_options() {
+        // Applied by `split_commandline()`, running them is an error.
+        let null = std::ptr::null_mut();
+        assert!(opt_preset(null, "vpre", "fast") < 0);
+        assert!(opt_target(null, "target", "pal-dvd") < 0);
+    }
+
+    #[test]
+    fn sync_and_abort_options() {
+        assert_eq!(parse_vsync("CFR"), Ok(VideoSyncMethod::Cfr));
+        assert_eq!(parse_vsync("drop"), Ok(VideoSyncMethod::Drop));
+        assert_eq!(parse_vsync("-1"), Ok(VideoSyncMethod::Auto));
+        assert_eq!(parse_vsync("2"), Ok(VideoSyncMethod::Vfr));
+        assert!(parse_vsync("3").is_err());
+        assert!(parse_vsync("vscfr").is_err());
+
+        let empty = AbortOnFlags::empty();
+        let both = AbortOnFlags::all();
+        assert_eq!(
+            parse_abort_on("empty_output", empty),
+            Ok(AbortOnFlags::EMPTY_OUTPUT)
+        );
+        assert_eq!(
+            parse_abort_on("empty_output+empty_output_stream", empty),
+            Ok(both)
+        );
+        assert_eq!(
+            parse_abort_on("-empty_output", both),
+            Ok(AbortOnFlags::EMPTY_OUTPUT_STREAM)
+        );
+        assert_eq!(
+            parse_abort_on("2", both),
+            Ok(AbortOnFlags::EMPTY_OUTPUT_STREAM)
+        );
+        assert!(parse_abort_on("empty", empty).is_err());
+        assert!(parse_abort_on("4", empty).is_err());
+        assert_eq!(
+            both.c_expr(),
+            "ABORT_ON_FLAG_EMPTY_OUTPUT | ABORT_ON_FLAG_EMPTY_OUTPUT_STREAM"
+        );
+        assert_eq!(empty.c_expr(), "0");
+    }
+
+    #[test]
+    fn audio_formats() {
+        assert_eq!(parse_channel_layout("stereo"), Ok((3, 2)));
+        assert_eq!(parse_channel_layout("mono"), Ok((4, 1)));
+        assert_eq!(
+            parse_channel_layout("quadrophonic"),
+            Err("Unknown channel layout: quadrophonic".into())
+        );
+        assert_eq!(parse_channel_layout("5.1"), Ok((0x3f, 6)));
+        assert_eq!(parse_channel_layout("5.1(side)"), Ok((0x60f, 6)));
+
+        let mut group = OptionGroup::new_anonymous();
+        let mut o = OptionsContext::new(&mut group);
+        let optctx = &mut o as *mut OptionsContext as *mut c_void;
+        assert_eq!(
+            opt_channel_layout(optctx, "channel_layout:a", "5.1(side)"),
+            0
+        );
+        let key = CString::new("channel_layout:a").unwrap();
+        let entry = unsafe { ffi::av_dict_get(o.g.codec_opts, key.as_ptr(), ptr::null(), 0) };
+        let layout = unsafe { std::ffi::CStr::from_ptr((*entry).value) };
+        assert_eq!(layout.to_str(), Ok("1551"));
+        let channels: Vec<(&str, isize)> = o
+            .audio_channels
+            .iter()
+            .map(|x| (x.specifier.as_str(), unsafe { x.u.i }))
+            .collect();
+        assert_eq!(channels, [("a", 6)]);
+
+        assert_eq!(parse_sample_fmt("s16").map(|_| ()), Ok(()));
+        assert_eq!(
+            parse_sample_fmt("s17"),
+            Err("Invalid sample format 's17'".into())
+        );
+    }
+
+    #[test]
+    fn frame_alias_callbacks() {
+        let mut group = OptionGroup::new_anonymous();
+        let mut o = OptionsContext::new(&mut group);
+        let optctx = &mut o as *mut OptionsContext as *mut c_void;
+        assert_eq!(opt_video_frames(optctx, "vframes", "100"), 0);
+        assert_eq!(opt_audio_frames(optctx, "aframes", "1e3"), 0);
+        assert_eq!(opt_data_frames(optctx, "dframes:1", "5"), 0);
+        let limits: Vec<(&str, i64)> = o
+            .max_frames
+            .iter()
+            .map(|x| (x.specifier.as_str(), unsafe { x.u.i64 }))
+            .collect();
+        assert_eq!(limits, [("v", 100), ("a", 1000), ("d", 5)]);
+    }
+
+    #[test]
+    fn alias_callbacks() {
+        let mut group = OptionGroup::new_anonymous();
+        let mut o = OptionsContext::new(&mut group);
+        let optctx = &mut o as *mut OptionsContext as *mut c_void;
+        assert_eq!(opt_video_codec(optctx, "vcodec", "libx264"), 0);
+        assert_eq!(opt_audio_filters(optctx, "af", "volume=2"), 0);
+        assert_eq!(opt_qscale(optctx, "qscale:a", "3"), 0);
+        assert_eq!(opt_audio_qscale(optctx, "aq", "4"), 0);
+        let specifiers = |so: &[SpecifierOpt]| -> Vec<String> {
+            so.iter().map(|x| x.specifier.clone()).collect()
+        };
+        assert_eq!(specifiers(&o.codec_names), ["v"]);
+        assert_eq!(specifiers(&o.filters), ["a"]);
+        assert_eq!(specifiers(&o.qscale), ["a", "a"]);
+        assert_eq!(unsafe { o.qscale[1].u.dbl }, 4.);
+
+        assert_eq!(opt_bitrate(optctx, "ab", "128k"), 0);
+        assert_eq!(opt_default_new(optctx, "b:v", "1M"), 0);
+        let codec_opt = |key: &str| {
+            let key = CString::new(key).unwrap();
+            let entry =
+                unsafe { ffi::av_dict_get(o.g.codec_opts, key.as_ptr(), ptr::null(), 0).as_ref() };
+            entry.map(|x| {
+                unsafe { std::ffi::CStr::from_ptr(x.value) }
+                    .to_str()
+                    .unwrap()
+            })
+        };
+        assert_eq!(codec_opt("b:a"), Some("128k"));
+        assert_eq!(codec_opt("b:v"), Some("1M"));
+        // Not into the global codec options.
+        assert!(
+            unsafe { ffi::av_dict_get(codec_opts, b"b:v\0".as_ptr() as _, ptr::null(), 0) }
+                .is_null()
+        );
+        crate::ffmpeg_opt::uninit_options(&mut o, &*OPTIONS);
+    }
+
+    #[test]
+    fn later_release_options() {
+        assert_eq!(parse_stats_period("0.5"), Ok(500000));
+        assert_eq!(
+            parse_stats_period("0"),
+            Err("stats_period 0 must be positive.".into())
+        );
+        assert!(parse_stats_period("-1").is_err());
+        assert!(parse_stats_period("often").is_err());
+
+        assert_eq!(
+            parse_stream_group("type=iamf_audio_element:id=1:st=0"),
+            Ok("iamf_audio_element")
+        );
+        assert!(parse_stream_group("id=1:st=0").is_err());
+        assert!(parse_stream_group("type=tile_grid:st=0").is_err());
+
+        let mut group = OptionGroup::new_anonymous();
+        let mut o = OptionsContext::new(&mut group);
+        let optctx = &mut o as *mut OptionsContext as *mut c_void;
+        let arg = "type=iamf_mix_presentation:id=2:stg=0";
+        assert_eq!(opt_stream_group(optctx, "stream_group", arg), 0);
+        assert!(opt_stream_group(optctx, "stream_group", "st=0") < 0);
+        assert_eq!(o.stream_groups, [arg]);
+    }
+
+    #[test]
+    fn ffmpeg_versions() {
+        assert_eq!("6.1".parse(), Ok(FFmpegVersion::V6_1));
+        assert_eq!("7".parse(), Ok(FFmpegVersion::V7_0));
+        assert_eq!("3.4".parse::<FFmpegVersion>(), Err(()));
+
+        let libavutil = |major: u32, minor: u32| {
+            FFmpegVersion::from_libavutil((major << 16) | (minor << 8) | 100)
+        };
+        assert_eq!(libavutil(56, 51), FFmpegVersion::V4_3);
+        assert_eq!(libavutil(57, 27), FFmpegVersion::V5_0);
+        assert_eq!(libavutil(58, 29), FFmpegVersion::V6_1);
+        assert_eq!(libavutil(55, 78), FFmpegVersion::V4_3);
+        assert_eq!(libavutil(60, 3), FFmpegVersion::V7_0);
+
+        assert_eq!(FFmpegVersion::V4_3.options().len(), OPTIONS.len());
+        for version in FFmpegVersion::ALL.iter() {
+            let options = version.options();
+            for (i, x) in options.iter().enumerate() {
+                assert!(
+                    options[i + 1..].iter().all(|y| y.name != x.name),
+                    "{}",
+                    x.name
+                );
+            }
+        }
+    }
+
     #[test]
     fn option_operation_macro() {
         // Test whether it compiles.
         let _ = option_operation!(func_arg => show_help);
     }
+
+    #[test]
+    fn map_channel_syntax() {
+        let map = |x: &str| {
+            parse_map_channel(x).map(|m| {
+                (
+                    m.file_idx,
+                    m.stream_idx,
+                    m.channel_idx,
+                    m.ofile_idx,
+                    m.ostream_idx,
+                )
+            })
+        };
+        assert_eq!(map("0.1.2"), Ok((0, 1, 2, -1, -1)));
+        assert_eq!(map("0.1.2?:0.1"), Ok((0, 1, 2, 0, 1)));
+        assert_eq!(map("0.1.2:0.1?"), Ok((0, 1, 2, 0, 1)));
+        assert_eq!(map("-1"), Ok((-1, -1, -1, -1, -1)));
+        assert_eq!(map("-1:0.1"), Ok((-1, -1, -1, 0, 1)));
+        for invalid in [
+            "", "0.1", "0.1.2.3", "0.-1.2", "0..2", "0.1.x", "0.1.2:0", "-1:", "-2",
+        ]
+        .iter()
+        {
+            assert!(map(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn aliases() {
+        let option = |x: &str| Some(Rewrite::Option(x.into()));
+        let codec_option = |x: &str| Some(Rewrite::CodecOption(x.into()));
+        assert_eq!(rewrite_alias("vcodec"), option("codec:v"));
+        assert_eq!(rewrite_alias("dcodec"), option("codec:d"));
+        assert_eq!(rewrite_alias("atag"), option("tag:a"));
+        assert_eq!(rewrite_alias("vbsf"), option("bsf:v"));
+        assert_eq!(rewrite_alias("af"), option("filter:a"));
+        assert_eq!(rewrite_alias("aq"), option("q:a"));
+        assert_eq!(rewrite_alias("qscale"), option("q:v"));
+        assert_eq!(rewrite_alias("qscale:a"), option("q:a"));
+        assert_eq!(rewrite_alias("b"), codec_option("b:v"));
+        assert_eq!(rewrite_alias("b:a"), codec_option("b:a"));
+        assert_eq!(rewrite_alias("ab"), codec_option("b:a"));
+        assert_eq!(rewrite_alias("profile"), codec_option("profile:v"));
+        assert_eq!(rewrite_alias("vframes"), option("frames:v"));
+        assert_eq!(rewrite_alias("dframes"), option("frames:d"));
+        assert_eq!(rewrite_alias("c:v"), None);
+    }
 }
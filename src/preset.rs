@@ -0,0 +1,262 @@
+//! Preset files: the `.ffpreset` files of `-vpre`, `-apre`, `-spre` and
+//! `-fpre`, read by `opt_preset()` in FFmpeg, and the `.avpreset` files of
+//! `-pre`, read for each output stream by `get_preset_file_2()`.
+use log::debug;
+use rusty_ffmpeg::ffi;
+
+use std::{
+    env,
+    ffi::{CStr, OsStr},
+    fs,
+    path::PathBuf,
+};
+
+/// One `key=value` line of a preset file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresetEntry {
+    /// 1-based
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub path: PathBuf,
+    pub entries: Vec<PresetEntry>,
+}
+
+/// `FFMPEG_DATADIR` (and `AVCONV_DATADIR`, the same directory) of the linked
+/// build: the `--datadir` it was configured with, otherwise the `share/ffmpeg`
+/// of its `--prefix`.
+fn data_dir() -> PathBuf {
+    let configuration = unsafe { CStr::from_ptr(ffi::avutil_configuration()) }
+        .to_string_lossy()
+        .into_owned();
+    let flag = |name: &str| {
+        configuration
+            .split_whitespace()
+            .rev()
+            .find_map(|x| x.strip_prefix(name))
+            .map(|x| x.trim_matches(|c| c == '\'' || c == '"').to_owned())
+    };
+    match (flag("--datadir="), flag("--prefix=")) {
+        (Some(datadir), _) => datadir.into(),
+        (None, Some(prefix)) => PathBuf::from(prefix).join("share/ffmpeg"),
+        (None, None) => "/usr/local/share/ffmpeg".into(),
+    }
+}
+
+/// The directories presets are looked up in, in order: the one of the
+/// environment variable, the dot directory in `$HOME`, and the data directory.
+fn bases(var: &str, dot_dir: &str) -> Vec<PathBuf> {
+    let mut bases = vec![];
+    bases.extend(env::var_os(var).map(PathBuf::from));
+    bases.extend(env::var_os("HOME").map(|home| PathBuf::from(home).join(dot_dir)));
+    bases.push(data_dir());
+    bases
+}
+
+/// The first existing `<name>.<extension>` or `<codec>-<name>.<extension>`
+/// in the bases, the codec prefixed one first when `codec_first`.
+fn find(
+    bases: &[PathBuf],
+    name: &str,
+    codec: Option<&str>,
+    extension: &str,
+    codec_first: bool,
+) -> Option<PathBuf> {
+    for base in bases.iter() {
+        let plain = base.join(format!("{}.{}", name, extension));
+        let prefixed = codec.map(|codec| base.join(format!("{}-{}.{}", codec, name, extension)));
+        let candidates = if codec_first {
+            vec![prefixed, Some(plain)]
+        } else {
+            vec![Some(plain), prefixed]
+        };
+        for path in candidates.into_iter().flatten() {
+            debug!("Looking for preset file '{}'.", path.display());
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// `get_preset_file()`: the `.ffpreset` file of `-vpre`, `-apre` and `-spre`,
+/// or the path given to `-fpre`.
+pub fn find_ffpreset(name: &OsStr, is_path: bool, codec: Option<&str>) -> Option<PathBuf> {
+    if is_path {
+        let path = PathBuf::from(name);
+        return Some(path).filter(|x| x.is_file());
+    }
+    find(
+        &bases("FFMPEG_DATADIR", ".ffmpeg"),
+        &name.to_string_lossy(),
+        codec,
+        "ffpreset",
+        false,
+    )
+}
+
+/// `get_preset_file_2()`: the `.avpreset` file of `-pre`.
+pub fn find_avpreset(name: &str, codec: Option<&str>) -> Option<PathBuf> {
+    find(
+        &bases("AVCONV_DATADIR", ".avconv"),
+        name,
+        codec,
+        "avpreset",
+        true,
+    )
+}
+
+/// Lines of `.ffpreset` files are `key=value`, with lines beginning with `#`
+/// and empty lines skipped. Neither the key nor the value can be empty.
+fn parse_ffpreset(text: &str) -> Result<Vec<PresetEntry>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // `av_strtok()` skips the leading delimiters.
+        let pair = line.trim_start_matches('=');
+        match pair.find('=') {
+            Some(n) if n + 1 < pair.len() => entries.push(PresetEntry {
+                line: i + 1,
+                key: pair[..n].into(),
+                value: pair[n + 1..].into(),
+            }),
+            _ => return Err(format!("Invalid syntax: '{}'", line)),
+        }
+    }
+    Ok(entries)
+}
+
+/// Lines of `.avpreset` files are `key=value`, with lines beginning with `#`
+/// and empty lines skipped. The value can be empty.
+fn parse_avpreset(text: &str) -> Result<Vec<PresetEntry>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.find('=') {
+            Some(n) => entries.push(PresetEntry {
+                line: i + 1,
+                key: line[..n].into(),
+                value: line[n + 1..].into(),
+            }),
+            None => return Err("Invalid line found in the preset file.".into()),
+        }
+    }
+    Ok(entries)
+}
+
+fn read(
+    path: PathBuf,
+    parse: fn(&str) -> Result<Vec<PresetEntry>, String>,
+) -> Result<Preset, String> {
+    let text = fs::read(&path)
+        .map_err(|e| format!("Failed to read preset file '{}': {}", path.display(), e))?;
+    // The values are given to FFmpeg as C strings.
+    if text.contains(&0) {
+        return Err(format!("{}: Contains a NUL byte", path.display()));
+    }
+    let entries =
+        parse(&String::from_utf8_lossy(&text)).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Preset { path, entries })
+}
+
+/// The preset of `-vpre`, `-apre`, `-spre` or `-fpre`, found with the codec
+/// given for the type of the option.
+pub fn load_ffpreset(name: &OsStr, is_path: bool, codec: Option<&str>) -> Result<Preset, String> {
+    let name_str = name.to_string_lossy();
+    match find_ffpreset(name, is_path, codec) {
+        Some(path) => read(path, parse_ffpreset),
+        None if name_str.starts_with("libx264-lossless") => {
+            Err("Please use -preset <speed> -qp 0".into())
+        }
+        None => Err(format!("File for preset '{}' not found", name_str)),
+    }
+}
+
+/// The preset of `-pre`, found with the codec of the streams it applies to.
+pub fn load_avpreset(name: &str, codec: Option<&str>) -> Result<Preset, String> {
+    match find_avpreset(name, codec) {
+        Some(path) => read(path, parse_avpreset),
+        None => Err(format!("Preset {} could not be opened.", name)),
+    }
+}
+
+/// Where a value injected from a preset came from, for the generated code.
+pub fn origin(preset: &Preset, entry: &PresetEntry, opt: &str, arg: &OsStr) -> String {
+    format!(
+        "{}:{} from -{} {}",
+        preset.path.display(),
+        entry.line,
+        opt,
+        arg.to_string_lossy()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn preset_syntax() {
+        let entries = parse_ffpreset("# comment\n\ncoder=1\r\nflags=+loop\n==g=250\n").unwrap();
+        let pairs: Vec<_> = entries
+            .iter()
+            .map(|x| (x.line, x.key.as_str(), x.value.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [(3, "coder", "1"), (4, "flags", "+loop"), (5, "g", "250")]
+        );
+        assert!(parse_ffpreset("coder=").is_err());
+        assert!(parse_ffpreset("coder").is_err());
+
+        let entries = parse_avpreset("#x\nb=\nqmin=10").unwrap();
+        assert_eq!(entries[0].value, "");
+        assert_eq!((entries[1].line, entries[1].key.as_str()), (3, "qmin"));
+        assert!(parse_avpreset("qmin").is_err());
+    }
+
+    #[test]
+    fn preset_lookup() {
+        let dir = TempDir::new("presets");
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("libx264-fast.ffpreset"), "coder=1\n").unwrap();
+        fs::write(second.join("fast.ffpreset"), "coder=0\n").unwrap();
+        fs::write(second.join("libx264-fast.avpreset"), "coder=0\n").unwrap();
+        let bases = [first.clone(), second.clone()];
+
+        // Every name in a base before the next base.
+        assert_eq!(
+            find(&bases, "fast", Some("libx264"), "ffpreset", false),
+            Some(first.join("libx264-fast.ffpreset"))
+        );
+        assert_eq!(
+            find(&bases, "fast", None, "ffpreset", false),
+            Some(second.join("fast.ffpreset"))
+        );
+        assert_eq!(
+            find(&bases, "fast", Some("libx264"), "avpreset", true),
+            Some(second.join("libx264-fast.avpreset"))
+        );
+        assert_eq!(find(&bases, "slow", None, "ffpreset", false), None);
+
+        let path = second.join("fast.ffpreset");
+        let preset = load_ffpreset(path.as_os_str(), true, None).unwrap();
+        assert_eq!(preset.entries[0].key, "coder");
+        let missing = OsStr::new("/nonexistent/fast.ffpreset");
+        assert!(load_ffpreset(missing, true, None).is_err());
+    }
+}
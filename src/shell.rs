@@ -0,0 +1,238 @@
+//! Splitting of a whole ffmpeg command, pasted from a ticket or the docs, into
+//! its arguments with the quoting rules of the POSIX shell.
+//!
+//! Only quoting is done: there is no expansion of variables, substitutions or
+//! globs, and pipes, redirections and lists of commands are rejected rather
+//! than given to ffmpeg as arguments.
+use std::ffi::OsString;
+
+use crate::cmdutils::os_string;
+
+/// Leading words which are dropped, with any directory or `.exe` suffix.
+const PROGRAMS: [&str; 3] = ["ffmpeg", "ffprobe", "ffplay"];
+
+fn is_blank(x: u8) -> bool {
+    matches!(x, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+/// What an unquoted operator character begins.
+fn operator(x: u8) -> Option<&'static str> {
+    match x {
+        b'|' => Some("pipe"),
+        b'&' => Some("background job or `&&` list"),
+        b';' => Some("command list"),
+        b'<' | b'>' => Some("redirection"),
+        b'(' | b')' => Some("subshell"),
+        _ => None,
+    }
+}
+
+/// Whether the `$` at `i` begins an expansion, instead of being a literal
+/// dollar sign like a trailing one.
+fn is_expansion(command: &[u8], i: usize) -> bool {
+    match command.get(i + 1) {
+        Some(&x) => x.is_ascii_alphanumeric() || b"_{(@*#?$!-".contains(&x),
+        None => false,
+    }
+}
+
+/// Length of the line continuation, backslash then newline, at `i`.
+fn continuation(command: &[u8], i: usize) -> Option<usize> {
+    match command.get(i..) {
+        Some([b'\\', b'\n', ..]) => Some(2),
+        Some([b'\\', b'\r', b'\n', ..]) => Some(3),
+        _ => None,
+    }
+}
+
+fn unsupported(what: &str, x: u8, i: usize) -> String {
+    format!(
+        "Unsupported shell construct at offset {}: {} '{}'.",
+        i, what, x as char
+    )
+}
+
+/// The words of `command`, quotes removed.
+pub fn split(command: &[u8]) -> Result<Vec<OsString>, String> {
+    let mut words = vec![];
+    let mut i = 0;
+    loop {
+        while i < command.len() {
+            if is_blank(command[i]) {
+                i += 1;
+            } else if let Some(n) = continuation(command, i) {
+                i += n;
+            } else {
+                break;
+            }
+        }
+        if i == command.len() {
+            break;
+        }
+        // Comments only begin a word.
+        if command[i] == b'#' {
+            while i < command.len() && command[i] != b'\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let mut word = vec![];
+        while i < command.len() && !is_blank(command[i]) {
+            let x = command[i];
+            match x {
+                b'\\' => {
+                    if let Some(n) = continuation(command, i) {
+                        i += n;
+                        continue;
+                    }
+                    match command.get(i + 1) {
+                        Some(&escaped) => word.push(escaped),
+                        None => return Err(format!("Trailing backslash at offset {}.", i)),
+                    }
+                    i += 2;
+                }
+                b'\'' => {
+                    let end = match command[i + 1..].iter().position(|&x| x == b'\'') {
+                        Some(n) => i + 1 + n,
+                        None => return Err(format!("Unterminated single quote at offset {}.", i)),
+                    };
+                    word.extend_from_slice(&command[i + 1..end]);
+                    i = end + 1;
+                }
+                b'"' => {
+                    let start = i;
+                    i += 1;
+                    loop {
+                        let y = match command.get(i) {
+                            Some(&y) => y,
+                            None => {
+                                return Err(format!(
+                                    "Unterminated double quote at offset {}.",
+                                    start
+                                ))
+                            }
+                        };
+                        match y {
+                            b'"' => break,
+                            // Only these keep their backslash away inside
+                            // double quotes.
+                            b'\\' => match command.get(i + 1) {
+                                Some(b'\n') => i += 1,
+                                Some(&z) if b"$`\"\\".contains(&z) => {
+                                    word.push(z);
+                                    i += 1;
+                                }
+                                _ => word.push(y),
+                            },
+                            b'$' if is_expansion(command, i) => {
+                                return Err(unsupported("expansion", y, i))
+                            }
+                            b'`' => return Err(unsupported("command substitution", y, i)),
+                            _ => word.push(y),
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                b'$' if is_expansion(command, i) => return Err(unsupported("expansion", x, i)),
+                b'`' => return Err(unsupported("command substitution", x, i)),
+                _ => {
+                    if let Some(what) = operator(x) {
+                        return Err(unsupported(what, x, i));
+                    }
+                    word.push(x);
+                    i += 1;
+                }
+            }
+        }
+        words.push(word);
+    }
+    Ok(words.into_iter().map(os_string).collect())
+}
+
+/// The arguments of the ffmpeg command, without the leading program name if
+/// there is one.
+pub fn command_args(command: &[u8]) -> Result<Vec<OsString>, String> {
+    let mut words = split(command)?;
+    let program = match words.first() {
+        Some(word) => word.to_string_lossy().into_owned(),
+        None => return Ok(words),
+    };
+    let name = program.rsplit(&['/', '\\'][..]).next().unwrap_or("");
+    if PROGRAMS.contains(&name.strip_suffix(".exe").unwrap_or(name)) {
+        words.remove(0);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn words(command: &str) -> Result<Vec<String>, String> {
+        command_args(command.as_bytes()).map(|words| {
+            words
+                .into_iter()
+                .map(|x| x.into_string().unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            words(r#"ffmpeg -i 'my video.mp4' -vf "drawtext=text='a b':x=\"1\"" out\ 1.mp4"#),
+            Ok(vec![
+                "-i".into(),
+                "my video.mp4".into(),
+                "-vf".into(),
+                r#"drawtext=text='a b':x="1""#.into(),
+                "out 1.mp4".into(),
+            ])
+        );
+        assert_eq!(
+            words("/usr/local/bin/ffmpeg -y \\\n  -i in.mkv \\\r\n  '' out.mp4 # done\n"),
+            Ok(vec![
+                "-y".into(),
+                "-i".into(),
+                "in.mkv".into(),
+                "".into(),
+                "out.mp4".into(),
+            ])
+        );
+        assert_eq!(
+            words(r#"ffprobe.exe "a\b\$c" 'x\y' price$ a#b"#),
+            Ok(vec![
+                r"a\b$c".into(),
+                r"x\y".into(),
+                "price$".into(),
+                "a#b".into(),
+            ])
+        );
+        assert_eq!(
+            words("ffgen -i a"),
+            Ok(vec!["ffgen".into(), "-i".into(), "a".into()])
+        );
+    }
+
+    #[test]
+    fn unsupported_constructs() {
+        let error = |command: &str| words(command).unwrap_err();
+        assert_eq!(
+            error("ffmpeg -i a.mp4 -f wav - | sox"),
+            "Unsupported shell construct at offset 25: pipe '|'."
+        );
+        assert!(error("ffmpeg -i a.mp4 out.mp4 2>&1").contains("redirection"));
+        assert!(error("ffmpeg -i $INPUT out.mp4").contains("expansion"));
+        assert!(error(r#"ffmpeg -i "${DIR}/a.mp4" out.mp4"#).contains("expansion"));
+        assert!(error("ffmpeg -i `ls` out.mp4").contains("command substitution"));
+        assert!(error("ffmpeg -i a.mp4 out.mp4; ls").contains("command list"));
+        assert_eq!(
+            error("ffmpeg -vf 'scale"),
+            "Unterminated single quote at offset 11."
+        );
+        assert!(error(r#"ffmpeg -vf "scale"#).starts_with("Unterminated double quote"));
+        assert!(error("ffmpeg -i a\\").starts_with("Trailing backslash"));
+    }
+}
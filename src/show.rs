@@ -0,0 +1,1933 @@
+//! The `show_*()` listings of cmdutils.c, built from the introspection of the
+//! linked libav* libraries.
+//!
+//! Every listing is printed as text, laid out like FFmpeg does, or as JSON
+//! for the tools which need to discover what the linked build supports. The
+//! help is built from the option table of ffmpeg and from the options of the
+//! classes of the components.
+use libc::{c_char, c_void};
+use log::error;
+use rusty_ffmpeg::{avutil::error::AVERROR, ffi};
+
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+    ptr,
+};
+
+use crate::cmdutils::{OptionDef, OptionFlag};
+
+/// Listings, named after the options printing them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Listing {
+    Formats,
+    Muxers,
+    Demuxers,
+    Devices,
+    Codecs,
+    Decoders,
+    Encoders,
+    Bsfs,
+    Protocols,
+    Filters,
+    PixFmts,
+    Layouts,
+    SampleFmts,
+    Colors,
+    Hwaccels,
+}
+
+impl Listing {
+    pub const ALL: [Listing; 15] = [
+        Listing::Formats,
+        Listing::Muxers,
+        Listing::Demuxers,
+        Listing::Devices,
+        Listing::Codecs,
+        Listing::Decoders,
+        Listing::Encoders,
+        Listing::Bsfs,
+        Listing::Protocols,
+        Listing::Filters,
+        Listing::PixFmts,
+        Listing::Layouts,
+        Listing::SampleFmts,
+        Listing::Colors,
+        Listing::Hwaccels,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Listing::Formats => "formats",
+            Listing::Muxers => "muxers",
+            Listing::Demuxers => "demuxers",
+            Listing::Devices => "devices",
+            Listing::Codecs => "codecs",
+            Listing::Decoders => "decoders",
+            Listing::Encoders => "encoders",
+            Listing::Bsfs => "bsfs",
+            Listing::Protocols => "protocols",
+            Listing::Filters => "filters",
+            Listing::PixFmts => "pix_fmts",
+            Listing::Layouts => "layouts",
+            Listing::SampleFmts => "sample_fmts",
+            Listing::Colors => "colors",
+            Listing::Hwaccels => "hwaccels",
+        }
+    }
+
+    /// The listing as FFmpeg prints it.
+    pub fn text(self) -> String {
+        unsafe {
+            match self {
+                Listing::Formats => formats_text("File formats:", &formats(true, true, false)),
+                Listing::Muxers => formats_text("File formats:", &formats(true, false, false)),
+                Listing::Demuxers => formats_text("File formats:", &formats(false, true, false)),
+                Listing::Devices => formats_text("Devices:", &formats(true, true, true)),
+                Listing::Codecs => codecs_text(&codecs()),
+                Listing::Decoders => coders_text("Decoders", &coders(false)),
+                Listing::Encoders => coders_text("Encoders", &coders(true)),
+                Listing::Bsfs => format!("Bitstream filters:\n{}\n", lines(&bsfs(), "")),
+                Listing::Protocols => format!(
+                    "Supported file protocols:\nInput:\n{}Output:\n{}",
+                    lines(&protocols(false), "  "),
+                    lines(&protocols(true), "  ")
+                ),
+                Listing::Filters => filters_text(&filters()),
+                Listing::PixFmts => pix_fmts_text(&pix_fmts()),
+                Listing::Layouts => layouts_text(&channels(), &layouts()),
+                Listing::SampleFmts => sample_fmts_text(&sample_fmts()),
+                Listing::Colors => colors_text(&colors()),
+                Listing::Hwaccels => format!(
+                    "Hardware acceleration methods:\n{}\n",
+                    lines(&hwaccels(), "")
+                ),
+            }
+        }
+    }
+
+    /// The listing as a JSON value.
+    pub fn json(self) -> String {
+        unsafe {
+            match self {
+                Listing::Formats => json_array(formats(true, true, false).iter().map(Format::json)),
+                Listing::Muxers => json_array(formats(true, false, false).iter().map(Format::json)),
+                Listing::Demuxers => {
+                    json_array(formats(false, true, false).iter().map(Format::json))
+                }
+                Listing::Devices => json_array(formats(true, true, true).iter().map(Format::json)),
+                Listing::Codecs => json_array(codecs().iter().map(Codec::json)),
+                Listing::Decoders => json_array(coders(false).iter().map(Coder::json)),
+                Listing::Encoders => json_array(coders(true).iter().map(Coder::json)),
+                Listing::Bsfs => json_strings(&bsfs()),
+                Listing::Protocols => json_object(&[
+                    ("input", json_strings(&protocols(false))),
+                    ("output", json_strings(&protocols(true))),
+                ]),
+                Listing::Filters => json_array(filters().iter().map(Filter::json)),
+                Listing::PixFmts => json_array(pix_fmts().iter().map(PixFmt::json)),
+                Listing::Layouts => json_object(&[
+                    (
+                        "channels",
+                        json_array(channels().iter().map(|(name, description)| {
+                            json_object(&[
+                                ("name", json_string(name)),
+                                ("description", json_string(description)),
+                            ])
+                        })),
+                    ),
+                    (
+                        "layouts",
+                        json_array(layouts().iter().map(|(name, channels)| {
+                            json_object(&[
+                                ("name", json_string(name)),
+                                ("channels", json_strings(channels)),
+                            ])
+                        })),
+                    ),
+                ]),
+                Listing::SampleFmts => json_array(sample_fmts().iter().map(|(name, depth)| {
+                    json_object(&[("name", json_string(name)), ("depth", depth.to_string())])
+                })),
+                Listing::Colors => json_array(colors().iter().map(|(name, rgb)| {
+                    json_object(&[
+                        ("name", json_string(name)),
+                        ("rgb", json_string(&hex_color(*rgb))),
+                    ])
+                })),
+                Listing::Hwaccels => json_strings(&hwaccels()),
+            }
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// The values are JSON already.
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}: {}", json_string(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// The items are JSON already, one per line.
+fn json_array<I: Iterator<Item = String>>(items: I) -> String {
+    let items: Vec<String> = items.map(|x| format!("  {}", x)).collect();
+    if items.is_empty() {
+        "[]".into()
+    } else {
+        format!("[\n{}\n]", items.join(",\n"))
+    }
+}
+
+fn json_strings(strings: &[String]) -> String {
+    let strings: Vec<String> = strings.iter().map(|x| json_string(x)).collect();
+    format!("[{}]", strings.join(", "))
+}
+
+/// Empty when the string is NULL.
+unsafe fn string(s: *const c_char) -> String {
+    s.as_ref()
+        .map(|_| CStr::from_ptr(s).to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn lines(names: &[String], indent: &str) -> String {
+    names
+        .iter()
+        .map(|name| format!("{}{}\n", indent, name))
+        .collect()
+}
+
+fn flag(set: bool, letter: char) -> char {
+    if set {
+        letter
+    } else {
+        '.'
+    }
+}
+
+/// `get_media_type_char()`
+fn media_type_char(media_type: ffi::AVMediaType) -> char {
+    match media_type {
+        ffi::AVMediaType_AVMEDIA_TYPE_VIDEO => 'V',
+        ffi::AVMediaType_AVMEDIA_TYPE_AUDIO => 'A',
+        ffi::AVMediaType_AVMEDIA_TYPE_DATA => 'D',
+        ffi::AVMediaType_AVMEDIA_TYPE_SUBTITLE => 'S',
+        ffi::AVMediaType_AVMEDIA_TYPE_ATTACHMENT => 'T',
+        _ => '?',
+    }
+}
+
+/// Name of the media type in the JSON output.
+fn media_type_name(media_type: char) -> &'static str {
+    match media_type {
+        'V' => "video",
+        'A' => "audio",
+        'D' => "data",
+        'S' => "subtitle",
+        'T' => "attachment",
+        _ => "unknown",
+    }
+}
+
+fn hex_color(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Format {
+    name: String,
+    long_name: String,
+    demuxing: bool,
+    muxing: bool,
+    device: bool,
+}
+
+impl Format {
+    fn line(&self) -> String {
+        format!(
+            " {}{} {:<15} {}",
+            if self.demuxing { 'D' } else { ' ' },
+            if self.muxing { 'E' } else { ' ' },
+            self.name,
+            self.long_name
+        )
+    }
+
+    fn json(&self) -> String {
+        json_object(&[
+            ("name", json_string(&self.name)),
+            ("long_name", json_string(&self.long_name)),
+            ("demuxing", self.demuxing.to_string()),
+            ("muxing", self.muxing.to_string()),
+            ("device", self.device.to_string()),
+        ])
+    }
+}
+
+unsafe fn is_device(class: *const ffi::AVClass) -> bool {
+    !class.is_null()
+        && (ffi::AVClassCategory_AV_CLASS_CATEGORY_DEVICE_VIDEO_OUTPUT
+            ..=ffi::AVClassCategory_AV_CLASS_CATEGORY_DEVICE_INPUT)
+            .contains(&(*class).category)
+}
+
+/// Sorted by name, a muxer and a demuxer with the same name are one format
+/// with the long name of the muxer.
+unsafe fn formats(muxers: bool, demuxers: bool, device_only: bool) -> Vec<Format> {
+    ffi::avdevice_register_all();
+    let mut formats: BTreeMap<String, Format> = BTreeMap::new();
+    let mut add = |name: *const c_char, long_name: *const c_char, class, muxer: bool| {
+        let device = is_device(class);
+        if device_only && !device {
+            return;
+        }
+        let format = formats.entry(string(name)).or_insert_with(|| Format {
+            name: string(name),
+            long_name: string(long_name),
+            demuxing: false,
+            muxing: false,
+            device,
+        });
+        if muxer {
+            format.muxing = true;
+        } else {
+            format.demuxing = true;
+        }
+    };
+
+    if muxers {
+        let mut opaque = ptr::null_mut();
+        while let Some(x) = ffi::av_muxer_iterate(&mut opaque).as_ref() {
+            add(x.name, x.long_name, x.priv_class, true);
+        }
+    }
+    if demuxers {
+        let mut opaque = ptr::null_mut();
+        while let Some(x) = ffi::av_demuxer_iterate(&mut opaque).as_ref() {
+            add(x.name, x.long_name, x.priv_class, false);
+        }
+    }
+    formats.values().cloned().collect()
+}
+
+fn formats_text(title: &str, formats: &[Format]) -> String {
+    let mut text = format!(
+        "{}\n D. = Demuxing supported\n .E = Muxing supported\n --\n",
+        title
+    );
+    for format in formats.iter() {
+        text.push_str(&format.line());
+        text.push('\n');
+    }
+    text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Codec {
+    name: String,
+    long_name: String,
+    media_type: char,
+    decoding: bool,
+    encoding: bool,
+    intra_only: bool,
+    lossy: bool,
+    lossless: bool,
+    decoders: Vec<String>,
+    encoders: Vec<String>,
+}
+
+impl Codec {
+    fn line(&self) -> String {
+        let mut line = format!(
+            " {}{}{}{}{}{} {:<20} {}",
+            flag(self.decoding, 'D'),
+            flag(self.encoding, 'E'),
+            self.media_type,
+            flag(self.intra_only, 'I'),
+            flag(self.lossy, 'L'),
+            flag(self.lossless, 'S'),
+            self.name,
+            self.long_name
+        );
+        // The decoders and encoders are printed when one of them isn't named
+        // as the codec.
+        for (kind, coders) in [("decoders", &self.decoders), ("encoders", &self.encoders)].iter() {
+            if coders.iter().any(|x| *x != self.name) {
+                line.push_str(&format!(" ({}: {} )", kind, coders.join(" ")));
+            }
+        }
+        line
+    }
+
+    fn json(&self) -> String {
+        json_object(&[
+            ("name", json_string(&self.name)),
+            ("long_name", json_string(&self.long_name)),
+            ("type", json_string(media_type_name(self.media_type))),
+            ("decoding", self.decoding.to_string()),
+            ("encoding", self.encoding.to_string()),
+            ("intra_only", self.intra_only.to_string()),
+            ("lossy", self.lossy.to_string()),
+            ("lossless", self.lossless.to_string()),
+            ("decoders", json_strings(&self.decoders)),
+            ("encoders", json_strings(&self.encoders)),
+        ])
+    }
+}
+
+/// `get_codecs_sorted()`: the codec descriptors sorted by media type and
+/// name, without the deprecated ones.
+unsafe fn codec_descriptors() -> Vec<&'static ffi::AVCodecDescriptor> {
+    let mut descriptors = vec![];
+    let mut desc = ffi::avcodec_descriptor_next(ptr::null());
+    while let Some(x) = desc.as_ref() {
+        if !string(x.name).contains("_deprecated") {
+            descriptors.push(x);
+        }
+        desc = ffi::avcodec_descriptor_next(desc);
+    }
+    descriptors.sort_by(|a, b| {
+        a.type_
+            .cmp(&b.type_)
+            .then_with(|| CStr::from_ptr(a.name).cmp(CStr::from_ptr(b.name)))
+    });
+    descriptors
+}
+
+/// Decoders or encoders of the codec, in registration order.
+unsafe fn codecs_for_id(id: ffi::AVCodecID, encoder: bool) -> Vec<&'static ffi::AVCodec> {
+    let mut codecs = vec![];
+    let mut opaque = ptr::null_mut();
+    while let Some(x) = ffi::av_codec_iterate(&mut opaque).as_ref() {
+        let is_encoder = ffi::av_codec_is_encoder(x) != 0;
+        let is_decoder = ffi::av_codec_is_decoder(x) != 0;
+        if x.id == id && ((encoder && is_encoder) || (!encoder && is_decoder)) {
+            codecs.push(x);
+        }
+    }
+    codecs
+}
+
+unsafe fn codecs() -> Vec<Codec> {
+    codec_descriptors()
+        .into_iter()
+        .map(|desc| {
+            let props = desc.props as u32;
+            let names = |encoder| -> Vec<String> {
+                codecs_for_id(desc.id, encoder)
+                    .iter()
+                    .map(|x| string(x.name))
+                    .collect()
+            };
+            Codec {
+                name: string(desc.name),
+                long_name: string(desc.long_name),
+                media_type: media_type_char(desc.type_),
+                decoding: !ffi::avcodec_find_decoder(desc.id).is_null(),
+                encoding: !ffi::avcodec_find_encoder(desc.id).is_null(),
+                intra_only: props & ffi::AV_CODEC_PROP_INTRA_ONLY != 0,
+                lossy: props & ffi::AV_CODEC_PROP_LOSSY != 0,
+                lossless: props & ffi::AV_CODEC_PROP_LOSSLESS != 0,
+                decoders: names(false),
+                encoders: names(true),
+            }
+        })
+        .collect()
+}
+
+fn codecs_text(codecs: &[Codec]) -> String {
+    let mut text = String::from(
+        "Codecs:\n \
+         D..... = Decoding supported\n \
+         .E.... = Encoding supported\n \
+         ..V... = Video codec\n \
+         ..A... = Audio codec\n \
+         ..S... = Subtitle codec\n \
+         ..D... = Data codec\n \
+         ..T... = Attachment codec\n \
+         ...I.. = Intra frame-only codec\n \
+         ....L. = Lossy compression\n \
+         .....S = Lossless compression\n \
+         -------\n",
+    );
+    for codec in codecs.iter() {
+        text.push_str(&codec.line());
+        text.push('\n');
+    }
+    text
+}
+
+/// A decoder or an encoder.
+#[derive(Debug, Clone, PartialEq)]
+struct Coder {
+    name: String,
+    long_name: String,
+    media_type: char,
+    /// Name of the codec.
+    codec: String,
+    frame_threads: bool,
+    slice_threads: bool,
+    experimental: bool,
+    draw_horiz_band: bool,
+    direct_rendering: bool,
+}
+
+impl Coder {
+    fn line(&self) -> String {
+        let mut line = format!(
+            " {}{}{}{}{}{} {:<20} {}",
+            self.media_type,
+            flag(self.frame_threads, 'F'),
+            flag(self.slice_threads, 'S'),
+            flag(self.experimental, 'X'),
+            flag(self.draw_horiz_band, 'B'),
+            flag(self.direct_rendering, 'D'),
+            self.name,
+            self.long_name
+        );
+        if self.name != self.codec {
+            line.push_str(&format!(" (codec {})", self.codec));
+        }
+        line
+    }
+
+    fn json(&self) -> String {
+        json_object(&[
+            ("name", json_string(&self.name)),
+            ("long_name", json_string(&self.long_name)),
+            ("type", json_string(media_type_name(self.media_type))),
+            ("codec", json_string(&self.codec)),
+            ("frame_threads", self.frame_threads.to_string()),
+            ("slice_threads", self.slice_threads.to_string()),
+            ("experimental", self.experimental.to_string()),
+            ("draw_horiz_band", self.draw_horiz_band.to_string()),
+            ("direct_rendering", self.direct_rendering.to_string()),
+        ])
+    }
+}
+
+unsafe fn coders(encoder: bool) -> Vec<Coder> {
+    let mut coders = vec![];
+    for desc in codec_descriptors() {
+        for codec in codecs_for_id(desc.id, encoder) {
+            let capabilities = codec.capabilities as u32;
+            coders.push(Coder {
+                name: string(codec.name),
+                long_name: string(codec.long_name),
+                media_type: media_type_char(desc.type_),
+                codec: string(desc.name),
+                frame_threads: capabilities & ffi::AV_CODEC_CAP_FRAME_THREADS != 0,
+                slice_threads: capabilities & ffi::AV_CODEC_CAP_SLICE_THREADS != 0,
+                experimental: capabilities & ffi::AV_CODEC_CAP_EXPERIMENTAL != 0,
+                draw_horiz_band: capabilities & ffi::AV_CODEC_CAP_DRAW_HORIZ_BAND != 0,
+                direct_rendering: capabilities & ffi::AV_CODEC_CAP_DR1 != 0,
+            });
+        }
+    }
+    coders
+}
+
+fn coders_text(title: &str, coders: &[Coder]) -> String {
+    let mut text = format!(
+        "{}:\n \
+         V..... = Video\n \
+         A..... = Audio\n \
+         S..... = Subtitle\n \
+         .F.... = Frame-level multithreading\n \
+         ..S... = Slice-level multithreading\n \
+         ...X.. = Codec is experimental\n \
+         ....B. = Supports draw_horiz_band\n \
+         .....D = Supports direct rendering method 1\n \
+         ------\n",
+        title
+    );
+    for coder in coders.iter() {
+        text.push_str(&coder.line());
+        text.push('\n');
+    }
+    text
+}
+
+unsafe fn bsfs() -> Vec<String> {
+    let mut bsfs = vec![];
+    let mut opaque = ptr::null_mut();
+    while let Some(x) = ffi::av_bsf_iterate(&mut opaque).as_ref() {
+        bsfs.push(string(x.name));
+    }
+    bsfs
+}
+
+unsafe fn protocols(output: bool) -> Vec<String> {
+    let mut protocols = vec![];
+    let mut opaque = ptr::null_mut();
+    loop {
+        let name = ffi::avio_enum_protocols(&mut opaque, output as _);
+        if name.is_null() {
+            break;
+        }
+        protocols.push(string(name));
+    }
+    protocols
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Filter {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    timeline: bool,
+    slice_threads: bool,
+    commands: bool,
+    /// Media types of the pads.
+    inputs: Vec<char>,
+    outputs: Vec<char>,
+    dynamic_inputs: bool,
+    dynamic_outputs: bool,
+}
+
+impl Filter {
+    fn line(&self) -> String {
+        let pads = |pads: &[char], dynamic: bool| -> String {
+            match (pads.is_empty(), dynamic) {
+                (false, _) => pads.iter().collect(),
+                (true, true) => "N".into(),
+                (true, false) => "|".into(),
+            }
+        };
+        let io = format!(
+            "{}->{}",
+            pads(&self.inputs, self.dynamic_inputs),
+            pads(&self.outputs, self.dynamic_outputs)
+        );
+        format!(
+            " {}{}{} {:<17} {:<10} {}",
+            flag(self.timeline, 'T'),
+            flag(self.slice_threads, 'S'),
+            flag(self.commands, 'C'),
+            self.name,
+            io,
+            self.description
+        )
+    }
+
+    fn json(&self) -> String {
+        let types = |pads: &[char]| -> String {
+            let types: Vec<String> = pads
+                .iter()
+                .map(|&x| json_string(media_type_name(x)))
+                .collect();
+            format!("[{}]", types.join(", "))
+        };
+        json_object(&[
+            ("name", json_string(&self.name)),
+            ("description", json_string(&self.description)),
+            ("timeline", self.timeline.to_string()),
+            ("slice_threads", self.slice_threads.to_string()),
+            ("commands", self.commands.to_string()),
+            ("inputs", types(&self.inputs)),
+            ("outputs", types(&self.outputs)),
+            ("dynamic_inputs", self.dynamic_inputs.to_string()),
+            ("dynamic_outputs", self.dynamic_outputs.to_string()),
+        ])
+    }
+}
+
+pub(crate) unsafe fn filters() -> Vec<Filter> {
+    let pad_types = |pads: *const ffi::AVFilterPad| -> Vec<char> {
+        if pads.is_null() {
+            return vec![];
+        }
+        (0..ffi::avfilter_pad_count(pads))
+            .map(|i| media_type_char(ffi::avfilter_pad_get_type(pads, i)))
+            .collect()
+    };
+
+    let mut filters = vec![];
+    let mut opaque = ptr::null_mut();
+    while let Some(x) = ffi::av_filter_iterate(&mut opaque).as_ref() {
+        let flags = x.flags as u32;
+        filters.push(Filter {
+            name: string(x.name),
+            description: string(x.description),
+            timeline: flags & ffi::AVFILTER_FLAG_SUPPORT_TIMELINE != 0,
+            slice_threads: flags & ffi::AVFILTER_FLAG_SLICE_THREADS != 0,
+            commands: x.process_command.is_some(),
+            inputs: pad_types(x.inputs),
+            outputs: pad_types(x.outputs),
+            dynamic_inputs: flags & ffi::AVFILTER_FLAG_DYNAMIC_INPUTS != 0,
+            dynamic_outputs: flags & ffi::AVFILTER_FLAG_DYNAMIC_OUTPUTS != 0,
+        });
+    }
+    filters
+}
+
+fn filters_text(filters: &[Filter]) -> String {
+    let mut text = String::from(
+        "Filters:\n  \
+         T.. = Timeline support\n  \
+         .S. = Slice threading\n  \
+         ..C = Command support\n  \
+         A = Audio input/output\n  \
+         V = Video input/output\n  \
+         N = Dynamic number and/or type of input/output\n  \
+         | = Source or sink filter\n",
+    );
+    for filter in filters.iter() {
+        text.push_str(&filter.line());
+        text.push('\n');
+    }
+    text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PixFmt {
+    name: String,
+    input: bool,
+    output: bool,
+    hwaccel: bool,
+    paletted: bool,
+    bitstream: bool,
+    nb_components: u8,
+    bits_per_pixel: i32,
+}
+
+impl PixFmt {
+    fn line(&self) -> String {
+        format!(
+            "{}{}{}{}{} {:<16}       {}            {:>2}",
+            flag(self.input, 'I'),
+            flag(self.output, 'O'),
+            flag(self.hwaccel, 'H'),
+            flag(self.paletted, 'P'),
+            flag(self.bitstream, 'B'),
+            self.name,
+            self.nb_components,
+            self.bits_per_pixel
+        )
+    }
+
+    fn json(&self) -> String {
+        json_object(&[
+            ("name", json_string(&self.name)),
+            ("input", self.input.to_string()),
+            ("output", self.output.to_string()),
+            ("hwaccel", self.hwaccel.to_string()),
+            ("paletted", self.paletted.to_string()),
+            ("bitstream", self.bitstream.to_string()),
+            ("nb_components", self.nb_components.to_string()),
+            ("bits_per_pixel", self.bits_per_pixel.to_string()),
+        ])
+    }
+}
+
+unsafe fn pix_fmts() -> Vec<PixFmt> {
+    let mut pix_fmts = vec![];
+    let mut desc = ffi::av_pix_fmt_desc_next(ptr::null());
+    while let Some(x) = desc.as_ref() {
+        let pix_fmt = ffi::av_pix_fmt_desc_get_id(desc);
+        let flags = x.flags;
+        pix_fmts.push(PixFmt {
+            name: string(x.name),
+            input: ffi::sws_isSupportedInput(pix_fmt) != 0,
+            output: ffi::sws_isSupportedOutput(pix_fmt) != 0,
+            hwaccel: flags & ffi::AV_PIX_FMT_FLAG_HWACCEL as u64 != 0,
+            paletted: flags & ffi::AV_PIX_FMT_FLAG_PAL as u64 != 0,
+            bitstream: flags & ffi::AV_PIX_FMT_FLAG_BITSTREAM as u64 != 0,
+            nb_components: x.nb_components,
+            bits_per_pixel: ffi::av_get_bits_per_pixel(desc),
+        });
+        desc = ffi::av_pix_fmt_desc_next(desc);
+    }
+    pix_fmts
+}
+
+fn pix_fmts_text(pix_fmts: &[PixFmt]) -> String {
+    let mut text = String::from(
+        "Pixel formats:\n\
+         I.... = Supported Input  format for conversion\n\
+         .O... = Supported Output format for conversion\n\
+         ..H.. = Hardware accelerated format\n\
+         ...P. = Paletted format\n\
+         ....B = Bitstream format\n\
+         FLAGS NAME            NB_COMPONENTS BITS_PER_PIXEL\n\
+         -----\n",
+    );
+    for pix_fmt in pix_fmts.iter() {
+        text.push_str(&pix_fmt.line());
+        text.push('\n');
+    }
+    text
+}
+
+/// `(name, description)` of the individual channels.
+unsafe fn channels() -> Vec<(String, String)> {
+    (0..63)
+        .filter_map(|i| {
+            let channel = 1u64 << i;
+            let name = ffi::av_get_channel_name(channel);
+            (!name.is_null()).then(|| {
+                (
+                    string(name),
+                    string(ffi::av_get_channel_description(channel)),
+                )
+            })
+        })
+        .collect()
+}
+
+/// `(name, channels)` of the standard channel layouts.
+unsafe fn layouts() -> Vec<(String, Vec<String>)> {
+    let mut layouts = vec![];
+    let mut layout = 0;
+    let mut name = ptr::null();
+    for i in 0.. {
+        if ffi::av_get_standard_channel_layout(i, &mut layout, &mut name) != 0 {
+            break;
+        }
+        if name.is_null() {
+            continue;
+        }
+        let channels = (0..64)
+            .map(|bit| 1u64 << bit)
+            .filter(|channel| layout & channel != 0)
+            .map(|channel| string(ffi::av_get_channel_name(channel)))
+            .collect();
+        layouts.push((string(name), channels));
+    }
+    layouts
+}
+
+fn layouts_text(channels: &[(String, String)], layouts: &[(String, Vec<String>)]) -> String {
+    let mut text = String::from("Individual channels:\nNAME           DESCRIPTION\n");
+    for (name, description) in channels.iter() {
+        text.push_str(&format!("{:<14} {}\n", name, description));
+    }
+    text.push_str("\nStandard channel layouts:\nNAME           DECOMPOSITION\n");
+    for (name, channels) in layouts.iter() {
+        text.push_str(&format!("{:<14} {}\n", name, channels.join("+")));
+    }
+    text
+}
+
+/// `(name, depth)` of the sample formats.
+unsafe fn sample_fmts() -> Vec<(String, i32)> {
+    (0..ffi::AVSampleFormat_AV_SAMPLE_FMT_NB)
+        .map(|fmt| {
+            (
+                string(ffi::av_get_sample_fmt_name(fmt)),
+                ffi::av_get_bytes_per_sample(fmt) * 8,
+            )
+        })
+        .collect()
+}
+
+fn sample_fmts_text(sample_fmts: &[(String, i32)]) -> String {
+    let mut text = String::from("name   depth\n");
+    for (name, depth) in sample_fmts.iter() {
+        text.push_str(&format!("{:<9} {:>2} \n", name, depth));
+    }
+    text
+}
+
+unsafe fn colors() -> Vec<(String, [u8; 3])> {
+    let mut colors = vec![];
+    let mut rgb: *const u8 = ptr::null();
+    for i in 0.. {
+        let name = ffi::av_get_known_color_name(i, &mut rgb);
+        if name.is_null() {
+            break;
+        }
+        colors.push((string(name), [*rgb, *rgb.add(1), *rgb.add(2)]));
+    }
+    colors
+}
+
+fn colors_text(colors: &[(String, [u8; 3])]) -> String {
+    let mut text = format!("{:<32} #RRGGBB\n", "name");
+    for (name, rgb) in colors.iter() {
+        text.push_str(&format!("{:<32} {}\n", name, hex_color(*rgb)));
+    }
+    text
+}
+
+unsafe fn hwaccels() -> Vec<String> {
+    let mut hwaccels = vec![];
+    let mut device_type = ffi::AVHWDeviceType_AV_HWDEVICE_TYPE_NONE;
+    loop {
+        device_type = ffi::av_hwdevice_iterate_types(device_type);
+        if device_type == ffi::AVHWDeviceType_AV_HWDEVICE_TYPE_NONE {
+            break;
+        }
+        hwaccels.push(string(ffi::av_hwdevice_get_type_name(device_type)));
+    }
+    hwaccels
+}
+
+/// `show_version()`: the version of ffgen and of the linked libraries.
+pub fn version() -> String {
+    let libraries = unsafe {
+        [
+            ("avutil", ffi::avutil_version()),
+            ("avcodec", ffi::avcodec_version()),
+            ("avformat", ffi::avformat_version()),
+            ("avdevice", ffi::avdevice_version()),
+            ("avfilter", ffi::avfilter_version()),
+            ("swscale", ffi::swscale_version()),
+            ("swresample", ffi::swresample_version()),
+        ]
+    };
+    let mut text = format!("ffgen version {}\n", env!("CARGO_PKG_VERSION"));
+    for (name, version) in libraries.iter() {
+        text.push_str(&format!(
+            "lib{:<11} {:>2}.{:>3}.{:>3}\n",
+            name,
+            version >> 16,
+            (version >> 8) & 0xff,
+            version & 0xff
+        ));
+    }
+    text
+}
+
+/// `show_buildconf()`: the configuration of the linked libavutil, one flag
+/// per line.
+pub fn buildconf() -> String {
+    let configuration = unsafe { string(ffi::avutil_configuration()) };
+    let mut text = String::from("\n  configuration:\n");
+    for flag in configuration.split(" --") {
+        let flag = flag.trim();
+        if !flag.is_empty() {
+            let dashes = if flag.starts_with("--") { "" } else { "--" };
+            text.push_str(&format!("    {}{}\n", dashes, flag));
+        }
+    }
+    text
+}
+
+/// `show_sources()` and `show_sinks()`: the sources of the input devices or
+/// the sinks of the output devices. `arg` is `device[,opt1=val1[,...]]`, or
+/// empty for every device.
+pub fn device_list(arg: &str, sinks: bool) -> Result<String, i32> {
+    let (device, options) = match arg.find(',') {
+        Some(i) => (&arg[..i], &arg[i + 1..]),
+        None => (arg, ""),
+    };
+    let device = CString::new(device).map_err(|_| AVERROR(libc::EINVAL))?;
+    let options = CString::new(options).map_err(|_| AVERROR(libc::EINVAL))?;
+
+    let mut text = String::new();
+    unsafe {
+        ffi::avdevice_register_all();
+        let mut opts = ptr::null_mut();
+        if ffi::av_dict_parse_string(
+            &mut opts,
+            options.as_ptr(),
+            b"=\0".as_ptr() as _,
+            b":\0".as_ptr() as _,
+            0,
+        ) < 0
+        {
+            error!("Invalid device options '{}'.", options.to_string_lossy());
+            ffi::av_dict_free(&mut opts);
+            return Err(AVERROR(libc::EINVAL));
+        }
+
+        // Input and output devices, audio ones first.
+        let mut devices: Vec<(*mut c_void, *const c_char)> = vec![];
+        if sinks {
+            let mut fmt = ffi::av_output_audio_device_next(ptr::null_mut());
+            while let Some(x) = fmt.as_ref() {
+                devices.push((fmt as _, x.name));
+                fmt = ffi::av_output_audio_device_next(fmt);
+            }
+            let mut fmt = ffi::av_output_video_device_next(ptr::null_mut());
+            while let Some(x) = fmt.as_ref() {
+                devices.push((fmt as _, x.name));
+                fmt = ffi::av_output_video_device_next(fmt);
+            }
+        } else {
+            let mut fmt = ffi::av_input_audio_device_next(ptr::null_mut());
+            while let Some(x) = fmt.as_ref() {
+                devices.push((fmt as _, x.name));
+                fmt = ffi::av_input_audio_device_next(fmt);
+            }
+            let mut fmt = ffi::av_input_video_device_next(ptr::null_mut());
+            while let Some(x) = fmt.as_ref() {
+                devices.push((fmt as _, x.name));
+                fmt = ffi::av_input_video_device_next(fmt);
+            }
+        }
+
+        for (fmt, name) in devices {
+            if !device.as_bytes().is_empty() && ffi::av_match_name(device.as_ptr(), name) == 0 {
+                continue;
+            }
+            let mut list = ptr::null_mut();
+            let ret = if sinks {
+                text.push_str(&format!("Auto-detected sinks for {}:\n", string(name)));
+                ffi::avdevice_list_output_sinks(fmt as _, ptr::null(), opts, &mut list)
+            } else {
+                text.push_str(&format!("Auto-detected sources for {}:\n", string(name)));
+                ffi::avdevice_list_input_sources(fmt as _, ptr::null(), opts, &mut list)
+            };
+            if ret < 0 {
+                text.push_str(&format!(
+                    "Cannot list {}.\n",
+                    if sinks { "sinks" } else { "sources" }
+                ));
+            } else if let Some(list) = list.as_ref() {
+                for i in 0..list.nb_devices {
+                    let info = &**list.devices.offset(i as isize);
+                    text.push_str(&format!(
+                        "{} {} [{}]\n",
+                        if list.default_device == i { "*" } else { " " },
+                        string(info.device_name),
+                        string(info.device_description)
+                    ));
+                }
+            }
+            ffi::avdevice_free_list_devices(&mut list);
+        }
+        ffi::av_dict_free(&mut opts);
+    }
+    Ok(text)
+}
+
+/// `show_help_options()`: the options having all of `req_flags`, none of
+/// `rej_flags` and one of `alt_flags` when it isn't empty.
+fn help_options(
+    options: &[OptionDef],
+    msg: &str,
+    req_flags: OptionFlag,
+    rej_flags: OptionFlag,
+    alt_flags: OptionFlag,
+) -> String {
+    let mut text = String::new();
+    for po in options.iter() {
+        if !po.flags.contains(req_flags)
+            || (!alt_flags.is_empty() && !po.flags.intersects(alt_flags))
+            || po.flags.intersects(rej_flags)
+        {
+            continue;
+        }
+        if text.is_empty() {
+            text.push_str(msg);
+            text.push('\n');
+        }
+        let name = match po.argname {
+            Some(argname) => format!("{} {}", po.name, argname),
+            None => po.name.to_owned(),
+        };
+        text.push_str(&format!("-{:<17}  {}\n", name, po.help));
+    }
+    text.push('\n');
+    text
+}
+
+/// `show_help_default()`: the options of ffmpeg, more of them with `long`,
+/// and the options of every component with `full`.
+fn default_help(options: &[OptionDef], topic: &str) -> String {
+    // Per-file options have at least one of those set.
+    let per_file = OptionFlag::OPT_SPEC | OptionFlag::OPT_OFFSET | OptionFlag::OPT_PERFILE;
+    let (show_advanced, show_avoptions) = match topic {
+        "" => (false, false),
+        "long" => (true, false),
+        "full" => (true, true),
+        _ => {
+            error!("Unknown help option '{}'.", topic);
+            (false, false)
+        }
+    };
+
+    let mut text = String::from(
+        "Hyper fast Audio and Video encoder\n\
+         usage: ffgen [options] [[infile options] -i infile]... {[outfile options] outfile}...\n\
+         \n\
+         Getting help:\n    \
+         -h      -- print basic options\n    \
+         -h long -- print more options\n    \
+         -h full -- print all options (including all format and codec specific options, very long)\n    \
+         -h type=name -- print all options for the named decoder/encoder/demuxer/muxer/filter/bsf\n    \
+         See man ffmpeg for detailed description of the options.\n\
+         \n",
+    );
+    let none = OptionFlag::NONE;
+    let exit = OptionFlag::OPT_EXIT;
+    let expert = OptionFlag::OPT_EXPERT;
+    let (video, audio, subtitle) = (
+        OptionFlag::OPT_VIDEO,
+        OptionFlag::OPT_AUDIO,
+        OptionFlag::OPT_SUBTITLE,
+    );
+    let mut section = |show: bool, msg: &str, req_flags, rej_flags, alt_flags| {
+        if show {
+            text.push_str(&help_options(options, msg, req_flags, rej_flags, alt_flags));
+        }
+    };
+    section(
+        true,
+        "Print help / information / capabilities:",
+        exit,
+        none,
+        none,
+    );
+    section(
+        true,
+        "Global options (affect whole program instead of just one file:",
+        none,
+        per_file | exit | expert,
+        none,
+    );
+    section(
+        show_advanced,
+        "Advanced global options:",
+        expert,
+        per_file | exit,
+        none,
+    );
+    section(
+        true,
+        "Per-file main options:",
+        none,
+        expert | audio | video | subtitle | exit,
+        per_file,
+    );
+    section(
+        show_advanced,
+        "Advanced per-file options:",
+        expert,
+        audio | video | subtitle,
+        per_file,
+    );
+    section(true, "Video options:", video, expert | audio, none);
+    section(
+        show_advanced,
+        "Advanced Video options:",
+        expert | video,
+        audio,
+        none,
+    );
+    section(true, "Audio options:", audio, expert | video, none);
+    section(
+        show_advanced,
+        "Advanced Audio options:",
+        expert | audio,
+        video,
+        none,
+    );
+    section(true, "Subtitle options:", subtitle, none, none);
+    text.push('\n');
+
+    if show_avoptions {
+        let encoding_decoding =
+            (ffi::AV_OPT_FLAG_ENCODING_PARAM | ffi::AV_OPT_FLAG_DECODING_PARAM) as i32;
+        let filtering = (ffi::AV_OPT_FLAG_VIDEO_PARAM
+            | ffi::AV_OPT_FLAG_AUDIO_PARAM
+            | ffi::AV_OPT_FLAG_FILTERING_PARAM) as i32;
+        unsafe {
+            text.push_str(&class_help(ffi::avcodec_get_class(), encoding_decoding));
+            text.push_str(&class_help(ffi::avformat_get_class(), encoding_decoding));
+            text.push_str(&class_help(ffi::sws_get_class(), encoding_decoding));
+            text.push_str(&class_help(
+                ffi::swr_get_class(),
+                ffi::AV_OPT_FLAG_AUDIO_PARAM as i32,
+            ));
+            text.push_str(&class_help(ffi::avfilter_get_class(), filtering));
+        }
+    }
+    text
+}
+
+/// `log_value()` of libavutil's opt.c, the limits are named.
+fn option_value(d: f64) -> String {
+    let limits = [
+        (i32::MAX as f64, "INT_MAX"),
+        (i32::MIN as f64, "INT_MIN"),
+        (u32::MAX as f64, "UINT32_MAX"),
+        (i64::MAX as f64, "I64_MAX"),
+        (i64::MIN as f64, "I64_MIN"),
+        (f32::MAX as f64, "FLT_MAX"),
+        (f32::MIN_POSITIVE as f64, "FLT_MIN"),
+        (-f32::MAX as f64, "-FLT_MAX"),
+        (-f32::MIN_POSITIVE as f64, "-FLT_MIN"),
+        (f64::MAX, "DBL_MAX"),
+        (f64::MIN_POSITIVE, "DBL_MIN"),
+        (-f64::MAX, "-DBL_MAX"),
+        (-f64::MIN_POSITIVE, "-DBL_MIN"),
+    ];
+    match limits.iter().find(|(limit, _)| *limit == d) {
+        Some((_, name)) => (*name).to_owned(),
+        None => {
+            // Printed with `%g` as libavutil does.
+            let mut buffer = [0 as c_char; 64];
+            unsafe {
+                libc::snprintf(buffer.as_mut_ptr(), buffer.len(), b"%g\0".as_ptr() as _, d);
+                string(buffer.as_ptr())
+            }
+        }
+    }
+}
+
+/// `log_int_value()` of libavutil's opt.c.
+fn option_int_value(i: i64) -> String {
+    match i {
+        x if x == i32::MAX as i64 => "INT_MAX".into(),
+        x if x == i32::MIN as i64 => "INT_MIN".into(),
+        x if x == u32::MAX as i64 => "UINT32_MAX".into(),
+        i64::MAX => "I64_MAX".into(),
+        i64::MIN => "I64_MIN".into(),
+        x => x.to_string(),
+    }
+}
+
+/// Name of the constant of the unit having the value.
+unsafe fn const_name(obj: *mut c_void, unit: *const c_char, value: i64) -> Option<String> {
+    if unit.is_null() {
+        return None;
+    }
+    let mut opt = ffi::av_opt_next(obj, ptr::null());
+    while let Some(x) = opt.as_ref() {
+        if x.type_ == ffi::AVOptionType_AV_OPT_TYPE_CONST
+            && !x.unit.is_null()
+            && CStr::from_ptr(x.unit) == CStr::from_ptr(unit)
+            && x.default_val.i64 == value
+        {
+            return Some(string(x.name));
+        }
+        opt = ffi::av_opt_next(obj, opt);
+    }
+    None
+}
+
+/// `opt_list()` of libavutil's opt.c: the options of the object, or the
+/// constants of `unit` when it isn't NULL.
+unsafe fn option_list(
+    obj: *mut c_void,
+    unit: *const c_char,
+    req_flags: i32,
+    rej_flags: i32,
+    parent_type: Option<ffi::AVOptionType>,
+) -> String {
+    let mut text = String::new();
+    let mut opt = ffi::av_opt_next(obj, ptr::null());
+    while let Some(x) = opt.as_ref() {
+        opt = ffi::av_opt_next(obj, opt);
+        if x.flags & req_flags == 0 || x.flags & rej_flags != 0 {
+            continue;
+        }
+        // No constants on level one, only the constants of the unit on level
+        // two.
+        let is_const = x.type_ == ffi::AVOptionType_AV_OPT_TYPE_CONST;
+        if unit.is_null() {
+            if is_const {
+                continue;
+            }
+            text.push_str(&format!(
+                "  {}{:<17} ",
+                if x.flags & ffi::AV_OPT_FLAG_FILTERING_PARAM as i32 != 0 {
+                    ""
+                } else {
+                    "-"
+                },
+                string(x.name)
+            ));
+        } else {
+            if !is_const || x.unit.is_null() || CStr::from_ptr(unit) != CStr::from_ptr(x.unit) {
+                continue;
+            }
+            text.push_str(&format!("     {:<15} ", string(x.name)));
+        }
+
+        let type_name = match x.type_ {
+            ffi::AVOptionType_AV_OPT_TYPE_FLAGS => "<flags>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_INT => "<int>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_INT64 => "<int64>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_UINT64 => "<uint64>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_DOUBLE => "<double>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_FLOAT => "<float>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_STRING => "<string>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_RATIONAL => "<rational>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_BINARY => "<binary>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_DICT => "<dictionary>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_IMAGE_SIZE => "<image_size>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_VIDEO_RATE => "<video_rate>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_PIXEL_FMT => "<pix_fmt>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_SAMPLE_FMT => "<sample_fmt>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_DURATION => "<duration>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_COLOR => "<color>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_CHANNEL_LAYOUT => "<channel_layout>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_BOOL => "<boolean>".into(),
+            ffi::AVOptionType_AV_OPT_TYPE_CONST
+                if parent_type == Some(ffi::AVOptionType_AV_OPT_TYPE_INT) =>
+            {
+                x.default_val.i64.to_string()
+            }
+            _ => String::new(),
+        };
+        text.push_str(&format!("{:<12} ", type_name));
+
+        let flags = [
+            (ffi::AV_OPT_FLAG_ENCODING_PARAM, 'E'),
+            (ffi::AV_OPT_FLAG_DECODING_PARAM, 'D'),
+            (ffi::AV_OPT_FLAG_FILTERING_PARAM, 'F'),
+            (ffi::AV_OPT_FLAG_VIDEO_PARAM, 'V'),
+            (ffi::AV_OPT_FLAG_AUDIO_PARAM, 'A'),
+            (ffi::AV_OPT_FLAG_SUBTITLE_PARAM, 'S'),
+            (ffi::AV_OPT_FLAG_EXPORT, 'X'),
+            (ffi::AV_OPT_FLAG_READONLY, 'R'),
+            (ffi::AV_OPT_FLAG_BSF_PARAM, 'B'),
+            (ffi::AV_OPT_FLAG_RUNTIME_PARAM, 'T'),
+            (ffi::AV_OPT_FLAG_DEPRECATED, 'P'),
+        ];
+        for &(mask, letter) in flags.iter() {
+            text.push(flag(x.flags & mask as i32 != 0, letter));
+        }
+        if !x.help.is_null() {
+            text.push_str(&format!(" {}", string(x.help)));
+        }
+
+        let ranged = [
+            ffi::AVOptionType_AV_OPT_TYPE_INT,
+            ffi::AVOptionType_AV_OPT_TYPE_INT64,
+            ffi::AVOptionType_AV_OPT_TYPE_UINT64,
+            ffi::AVOptionType_AV_OPT_TYPE_DOUBLE,
+            ffi::AVOptionType_AV_OPT_TYPE_FLOAT,
+            ffi::AVOptionType_AV_OPT_TYPE_RATIONAL,
+        ];
+        if ranged.contains(&x.type_) {
+            text.push_str(&format!(
+                " (from {} to {})",
+                option_value(x.min),
+                option_value(x.max)
+            ));
+        }
+
+        let string_default = [
+            ffi::AVOptionType_AV_OPT_TYPE_COLOR,
+            ffi::AVOptionType_AV_OPT_TYPE_IMAGE_SIZE,
+            ffi::AVOptionType_AV_OPT_TYPE_STRING,
+            ffi::AVOptionType_AV_OPT_TYPE_DICT,
+            ffi::AVOptionType_AV_OPT_TYPE_VIDEO_RATE,
+        ];
+        let default = match x.type_ {
+            ffi::AVOptionType_AV_OPT_TYPE_CONST | ffi::AVOptionType_AV_OPT_TYPE_BINARY => None,
+            t if string_default.contains(&t) => x
+                .default_val
+                .str
+                .as_ref()
+                .map(|_| format!("\"{}\"", string(x.default_val.str))),
+            ffi::AVOptionType_AV_OPT_TYPE_BOOL => Some(
+                match x.default_val.i64 {
+                    -1 => "auto",
+                    0 => "false",
+                    1 => "true",
+                    _ => "invalid",
+                }
+                .into(),
+            ),
+            ffi::AVOptionType_AV_OPT_TYPE_FLAGS => Some(format!("{:X}", x.default_val.i64)),
+            ffi::AVOptionType_AV_OPT_TYPE_INT
+            | ffi::AVOptionType_AV_OPT_TYPE_INT64
+            | ffi::AVOptionType_AV_OPT_TYPE_UINT64
+            | ffi::AVOptionType_AV_OPT_TYPE_DURATION => Some(
+                const_name(obj, x.unit, x.default_val.i64)
+                    .unwrap_or_else(|| option_int_value(x.default_val.i64)),
+            ),
+            ffi::AVOptionType_AV_OPT_TYPE_DOUBLE | ffi::AVOptionType_AV_OPT_TYPE_FLOAT => {
+                Some(option_value(x.default_val.dbl))
+            }
+            ffi::AVOptionType_AV_OPT_TYPE_RATIONAL => {
+                Some(format!("{}/{}", x.default_val.q.num, x.default_val.q.den))
+            }
+            ffi::AVOptionType_AV_OPT_TYPE_PIXEL_FMT => Some(
+                ffi::av_get_pix_fmt_name(x.default_val.i64 as _)
+                    .as_ref()
+                    .map_or_else(|| "none".into(), |name| string(name)),
+            ),
+            ffi::AVOptionType_AV_OPT_TYPE_SAMPLE_FMT => Some(
+                ffi::av_get_sample_fmt_name(x.default_val.i64 as _)
+                    .as_ref()
+                    .map_or_else(|| "none".into(), |name| string(name)),
+            ),
+            ffi::AVOptionType_AV_OPT_TYPE_CHANNEL_LAYOUT => {
+                Some(format!("0x{:x}", x.default_val.i64))
+            }
+            _ => None,
+        };
+        if let Some(default) = default {
+            text.push_str(&format!(" (default {})", default));
+        }
+        text.push('\n');
+
+        if !x.unit.is_null() && !is_const {
+            text.push_str(&option_list(
+                obj,
+                x.unit,
+                req_flags,
+                rej_flags,
+                Some(x.type_),
+            ));
+        }
+    }
+    text
+}
+
+/// `show_help_children()`: the options of the class with `flags`, then the
+/// ones of its child classes.
+unsafe fn class_help(class: *const ffi::AVClass, flags: i32) -> String {
+    let mut text = String::new();
+    let class_ref = match class.as_ref() {
+        Some(x) => x,
+        None => return text,
+    };
+    if !class_ref.option.is_null() {
+        // `av_opt_show2()`, on a fake object beginning with the class.
+        let obj = &class as *const *const ffi::AVClass as *mut c_void;
+        text.push_str(&format!("{} AVOptions:\n", string(class_ref.class_name)));
+        text.push_str(&option_list(obj, ptr::null(), flags, 0, None));
+        text.push('\n');
+    }
+    let mut child = ffi::av_opt_child_class_next(class, ptr::null());
+    while !child.is_null() {
+        text.push_str(&class_help(child, flags));
+        child = ffi::av_opt_child_class_next(class, child);
+    }
+    text
+}
+
+/// `PRINT_CODEC_SUPPORTED()`: the line of the values of a list ended by
+/// `end`, nothing when there is no list.
+unsafe fn supported<T: PartialEq + Copy>(
+    what: &str,
+    list: *const T,
+    end: T,
+    name: impl Fn(T) -> String,
+) -> String {
+    if list.is_null() {
+        return String::new();
+    }
+    let mut text = format!("    Supported {}:", what);
+    let mut p = list;
+    while *p != end {
+        text.push_str(&format!(" {}", name(*p)));
+        p = p.add(1);
+    }
+    text.push('\n');
+    text
+}
+
+/// `print_codec()`
+unsafe fn codec_help(c: &ffi::AVCodec) -> String {
+    let encoder = ffi::av_codec_is_encoder(c) != 0;
+    let mut text = format!(
+        "{} {} [{}]:\n",
+        if encoder { "Encoder" } else { "Decoder" },
+        string(c.name),
+        string(c.long_name)
+    );
+
+    let capabilities = c.capabilities as u32;
+    let threads = ffi::AV_CODEC_CAP_FRAME_THREADS
+        | ffi::AV_CODEC_CAP_SLICE_THREADS
+        | ffi::AV_CODEC_CAP_AUTO_THREADS;
+    let names = [
+        (ffi::AV_CODEC_CAP_DRAW_HORIZ_BAND, "horizband"),
+        (ffi::AV_CODEC_CAP_DR1, "dr1"),
+        (ffi::AV_CODEC_CAP_TRUNCATED, "trunc"),
+        (ffi::AV_CODEC_CAP_DELAY, "delay"),
+        (ffi::AV_CODEC_CAP_SMALL_LAST_FRAME, "small"),
+        (ffi::AV_CODEC_CAP_SUBFRAMES, "subframes"),
+        (ffi::AV_CODEC_CAP_EXPERIMENTAL, "exp"),
+        (ffi::AV_CODEC_CAP_CHANNEL_CONF, "chconf"),
+        (ffi::AV_CODEC_CAP_PARAM_CHANGE, "paramchange"),
+        (ffi::AV_CODEC_CAP_VARIABLE_FRAME_SIZE, "variable"),
+        (threads, "threads"),
+        (ffi::AV_CODEC_CAP_AVOID_PROBING, "avoidprobe"),
+        (ffi::AV_CODEC_CAP_INTRA_ONLY, "intraonly"),
+        (ffi::AV_CODEC_CAP_LOSSLESS, "lossless"),
+        (ffi::AV_CODEC_CAP_HARDWARE, "hardware"),
+        (ffi::AV_CODEC_CAP_HYBRID, "hybrid"),
+    ];
+    text.push_str("    General capabilities: ");
+    for &(mask, name) in names.iter() {
+        if capabilities & mask != 0 {
+            text.push_str(name);
+            text.push(' ');
+        }
+    }
+    if capabilities == 0 {
+        text.push_str("none");
+    }
+    text.push('\n');
+
+    if c.type_ == ffi::AVMediaType_AVMEDIA_TYPE_VIDEO
+        || c.type_ == ffi::AVMediaType_AVMEDIA_TYPE_AUDIO
+    {
+        let frame = ffi::AV_CODEC_CAP_FRAME_THREADS;
+        let slice = ffi::AV_CODEC_CAP_SLICE_THREADS;
+        let threading = match capabilities & threads {
+            x if x == frame | slice => "frame and slice",
+            x if x == frame => "frame",
+            x if x == slice => "slice",
+            ffi::AV_CODEC_CAP_AUTO_THREADS => "auto",
+            _ => "none",
+        };
+        text.push_str(&format!("    Threading capabilities: {}\n", threading));
+    }
+
+    if !ffi::avcodec_get_hw_config(c, 0).is_null() {
+        text.push_str("    Supported hardware devices: ");
+        let mut i = 0;
+        while let Some(config) = ffi::avcodec_get_hw_config(c, i).as_ref() {
+            text.push_str(&string(ffi::av_hwdevice_get_type_name(config.device_type)));
+            text.push(' ');
+            i += 1;
+        }
+        text.push('\n');
+    }
+
+    if !c.supported_framerates.is_null() {
+        text.push_str("    Supported framerates:");
+        let mut fps = c.supported_framerates;
+        while (*fps).num != 0 {
+            text.push_str(&format!(" {}/{}", (*fps).num, (*fps).den));
+            fps = fps.add(1);
+        }
+        text.push('\n');
+    }
+    text.push_str(&supported(
+        "pixel formats",
+        c.pix_fmts,
+        ffi::AVPixelFormat_AV_PIX_FMT_NONE,
+        |x| string(ffi::av_get_pix_fmt_name(x)),
+    ));
+    text.push_str(&supported(
+        "sample rates",
+        c.supported_samplerates,
+        0,
+        |x| x.to_string(),
+    ));
+    text.push_str(&supported(
+        "sample formats",
+        c.sample_fmts,
+        ffi::AVSampleFormat_AV_SAMPLE_FMT_NONE,
+        |x| string(ffi::av_get_sample_fmt_name(x)),
+    ));
+    text.push_str(&supported("channel layouts", c.channel_layouts, 0, |x| {
+        let mut buffer = [0 as c_char; 128];
+        ffi::av_get_channel_layout_string(buffer.as_mut_ptr(), buffer.len() as _, 0, x);
+        string(buffer.as_ptr())
+    }));
+
+    if !c.priv_class.is_null() {
+        text.push_str(&class_help(
+            c.priv_class,
+            (ffi::AV_OPT_FLAG_ENCODING_PARAM | ffi::AV_OPT_FLAG_DECODING_PARAM) as i32,
+        ));
+    }
+    text
+}
+
+/// `show_help_codec()`: the coder with the name, or every coder of the codec
+/// with the name.
+unsafe fn codec_help_by_name(name: &CStr, encoder: bool) -> String {
+    let codec = if encoder {
+        ffi::avcodec_find_encoder_by_name(name.as_ptr())
+    } else {
+        ffi::avcodec_find_decoder_by_name(name.as_ptr())
+    };
+    if let Some(codec) = codec.as_ref() {
+        return codec_help(codec);
+    }
+    match ffi::avcodec_descriptor_get_by_name(name.as_ptr()).as_ref() {
+        Some(desc) => {
+            let text: String = codecs_for_id(desc.id, encoder)
+                .into_iter()
+                .map(|codec| codec_help(codec))
+                .collect();
+            if text.is_empty() {
+                error!(
+                    "Codec '{}' is known to FFmpeg, but no {} for it are available. \
+                     FFmpeg might need to be recompiled with additional external libraries.",
+                    name.to_string_lossy(),
+                    if encoder { "encoders" } else { "decoders" }
+                );
+            }
+            text
+        }
+        None => {
+            error!(
+                "Codec '{}' is not recognized by FFmpeg.",
+                name.to_string_lossy()
+            );
+            String::new()
+        }
+    }
+}
+
+/// `show_help_demuxer()`
+unsafe fn demuxer_help(name: &CStr) -> String {
+    let fmt = match ffi::av_find_input_format(name.as_ptr()).as_ref() {
+        Some(x) => x,
+        None => {
+            error!("Unknown format '{}'.", name.to_string_lossy());
+            return String::new();
+        }
+    };
+    let mut text = format!(
+        "Demuxer {} [{}]:\n",
+        string(fmt.name),
+        string(fmt.long_name)
+    );
+    if !fmt.extensions.is_null() {
+        text.push_str(&format!(
+            "    Common extensions: {}.\n",
+            string(fmt.extensions)
+        ));
+    }
+    if !fmt.priv_class.is_null() {
+        text.push_str(&class_help(
+            fmt.priv_class,
+            ffi::AV_OPT_FLAG_DECODING_PARAM as i32,
+        ));
+    }
+    text
+}
+
+/// `show_help_muxer()`
+unsafe fn muxer_help(name: &CStr) -> String {
+    let fmt = match ffi::av_guess_format(name.as_ptr(), ptr::null(), ptr::null()).as_ref() {
+        Some(x) => x,
+        None => {
+            error!("Unknown format '{}'.", name.to_string_lossy());
+            return String::new();
+        }
+    };
+    let mut text = format!("Muxer {} [{}]:\n", string(fmt.name), string(fmt.long_name));
+    if !fmt.extensions.is_null() {
+        text.push_str(&format!(
+            "    Common extensions: {}.\n",
+            string(fmt.extensions)
+        ));
+    }
+    if !fmt.mime_type.is_null() {
+        text.push_str(&format!("    Mime type: {}.\n", string(fmt.mime_type)));
+    }
+    for &(kind, id) in [
+        ("video", fmt.video_codec),
+        ("audio", fmt.audio_codec),
+        ("subtitle", fmt.subtitle_codec),
+    ]
+    .iter()
+    {
+        if id == ffi::AVCodecID_AV_CODEC_ID_NONE {
+            continue;
+        }
+        if let Some(desc) = ffi::avcodec_descriptor_get(id).as_ref() {
+            text.push_str(&format!(
+                "    Default {} codec: {}.\n",
+                kind,
+                string(desc.name)
+            ));
+        }
+    }
+    if !fmt.priv_class.is_null() {
+        text.push_str(&class_help(
+            fmt.priv_class,
+            ffi::AV_OPT_FLAG_ENCODING_PARAM as i32,
+        ));
+    }
+    text
+}
+
+/// `show_help_filter()`
+unsafe fn filter_help(name: &CStr) -> String {
+    let f = match ffi::avfilter_get_by_name(name.as_ptr()).as_ref() {
+        Some(x) => x,
+        None => {
+            error!("Unknown filter '{}'.", name.to_string_lossy());
+            return String::new();
+        }
+    };
+    let flags = f.flags as u32;
+    let mut text = format!("Filter {}\n", string(f.name));
+    if !f.description.is_null() {
+        text.push_str(&format!("  {}\n", string(f.description)));
+    }
+    if flags & ffi::AVFILTER_FLAG_SLICE_THREADS != 0 {
+        text.push_str("    slice threading supported\n");
+    }
+
+    let pads = |title: &str, pads: *const ffi::AVFilterPad, dynamic: u32, none: &str| {
+        let mut text = format!("    {}:\n", title);
+        let count = if pads.is_null() {
+            0
+        } else {
+            ffi::avfilter_pad_count(pads)
+        };
+        for i in 0..count {
+            let media_type = ffi::av_get_media_type_string(ffi::avfilter_pad_get_type(pads, i));
+            text.push_str(&format!(
+                "       #{}: {} ({})\n",
+                i,
+                string(ffi::avfilter_pad_get_name(pads, i)),
+                if media_type.is_null() {
+                    "unknown".into()
+                } else {
+                    string(media_type)
+                }
+            ));
+        }
+        if flags & dynamic != 0 {
+            text.push_str("        dynamic (depending on the options)\n");
+        } else if count == 0 {
+            text.push_str(&format!("        none ({})\n", none));
+        }
+        text
+    };
+    text.push_str(&pads(
+        "Inputs",
+        f.inputs,
+        ffi::AVFILTER_FLAG_DYNAMIC_INPUTS,
+        "source filter",
+    ));
+    text.push_str(&pads(
+        "Outputs",
+        f.outputs,
+        ffi::AVFILTER_FLAG_DYNAMIC_OUTPUTS,
+        "sink filter",
+    ));
+
+    if !f.priv_class.is_null() {
+        text.push_str(&class_help(
+            f.priv_class,
+            (ffi::AV_OPT_FLAG_VIDEO_PARAM
+                | ffi::AV_OPT_FLAG_FILTERING_PARAM
+                | ffi::AV_OPT_FLAG_AUDIO_PARAM) as i32,
+        ));
+    }
+    if flags & ffi::AVFILTER_FLAG_SUPPORT_TIMELINE != 0 {
+        text.push_str("This filter has support for timeline through the 'enable' option.\n");
+    }
+    text
+}
+
+/// `show_help_bsf()`
+unsafe fn bsf_help(name: &CStr) -> String {
+    let bsf = match ffi::av_bsf_get_by_name(name.as_ptr()).as_ref() {
+        Some(x) => x,
+        None => {
+            error!("Unknown bit stream filter '{}'.", name.to_string_lossy());
+            return String::new();
+        }
+    };
+    let mut text = format!("Bit stream filter {}\n", string(bsf.name));
+    text.push_str(&supported(
+        "codecs",
+        bsf.codec_ids,
+        ffi::AVCodecID_AV_CODEC_ID_NONE,
+        |x| string(ffi::avcodec_get_name(x)),
+    ));
+    if !bsf.priv_class.is_null() {
+        text.push_str(&class_help(
+            bsf.priv_class,
+            ffi::AV_OPT_FLAG_BSF_PARAM as i32,
+        ));
+    }
+    text
+}
+
+/// `show_help()`: `topic` is `type=name` for the options of the named
+/// decoder, encoder, demuxer, muxer, filter or bsf, anything else is given to
+/// the help of ffmpeg's own options.
+pub fn help(options: &[OptionDef], topic: &str) -> String {
+    let (topic, name) = match topic.find('=') {
+        Some(i) => (&topic[..i], Some(&topic[i + 1..])),
+        None => (topic, None),
+    };
+    let component = |help: &dyn Fn(&CStr) -> String| -> String {
+        match name.map(CString::new) {
+            Some(Ok(name)) if !name.as_bytes().is_empty() => help(&name),
+            _ => {
+                error!("No {} name specified.", topic);
+                String::new()
+            }
+        }
+    };
+    match topic {
+        "decoder" => component(&|name| unsafe { codec_help_by_name(name, false) }),
+        "encoder" => component(&|name| unsafe { codec_help_by_name(name, true) }),
+        "demuxer" => component(&|name| unsafe { demuxer_help(name) }),
+        "muxer" => component(&|name| unsafe { muxer_help(name) }),
+        "filter" => component(&|name| unsafe { filter_help(name) }),
+        "bsf" => component(&|name| unsafe { bsf_help(name) }),
+        _ => default_help(options, topic),
+    }
+}
+
+/// Entry of `ffgen list [--json] [<listing>...]`, which prints the listings,
+/// every one of them when none is given. With `--json`, they are printed as
+/// one JSON object keyed by the listing names.
+pub fn list(args: &[String]) -> Result<(), ()> {
+    let (json, names) = match args.split_first() {
+        Some((first, names)) if first == "--json" => (true, names),
+        _ => (false, args),
+    };
+    let listings = if names.is_empty() {
+        Listing::ALL.to_vec()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                Listing::ALL
+                    .iter()
+                    .copied()
+                    .find(|x| x.name() == name)
+                    .ok_or_else(|| {
+                        let names: Vec<_> = Listing::ALL.iter().map(|x| x.name()).collect();
+                        error!(
+                            "Unknown listing '{}', available ones are {}.",
+                            name,
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, ()>>()?
+    };
+
+    if json {
+        let fields: Vec<(&str, String)> = listings.iter().map(|x| (x.name(), x.json())).collect();
+        println!("{}", json_object(&fields));
+    } else {
+        for listing in listings {
+            println!("{}", listing.text());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::options::OPTIONS;
+
+    #[test]
+    fn text_lines() {
+        let format = Format {
+            name: "mov,mp4,m4a,3gp,3g2,mj2".into(),
+            long_name: "QuickTime / MOV".into(),
+            demuxing: true,
+            muxing: false,
+            device: false,
+        };
+        assert_eq!(format.line(), " D  mov,mp4,m4a,3gp,3g2,mj2 QuickTime / MOV");
+
+        let codec = Codec {
+            name: "h264".into(),
+            long_name: "H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10".into(),
+            media_type: 'V',
+            decoding: true,
+            encoding: true,
+            intra_only: false,
+            lossy: true,
+            lossless: true,
+            decoders: vec!["h264".into(), "h264_cuvid".into()],
+            encoders: vec!["libx264".into()],
+        };
+        assert_eq!(
+            codec.line(),
+            " DEV.LS h264                 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 \
+             (decoders: h264 h264_cuvid ) (encoders: libx264 )"
+        );
+
+        let filter = Filter {
+            name: "overlay".into(),
+            description: "Overlay a video source on top of the input.".into(),
+            timeline: true,
+            slice_threads: false,
+            commands: true,
+            inputs: vec!['V', 'V'],
+            outputs: vec!['V'],
+            dynamic_inputs: false,
+            dynamic_outputs: false,
+        };
+        assert_eq!(
+            filter.line(),
+            " T.C overlay           VV->V      Overlay a video source on top of the input."
+        );
+        let filter = Filter {
+            name: "amix".into(),
+            inputs: vec![],
+            outputs: vec!['A'],
+            dynamic_inputs: true,
+            ..filter
+        };
+        assert!(filter.line().contains(" amix              N->A       "));
+    }
+
+    #[test]
+    fn help_of_options() {
+        let short = help(&*OPTIONS, "");
+        assert!(short.contains(
+            "Print help / information / capabilities:\n\
+             -L                  show license\n\
+             -h topic            show help\n"
+        ));
+        assert!(short.contains("\nVideo options:\n"));
+        assert!(short.contains("\n-vframes number     set the number of video frames to output\n"));
+        // Expert options are only shown with `long` and `full`.
+        assert!(!short.contains("Advanced global options:"));
+        assert!(!short.contains("-benchmark "));
+        let long = help(&*OPTIONS, "long");
+        assert!(long.contains("Advanced global options:"));
+        assert!(long.contains("\n-benchmark          add timings for benchmarking\n"));
+    }
+
+    #[test]
+    fn values_of_avoptions() {
+        assert_eq!(option_value(i32::MAX as f64), "INT_MAX");
+        assert_eq!(option_value(-f32::MAX as f64), "-FLT_MAX");
+        assert_eq!(option_value(0.5), "0.5");
+        assert_eq!(option_value(1e-7), "1e-07");
+        assert_eq!(option_int_value(u32::MAX as i64), "UINT32_MAX");
+        assert_eq!(option_int_value(-1), "-1");
+    }
+
+    #[test]
+    fn json_output() {
+        assert_eq!(json_string("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+        assert_eq!(json_array(vec![].into_iter()), "[]");
+        assert_eq!(
+            json_array(vec!["1".to_string(), "2".to_string()].into_iter()),
+            "[\n  1,\n  2\n]"
+        );
+        let format = Format {
+            name: "wav".into(),
+            long_name: "WAV / WAVE (Waveform Audio)".into(),
+            demuxing: true,
+            muxing: true,
+            device: false,
+        };
+        assert_eq!(
+            format.json(),
+            r#"{"name": "wav", "long_name": "WAV / WAVE (Waveform Audio)", "demuxing": true, "muxing": true, "device": false}"#
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! `-target`: the codec, format, size, rate and bitrate options FFmpeg sets
+//! for VCD, SVCD, DVD and DV output in `opt_target()`.
+use log::info;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Norm {
+    Pal,
+    Ntsc,
+    /// NTSC at 24000/1001 frames per second.
+    Film,
+}
+
+impl Norm {
+    fn frame_rate(self) -> &'static str {
+        match self {
+            Norm::Pal => "25",
+            Norm::Ntsc => "30000/1001",
+            Norm::Film => "24000/1001",
+        }
+    }
+}
+
+/// One option set by a target.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    /// An option of the option table, given with `parse_option()`.
+    Parse(&'static str, &'static str),
+    /// A codec or format AVOption, given with `opt_default()`.
+    AVOption(&'static str, &'static str),
+}
+
+/// `av_parse_video_rate()`, for the rates the norm can be told from.
+fn parse_rate(rate: &str) -> Option<(i64, i64)> {
+    let abbreviation = match rate {
+        "ntsc" | "qntsc" | "sntsc" => Some((30000, 1001)),
+        "pal" | "qpal" | "spal" => Some((25, 1)),
+        "film" => Some((24, 1)),
+        "ntsc-film" => Some((24000, 1001)),
+        _ => None,
+    };
+    if abbreviation.is_some() {
+        return abbreviation;
+    }
+    let separator = rate.find(&['/', ':'][..]);
+    let (num, den) = match separator {
+        Some(n) => (rate[..n].parse().ok()?, rate[n + 1..].parse().ok()?),
+        None => {
+            let x: f64 = rate.parse().ok()?;
+            ((x * 1000.0).round() as i64, 1000)
+        }
+    };
+    if num <= 0 || den <= 0 {
+        return None;
+    }
+    Some((num, den))
+}
+
+/// The norm of a frame rate, told apart the way `opt_target()` does for the
+/// frame rates of the input streams.
+pub fn rate_norm(rate: &str) -> Option<Norm> {
+    let (num, den) = parse_rate(rate)?;
+    match num.checked_mul(1000)? / den {
+        25000 => Some(Norm::Pal),
+        29970 | 23976 => Some(Norm::Ntsc),
+        _ => None,
+    }
+}
+
+/// The options of `target`, like `pal-dvd` or `vcd`. Without a norm prefix,
+/// the norm is the one guessed from the input frame rates.
+pub fn expand(target: &str, guessed: Option<Norm>) -> Result<(Norm, Vec<Setting>), String> {
+    let (norm, name) = if let Some(name) = target.strip_prefix("pal-") {
+        (Some(Norm::Pal), name)
+    } else if let Some(name) = target.strip_prefix("ntsc-") {
+        (Some(Norm::Ntsc), name)
+    } else if let Some(name) = target.strip_prefix("film-") {
+        (Some(Norm::Film), name)
+    } else {
+        if let Some(norm) = guessed {
+            let norm = if norm == Norm::Pal { "PAL" } else { "NTSC" };
+            info!("Assuming {} for target.", norm);
+        }
+        (guessed, target)
+    };
+    let norm = norm.ok_or_else(|| {
+        String::from(
+            "Could not determine norm (PAL/NTSC/NTSC-Film) for target. \
+             Please prefix target with \"pal-\", \"ntsc-\" or \"film-\", \
+             or set a framerate with \"-r xxx\".",
+        )
+    })?;
+    let pal = norm == Norm::Pal;
+
+    let settings = if name == "vcd" {
+        vec![
+            Setting::Parse("c:v", "mpeg1video"),
+            Setting::Parse("c:a", "mp2"),
+            Setting::Parse("f", "vcd"),
+            Setting::Parse("s", if pal { "352x288" } else { "352x240" }),
+            Setting::Parse("r", norm.frame_rate()),
+            Setting::AVOption("g", if pal { "15" } else { "18" }),
+            Setting::AVOption("b:v", "1150000"),
+            Setting::AVOption("maxrate:v", "1150000"),
+            Setting::AVOption("minrate:v", "1150000"),
+            // 40*1024*8
+            Setting::AVOption("bufsize:v", "327680"),
+            Setting::AVOption("b:a", "224000"),
+            Setting::Parse("ar", "44100"),
+            Setting::Parse("ac", "2"),
+            Setting::AVOption("packetsize", "2324"),
+            // 2352 * 75 * 8
+            Setting::AVOption("muxrate", "1411200"),
+            // The SCR starts at 36000 and the data after three packs of
+            // 1200, so the PTS are offset by (36000 + 3 * 1200) / 90000.
+            Setting::Parse("muxpreload", "0.44"),
+        ]
+    } else if name == "svcd" {
+        vec![
+            Setting::Parse("c:v", "mpeg2video"),
+            Setting::Parse("c:a", "mp2"),
+            Setting::Parse("f", "svcd"),
+            Setting::Parse("s", if pal { "480x576" } else { "480x480" }),
+            Setting::Parse("r", norm.frame_rate()),
+            Setting::Parse("pix_fmt", "yuv420p"),
+            Setting::AVOption("g", if pal { "15" } else { "18" }),
+            Setting::AVOption("b:v", "2040000"),
+            Setting::AVOption("maxrate:v", "2516000"),
+            Setting::AVOption("minrate:v", "0"),
+            // 224*1024*8
+            Setting::AVOption("bufsize:v", "1835008"),
+            Setting::AVOption("scan_offset", "1"),
+            Setting::AVOption("b:a", "224000"),
+            Setting::Parse("ar", "44100"),
+            Setting::AVOption("packetsize", "2324"),
+        ]
+    } else if name == "dvd" {
+        vec![
+            Setting::Parse("c:v", "mpeg2video"),
+            Setting::Parse("c:a", "ac3"),
+            Setting::Parse("f", "dvd"),
+            Setting::Parse("s", if pal { "720x576" } else { "720x480" }),
+            Setting::Parse("r", norm.frame_rate()),
+            Setting::Parse("pix_fmt", "yuv420p"),
+            Setting::AVOption("g", if pal { "15" } else { "18" }),
+            Setting::AVOption("b:v", "6000000"),
+            Setting::AVOption("maxrate:v", "9000000"),
+            Setting::AVOption("minrate:v", "0"),
+            // 224*1024*8
+            Setting::AVOption("bufsize:v", "1835008"),
+            // A DVD sector, and pack, holds 2048 bytes of data.
+            Setting::AVOption("packetsize", "2048"),
+            Setting::AVOption("muxrate", "10080000"),
+            Setting::AVOption("b:a", "448000"),
+            Setting::Parse("ar", "48000"),
+        ]
+    } else if name.starts_with("dv") {
+        let pix_fmt = if name.starts_with("dv50") {
+            "yuv422p"
+        } else if pal {
+            "yuv420p"
+        } else {
+            "yuv411p"
+        };
+        vec![
+            Setting::Parse("f", "dv"),
+            Setting::Parse("s", if pal { "720x576" } else { "720x480" }),
+            Setting::Parse("pix_fmt", pix_fmt),
+            Setting::Parse("r", norm.frame_rate()),
+            Setting::Parse("ar", "48000"),
+            Setting::Parse("ac", "2"),
+        ]
+    } else {
+        return Err(format!("Unknown target: {}", name));
+    };
+    Ok((norm, settings))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn norms() {
+        assert_eq!(rate_norm("25"), Some(Norm::Pal));
+        assert_eq!(rate_norm("pal"), Some(Norm::Pal));
+        assert_eq!(rate_norm("30000/1001"), Some(Norm::Ntsc));
+        assert_eq!(rate_norm("29.97"), Some(Norm::Ntsc));
+        // Film is NTSC too when guessed.
+        assert_eq!(rate_norm("24000/1001"), Some(Norm::Ntsc));
+        assert_eq!(rate_norm("24"), None);
+        assert_eq!(rate_norm("25/0"), None);
+        assert_eq!(rate_norm("fast"), None);
+    }
+
+    #[test]
+    fn targets() {
+        let (norm, settings) = expand("film-dvd", None).unwrap();
+        assert_eq!(norm, Norm::Film);
+        assert!(settings.contains(&Setting::Parse("r", "24000/1001")));
+        assert!(settings.contains(&Setting::Parse("s", "720x480")));
+        assert!(settings.contains(&Setting::AVOption("g", "18")));
+
+        let (_, settings) = expand("dv50", Some(Norm::Pal)).unwrap();
+        assert!(settings.contains(&Setting::Parse("pix_fmt", "yuv422p")));
+        assert!(settings.contains(&Setting::Parse("s", "720x576")));
+
+        assert!(expand("vcd", None)
+            .unwrap_err()
+            .starts_with("Could not determine norm"));
+        assert_eq!(
+            expand("pal-laserdisc", None),
+            Err("Unknown target: laserdisc".into())
+        );
+    }
+}
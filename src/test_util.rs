@@ -0,0 +1,35 @@
+//! Helpers shared by the tests.
+
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A directory of a test under the temporary directory, removed with
+/// everything in it when dropped. The process id keeps concurrent runs apart.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("ffgen-{}-{}", process::id(), name));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // A failed test may have left it half written, which is fine.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}